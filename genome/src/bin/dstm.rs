@@ -2,14 +2,17 @@
 static GLOBAL: jemallocator::Jemalloc = jemallocator::Jemalloc;
 
 use clap::{App, Arg};
+use genome::fasta;
 use genome::gene::Gene;
-use genome::segments::Segments;
+use genome::quality::{self, Quality};
+use genome::segments::{ReadErrors, Segments};
 use genome::dstm_sequencer as sequencer;
-use genome::Nucleotide;
+use genome::Reconstruction;
 use rand_chacha::rand_core::SeedableRng;
 use rand_chacha::ChaCha12Rng;
 use std::fs::{create_dir_all, File};
 use std::io::Write;
+use std::process;
 use std::str::FromStr;
 use std::sync::Arc;
 use time::PreciseTime;
@@ -74,6 +77,60 @@ fn main() {
                 .takes_value(true)
                 .default_value("4"),
         )
+        .arg(
+            Arg::with_name("genefile")
+                .long("gene")
+                .help("Reads the gene from the given FASTA file instead of generating it. The first record is used.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("readsfile")
+                .long("reads")
+                .help("Reads the gene segments from the given FASTA or FASTQ file instead of sampling them from the gene. All reads must have the same length. Without `--gene`, the result can not be verified.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("dumpfasta")
+                .long("dump-fasta")
+                .help("Writes the reconstructed gene of the last run to the given FASTA file.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("substitutionrate")
+                .long("substitution-rate")
+                .help("Probability of a substitution error per nucleotide of a generated segment.")
+                .takes_value(true)
+                .default_value("0"),
+        )
+        .arg(
+            Arg::with_name("insertionrate")
+                .long("insertion-rate")
+                .help("Probability of an inserted nucleotide per nucleotide of a generated segment.")
+                .takes_value(true)
+                .default_value("0"),
+        )
+        .arg(
+            Arg::with_name("deletionrate")
+                .long("deletion-rate")
+                .help("Probability of a deleted nucleotide per nucleotide of a generated segment.")
+                .takes_value(true)
+                .default_value("0"),
+        )
+        .arg(
+            Arg::with_name("revcomp")
+                .long("reverse-complement")
+                .help("Probability of a generated segment being read from the opposite strand (reverse complement).")
+                .takes_value(true)
+                .default_value("0"),
+        )
+        .arg(
+            Arg::with_name("mismatches")
+                .long("mismatches")
+                .short("k")
+                .help("The number of mismatching nucleotides tolerated in the overlap of two segments. Overlaps of at most twice this length have to match exactly.")
+                .takes_value(true)
+                .default_value("0"),
+        )
         .get_matches();
 
     // parse benchmark parameters
@@ -83,6 +140,24 @@ fn main() {
         .expect("provided invalid value for `min-number`");
     let segment_length = usize::from_str(matches.value_of("seglength").unwrap())
         .expect("provided invalid value for `segment-length`");
+    let rate = |name: &str, arg: &str| {
+        f64::from_str(matches.value_of(name).unwrap())
+            .ok()
+            .filter(|r| (0.0..=1.0).contains(r))
+            .unwrap_or_else(|| panic!("provided invalid value for `{}`", arg))
+    };
+    let read_errors = ReadErrors {
+        substitution: rate("substitutionrate", "substitution-rate"),
+        insertion: rate("insertionrate", "insertion-rate"),
+        deletion: rate("deletionrate", "deletion-rate"),
+        reverse_complement: rate("revcomp", "reverse-complement"),
+    };
+    if read_errors.insertion + read_errors.deletion >= 1.0 {
+        eprintln!("[ERROR] The insertion and deletion rate must add up to less than 1.");
+        process::exit(1);
+    }
+    let max_mismatches = usize::from_str(matches.value_of("mismatches").unwrap())
+        .expect("provided invalid value for `mismatches`");
 
     // parse runtime parameters
     let runs =
@@ -92,24 +167,79 @@ fn main() {
     let threads = usize::from_str(matches.value_of("threads").unwrap())
         .expect("provided invalid value for `threads`");
 
-    // generate the gene and its segments
+    // generate or load the gene and its segments
     let mut rng = ChaCha12Rng::seed_from_u64(0);
 
-    let mut gene = Gene::create(gene_length, &mut rng);
-    let segments = Segments::create(segment_length, min_number, &mut gene, &mut rng);
+    let gene_file = matches.value_of("genefile");
+    let reads_file = matches.value_of("readsfile");
+    let mut gene = match gene_file {
+        Some(file) => Some(fasta::read_gene(file).unwrap_or_else(|e| {
+            eprintln!("[ERROR] Failed to read {}: {}", file, e);
+            process::exit(1);
+        })),
+        None if reads_file.is_none() => Some(Gene::create(gene_length, &mut rng)),
+        None => None,
+    };
+    if let (Some(gene), None) = (&gene, reads_file) {
+        if segment_length < 2 || segment_length > gene.length {
+            eprintln!(
+                "[ERROR] The segment length must be between 2 and the length of the gene ({}), got {}",
+                gene.length, segment_length
+            );
+            process::exit(1);
+        }
+    }
+    let segments = match reads_file {
+        Some(file) => {
+            let length_hint = gene.as_ref().map_or(gene_length, |g| g.length);
+            fasta::read_segments(file, length_hint).unwrap_or_else(|e| {
+                eprintln!("[ERROR] Failed to read {}: {}", file, e);
+                process::exit(1);
+            })
+        }
+        None => Segments::create_with_errors(
+            segment_length,
+            min_number,
+            gene.as_mut().unwrap(),
+            read_errors,
+            &mut rng,
+        ),
+    };
+
+    // sequencing errors change the length of the reconstruction
+    let exact_segments = reads_file.is_some() || read_errors.is_exact();
+
+    // loaded inputs determine the parameters
+    let gene_length = segments.orig_gene_length;
+    let segment_length = segments.length;
+    let min_number = segments.minimal_segmentcount;
+
     if !json_dump {
-        println!(
-            "[INFO] Generated {} gene segments.",
-            segments.contents.len()
-        );
-        // println!(
-        //     "[DEBUG] Gene (still) has {} Nucleotides.",
-        //     gene.contents.len()
-        // );
+        if let Some(file) = gene_file {
+            println!("[INFO] Loaded a gene of {} nucleotides from {}.", gene_length, file);
+        }
+        if let Some(file) = reads_file {
+            println!(
+                "[INFO] Loaded {} gene segments from {}.",
+                segments.contents.len(),
+                file
+            );
+        } else {
+            println!(
+                "[INFO] Generated {} gene segments.",
+                segments.contents.len()
+            );
+        }
+        if gene.is_none() {
+            println!("[INFO] No gene given, the sequenced genome will not be verified.");
+        } else if !exact_segments {
+            println!("[INFO] The segments contain errors, the sequenced genome will not be verified.");
+        }
     }
 
     let mut results = Vec::with_capacity(runs);
     let mut cpu_results = Vec::with_capacity(runs);
+    let mut qualities = Vec::with_capacity(runs);
 
     for r in 0..runs {
         // prepare the data for the run
@@ -120,7 +250,7 @@ fn main() {
         let cpu_start = ProcessTime::now();
 
         // run the algorithm
-        let result = run_benchmark(input_data, threads);
+        let result = run_benchmark(input_data, threads, max_mismatches);
 
         // stop the clock
         let cpu_end = ProcessTime::now();
@@ -132,11 +262,31 @@ fn main() {
             println!("[INFO] Genome sequencing run {} completed.", r + 1);
         }
 
-        if result.len() != gene.contents.len() {
-            eprintln!("[ERROR] Output verification failed. An error occured during genome sequencing. Sequenced genome length deviated from the original genome size ({}/{})", result.len(), gene.contents.len());
-        } else {
-            results.push(runtime_ms);
-            cpu_results.push(cpu_runtime_ms);
+        let quality = Quality::evaluate(&result, gene.as_ref().map(|g| g.contents.as_slice()));
+        if !json_dump {
+            println!(
+                "[INFO] Reconstructed {} nucleotides in {} contig(s).",
+                quality.length, quality.contigs
+            );
+        }
+        qualities.push(quality);
+
+        match &gene {
+            Some(gene) if exact_segments && result.sequence.len() != gene.contents.len() => {
+                eprintln!("[ERROR] Output verification failed. An error occured during genome sequencing. Sequenced genome length deviated from the original genome size ({}/{})", result.sequence.len(), gene.contents.len());
+            }
+            _ => {
+                results.push(runtime_ms);
+                cpu_results.push(cpu_runtime_ms);
+            }
+        }
+
+        if r + 1 == runs {
+            if let Some(file) = matches.value_of("dumpfasta") {
+                if let Err(e) = fasta::write_fasta(file, "reconstructed", &result.sequence) {
+                    eprintln!("[ERROR] Could not write the sequenced genome: {}", e);
+                }
+            }
         }
     }
 
@@ -151,37 +301,86 @@ fn main() {
         f.write_fmt(format_args!(
             "{{
     \"algorithm\": \"rust-dstm\",
+    \"gene\": \"{gene}\",
+    \"reads\": \"{reads}\",
     \"gene_length\": {gene_len},
     \"min_segment_count\": {min_segment},
     \"segment_length\": {seg_len},
+    \"substitution_rate\": {substitution},
+    \"insertion_rate\": {insertion},
+    \"deletion_rate\": {deletion},
+    \"reverse_complement_rate\": {reverse_complement},
+    \"max_mismatches\": {max_mismatches},
     \"threadcount\": {threads},
     \"runs\": {runs},
     \"cpu_time\": {cpu:?},
-    \"results\": {res:?}
+    \"results\": {res:?},
+    \"reconstruction_length\": {rec_len:?},
+    \"contigs\": {contigs:?},
+    \"identity\": {identity},
+    \"mismatches\": {mismatches}
 }}",
+            gene = gene_file.unwrap_or("generated"),
+            reads = reads_file.unwrap_or("generated"),
             gene_len = gene_length,
             min_segment = min_number,
             seg_len = segment_length,
+            substitution = read_errors.substitution,
+            insertion = read_errors.insertion,
+            deletion = read_errors.deletion,
+            reverse_complement = read_errors.reverse_complement,
+            max_mismatches = max_mismatches,
             threads = threads,
             runs = runs,
             cpu = cpu_results,
-            res = results
+            res = results,
+            rec_len = qualities.iter().map(|q| q.length).collect::<Vec<_>>(),
+            contigs = qualities.iter().map(|q| q.contigs).collect::<Vec<_>>(),
+            identity = quality::json_array(qualities.iter().map(|q| q.identity)),
+            mismatches = quality::json_array(qualities.iter().map(|q| q.mismatches))
         ))
         .unwrap();
     } else {
         println!("[INFO] All runs completed successfully.");
         println!("\nStatistics:");
-        println!("    Length of the generated gene: {}", gene_length);
+        if let Some(file) = gene_file {
+            println!("    Gene file:                    {}", file);
+        }
+        if let Some(file) = reads_file {
+            println!("    Segments file:                {}", file);
+        }
+        println!("    Length of the gene:           {}", gene_length);
         println!("    Minimal number of segments:   {}", min_number);
         println!("    Length of a gene segment:     {}", segment_length);
+        println!("    Substitution rate:            {}", read_errors.substitution);
+        println!("    Insertion rate:               {}", read_errors.insertion);
+        println!("    Deletion rate:                {}", read_errors.deletion);
+        println!("    Reverse complement rate:      {}", read_errors.reverse_complement);
+        println!("    Mismatches per overlap:       {}", max_mismatches);
         println!("    Threads:                      {}", threads);
         println!("    Runs:                         {}", runs);
         println!("\nCPU-time used (ms): {:?}", cpu_results);
         println!("Runtime in ms: {:?}", results);
+        println!(
+            "\nReconstructed length: {:?}",
+            qualities.iter().map(|q| q.length).collect::<Vec<_>>()
+        );
+        println!(
+            "Contigs: {:?}",
+            qualities.iter().map(|q| q.contigs).collect::<Vec<_>>()
+        );
+        println!(
+            "Identity with the gene: {}",
+            quality::json_array(qualities.iter().map(|q| q.identity))
+        );
+        println!(
+            "Mismatched positions: {}",
+            quality::json_array(qualities.iter().map(|q| q.mismatches))
+        );
     }
 }
 
-fn run_benchmark(segments: Segments, threadcount: usize) -> Vec<Nucleotide> {
+fn run_benchmark(segments: Segments, threadcount: usize, max_mismatches: usize) -> Reconstruction {
     // Phase 1
     let segment_length = segments.length;
 
@@ -205,7 +404,7 @@ fn run_benchmark(segments: Segments, threadcount: usize) -> Vec<Nucleotide> {
         ranges.push(lower..upper);
     }
 
-    sequencer::run_sequencer(deduplicated.clone(), segment_length, ranges);
+    sequencer::run_sequencer(deduplicated.clone(), segment_length, ranges, max_mismatches);
 
     // Phase 3
     sequencer::reconstruct(deduplicated.clone())
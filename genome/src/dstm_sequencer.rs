@@ -1,5 +1,6 @@
+use crate::index::SegmentIndex;
 use crate::segments::Segments;
-use crate::Nucleotide;
+use crate::{count_contigs, Nucleotide, Reconstruction};
 use std::collections::VecDeque;
 use std::ops::Range;
 use std::sync::mpsc::{Receiver, Sender};
@@ -175,6 +176,7 @@ pub fn run_sequencer(
     unique_segments: Arc<VecDeque<SequencerItem>>,
     segment_length: usize,
     iteration_ranges: Vec<Range<usize>>,
+    max_mismatches: usize,
 ) {
     let threadcount = iteration_ranges.len();
     let mut handles = Vec::with_capacity(threadcount);
@@ -182,6 +184,13 @@ pub fn run_sequencer(
     let lower = iteration_ranges.last().unwrap().len();
     let upper = iteration_ranges.first().unwrap().len();
 
+    // the index is only read by the threads
+    let index = Arc::new(SegmentIndex::new(
+        unique_segments.iter().map(|item| item.segment.as_slice()),
+        segment_length,
+        max_mismatches,
+    ));
+
     // TODO: construct the 2 vecs containing the senders/receivers, use them in the threads and
     // adjust the atomically call
     let (coord_in, thread_rx): (Vec<Sender<DTMHandle>>, Vec<Receiver<DTMHandle>>) =
@@ -198,6 +207,7 @@ pub fn run_sequencer(
     {
         //let iteration_range = rng.clone();
         let segments = unique_segments.clone();
+        let index = index.clone();
         handles.push(thread::spawn(move || {
             // Step 2: go through the prefixes and suffixes of the genomes in descending size and stitch the genome back together
             for match_length in (1..segment_length).rev() {
@@ -220,9 +230,9 @@ pub fn run_sequencer(
                         if cur_links.prev.is_none() {
                             let slice = &cur_seg.segment[0..match_length];
 
-                            // go over all items in Vec and test whether we can append our
-                            // `cur_seg` to the item. If so, stop
-                            'inner: for it in 0..segments.len() {
+                            // go over the items whose suffix may match and test whether we can
+                            // append our `cur_seg` to the item. If so, stop
+                            'inner: for &it in index.candidates(idx, match_length).iter() {
                                 // skip the element itself -- this might be unnecessary but we want to avoid
                                 // breaking the matching algorithm by linking an element to itself
                                 if idx == it {
@@ -242,7 +252,7 @@ pub fn run_sequencer(
 
                                 let cur_slice = &segments[it].segment
                                     [(segment_length - match_length)..segment_length];
-                                if slice == cur_slice {
+                                if index.is_match(slice, cur_slice) {
                                     // link both items together
                                     //segments[it].next.write(trans, Some(idx))?;
                                     segments[it].links.modify(trans, |mut l| {
@@ -267,7 +277,7 @@ pub fn run_sequencer(
     handles.into_iter().for_each(|h| h.join().unwrap());
 }
 
-pub fn reconstruct(unique_segments: Arc<VecDeque<SequencerItem>>) -> Vec<Nucleotide> {
+pub fn reconstruct(unique_segments: Arc<VecDeque<SequencerItem>>) -> Reconstruction {
     if cfg!(feature = "verify") {
         // TMP test
         println!("[TEST] checking segment links");
@@ -290,27 +300,37 @@ pub fn reconstruct(unique_segments: Arc<VecDeque<SequencerItem>>) -> Vec<Nucleot
 
     // Step 3 link together sequence
     atomically(|trans| {
-        // find first element
-        let mut cur = unique_segments
+        let mut next = Vec::with_capacity(unique_segments.len());
+        for item in unique_segments.iter() {
+            next.push(item.links.read(trans)?.next);
+        }
+        let contigs = count_contigs(&next);
+
+        // find first element, if all segments have been linked into cycles, start anywhere
+        let first = unique_segments
             .iter()
-            .find(|seg| seg.links.read_atomic().prev.is_none())
-            .unwrap();
+            .position(|seg| seg.links.read_atomic().prev.is_none())
+            .unwrap_or(0);
+        let mut cur = &unique_segments[first];
 
-        let mut reconstructed_sequence = Vec::new();
+        let mut reconstructed_sequence = cur.segment.to_vec();
 
         loop {
-            let link_info = cur.links.read(trans)?;
-
-            reconstructed_sequence.extend_from_slice(&cur.segment[link_info.overlap_with_prev..]);
-
-            if let Some(next_idx) = link_info.next {
-                // move to the next value -> have to assign to another let binding first to drop `val` due to ownership issues
-                cur = &unique_segments[next_idx];
-            } else {
-                break;
+            match cur.links.read(trans)?.next {
+                // stop at the end of the chain or after going around a cycle
+                Some(next_idx) if next_idx != first => {
+                    cur = &unique_segments[next_idx];
+                    let link_info = cur.links.read(trans)?;
+                    reconstructed_sequence
+                        .extend_from_slice(&cur.segment[link_info.overlap_with_prev..]);
+                }
+                _ => break,
             }
         }
 
-        Ok(reconstructed_sequence)
+        Ok(Reconstruction {
+            sequence: reconstructed_sequence,
+            contigs,
+        })
     })
 }
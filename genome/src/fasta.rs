@@ -0,0 +1,241 @@
+//! Reading and writing of nucleotide sequences in the FASTA and FASTQ formats.
+//!
+//! Only the four nucleotides are supported, ambiguity codes like `N` are rejected. The format of
+//! an input file is detected from its first character: `>` starts a FASTA and `@` a FASTQ file.
+
+use crate::gene::Gene;
+use crate::segments::Segments;
+use crate::Nucleotide;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+/// Number of nucleotides per line in written FASTA files
+const LINE_WIDTH: usize = 60;
+
+/// A named nucleotide sequence.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Record {
+    pub name: String,
+    pub sequence: Vec<Nucleotide>,
+}
+
+fn invalid_data(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Parses a sequence line of the record `name` and appends it to `sequence`.
+fn parse_sequence(line: &str, name: &str, sequence: &mut Vec<Nucleotide>) -> io::Result<()> {
+    for code in line.trim_end().bytes() {
+        match Nucleotide::from_ascii(code) {
+            Some(n) => sequence.push(n),
+            None => {
+                return Err(invalid_data(format!(
+                    "invalid nucleotide '{}' at position {} of record {}",
+                    code as char,
+                    sequence.len() + 1,
+                    name
+                )))
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The name of a record is the first word of its header line.
+fn record_name(header: &str) -> String {
+    header[1..]
+        .split_whitespace()
+        .next()
+        .unwrap_or("")
+        .to_owned()
+}
+
+/// Reads all records of a FASTA or FASTQ file. Sequences in FASTA files may span several lines,
+/// FASTQ records must consist of exactly four lines.
+pub fn read_records<R: BufRead>(reader: R) -> io::Result<Vec<Record>> {
+    let mut lines = reader
+        .lines()
+        .filter(|l| !matches!(l, Ok(l) if l.trim().is_empty()));
+    let mut records = Vec::new();
+
+    let first = match lines.next() {
+        Some(line) => line?,
+        None => return Ok(records),
+    };
+
+    if first.starts_with('>') {
+        let mut current = Record {
+            name: record_name(&first),
+            sequence: Vec::new(),
+        };
+        for line in lines {
+            let line = line?;
+            if line.starts_with('>') {
+                let next = Record {
+                    name: record_name(&line),
+                    sequence: Vec::new(),
+                };
+                records.push(std::mem::replace(&mut current, next));
+            } else if !line.starts_with(';') {
+                parse_sequence(&line, &current.name, &mut current.sequence)?;
+            }
+        }
+        records.push(current);
+    } else if first.starts_with('@') {
+        let mut header = Some(first);
+        while let Some(h) = header {
+            if !h.starts_with('@') {
+                return Err(invalid_data(format!(
+                    "expected a FASTQ header, found: {}",
+                    h
+                )));
+            }
+            let name = record_name(&h);
+            let mut next_line = || {
+                lines.next().unwrap_or_else(|| {
+                    Err(invalid_data(format!("truncated FASTQ record {}", name)))
+                })
+            };
+
+            let mut sequence = Vec::new();
+            parse_sequence(&next_line()?, &name, &mut sequence)?;
+            if !next_line()?.starts_with('+') {
+                return Err(invalid_data(format!(
+                    "missing separator in FASTQ record {}",
+                    name
+                )));
+            }
+            if next_line()?.trim_end().len() != sequence.len() {
+                return Err(invalid_data(format!(
+                    "quality and sequence length differ in FASTQ record {}",
+                    name
+                )));
+            }
+            records.push(Record { name, sequence });
+
+            header = lines.next().transpose()?;
+        }
+    } else {
+        return Err(invalid_data("neither a FASTA nor a FASTQ file".to_owned()));
+    }
+
+    Ok(records)
+}
+
+fn read_file<P: AsRef<Path>>(path: P) -> io::Result<Vec<Record>> {
+    read_records(BufReader::new(File::open(path)?))
+}
+
+/// Reads a gene from the first record of the FASTA file at `path`.
+pub fn read_gene<P: AsRef<Path>>(path: P) -> io::Result<Gene> {
+    match read_file(path)?.into_iter().next() {
+        Some(record) if !record.sequence.is_empty() => Ok(Gene::from_contents(record.sequence)),
+        _ => Err(invalid_data("the file contains no sequence".to_owned())),
+    }
+}
+
+/// Reads pre-cut gene segments (reads) from the FASTA or FASTQ file at `path`. The sequencers
+/// require all segments to have the same length.
+///
+/// `orig_gene_length` is the length of the gene the reads have been taken from, if it is
+/// unknown it only serves as size hint.
+pub fn read_segments<P: AsRef<Path>>(path: P, orig_gene_length: usize) -> io::Result<Segments> {
+    let contents: Vec<Vec<Nucleotide>> = read_file(path)?.into_iter().map(|r| r.sequence).collect();
+
+    let length = match contents.first() {
+        Some(first) if first.len() > 1 => first.len(),
+        Some(_) => return Err(invalid_data("segments must be longer than 1".to_owned())),
+        None => return Err(invalid_data("the file contains no segments".to_owned())),
+    };
+    if let Some(idx) = contents.iter().position(|s| s.len() != length) {
+        return Err(invalid_data(format!(
+            "segment {} has length {}, expected {}",
+            idx + 1,
+            contents[idx].len(),
+            length
+        )));
+    }
+
+    Ok(Segments {
+        length,
+        orig_gene_length,
+        minimal_segmentcount: contents.len(),
+        contents,
+    })
+}
+
+/// Writes the `sequence` as a single FASTA record with the given `name`.
+pub fn write_sequence<W: Write>(mut out: W, name: &str, sequence: &[Nucleotide]) -> io::Result<()> {
+    writeln!(out, ">{} length={}", name, sequence.len())?;
+    for line in sequence.chunks(LINE_WIDTH) {
+        let codes: Vec<u8> = line.iter().map(|n| n.to_ascii()).collect();
+        out.write_all(&codes)?;
+        out.write_all(b"\n")?;
+    }
+    out.flush()
+}
+
+/// Writes the `sequence` as FASTA file to `path`.
+pub fn write_fasta<P: AsRef<Path>>(path: P, name: &str, sequence: &[Nucleotide]) -> io::Result<()> {
+    write_sequence(BufWriter::new(File::create(path)?), name, sequence)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Nucleotide::*;
+
+    #[test]
+    fn read_fasta() {
+        let input = b">gene1 some description\nACgt\nTA\n; comment\n\n>gene2\nC\n";
+        let records = read_records(&input[..]).unwrap();
+        assert_eq!(
+            records,
+            vec![
+                Record {
+                    name: "gene1".into(),
+                    sequence: vec![Adenine, Cytosine, Guanine, Thyamine, Thyamine, Adenine],
+                },
+                Record {
+                    name: "gene2".into(),
+                    sequence: vec![Cytosine],
+                },
+            ]
+        );
+
+        assert!(read_records(&b">gene\nACNT\n"[..]).is_err());
+        assert!(read_records(&b"ACGT\n"[..]).is_err());
+        assert!(read_records(&b""[..]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn read_fastq() {
+        let input = b"@read1\nACG\n+\nIII\n@read2\nTTA\n+read2\n#I#\n";
+        let records = read_records(&input[..]).unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[1].name, "read2");
+        assert_eq!(records[1].sequence, vec![Thyamine, Thyamine, Adenine]);
+
+        // wrong quality length, missing separator and truncated record
+        assert!(read_records(&b"@r\nACG\n+\nII\n"[..]).is_err());
+        assert!(read_records(&b"@r\nACG\nIII\n"[..]).is_err());
+        assert!(read_records(&b"@r\nACG\n+\n"[..]).is_err());
+    }
+
+    #[test]
+    fn write_and_read_back() {
+        let sequence: Vec<Nucleotide> = (0..150)
+            .map(|i| [Adenine, Cytosine, Guanine, Thyamine][i * 7 % 4])
+            .collect();
+        let mut out = Vec::new();
+        write_sequence(&mut out, "result", &sequence).unwrap();
+
+        let text = String::from_utf8(out.clone()).unwrap();
+        assert!(text.starts_with(">result length=150\n"));
+        assert_eq!(text.lines().map(str::len).max(), Some(LINE_WIDTH));
+
+        let records = read_records(&out[..]).unwrap();
+        assert_eq!(records[0].sequence, sequence);
+    }
+}
@@ -19,9 +19,15 @@ impl Gene {
             cont.push(rng.gen());
         }
 
+        Gene::from_contents(cont)
+    }
+
+    /// Create a gene from a known nucleotide sequence
+    pub fn from_contents(contents: Vec<Nucleotide>) -> Self {
+        let length = contents.len();
         Gene {
             length,
-            contents: cont,
+            contents,
             bitmap: Bitmap::new(length),
         }
     }
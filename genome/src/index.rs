@@ -0,0 +1,197 @@
+//! Hash index for matching the prefixes of segments against the suffixes of other segments.
+//!
+//! Instead of comparing every segment with all others, the sequencers look up the segments whose
+//! suffix has the same Rabin-Karp hash as the prefix of the current segment. The hashes are
+//! computed from the rolling prefix hashes of every segment, the index for every overlap length is
+//! built once and only read afterwards, so the threads can share it.
+//!
+//! Overlaps longer than `2 * max_mismatches` may contain up to `max_mismatches` differing
+//! nucleotides. To find them, an overlap is split into `max_mismatches + 1` blocks, at least one of
+//! which has to agree exactly. Shorter overlaps would match almost any segment, so they have to
+//! agree completely.
+
+use crate::Nucleotide;
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+/// Base of the polynomial hash, arithmetic is modulo 2^64
+const BASE: u64 = 0x100_0000_01b3;
+
+/// Segments by the hash of one block of their suffix.
+#[derive(Debug, Default)]
+struct BlockIndex {
+    /// Hash of the block of the prefix of every segment
+    prefixes: Vec<u64>,
+    /// Segments with a suffix block of the given hash, in ascending order
+    suffixes: HashMap<u64, Vec<usize>>,
+}
+
+impl BlockIndex {
+    fn lookup(&self, segment: usize) -> &[usize] {
+        self.suffixes
+            .get(&self.prefixes[segment])
+            .map_or(&[], Vec::as_slice)
+    }
+}
+
+#[derive(Debug)]
+pub struct SegmentIndex {
+    max_mismatches: usize,
+    /// The blocks for overlap `k` are at position `k - 1`. Overlaps that have to match exactly
+    /// consist of a single block.
+    overlaps: Vec<Vec<BlockIndex>>,
+}
+
+fn code(n: Nucleotide) -> u64 {
+    n as u64 + 1
+}
+
+/// Splits an overlap of length `overlap` into `count` blocks of almost equal length.
+fn block_bounds(overlap: usize, count: usize, block: usize) -> (usize, usize) {
+    (overlap * block / count, overlap * (block + 1) / count)
+}
+
+/// Number of mismatches tolerated in an overlap of length `overlap`.
+fn tolerated_mismatches(overlap: usize, max_mismatches: usize) -> usize {
+    if overlap > 2 * max_mismatches {
+        max_mismatches
+    } else {
+        0
+    }
+}
+
+impl SegmentIndex {
+    /// Indexes the `segments` for all overlap lengths from `1` to `segment_length - 1`, allowing
+    /// up to `max_mismatches` differing nucleotides in overlaps longer than `2 * max_mismatches`.
+    pub fn new<'a, I>(segments: I, segment_length: usize, max_mismatches: usize) -> Self
+    where
+        I: IntoIterator<Item = &'a [Nucleotide]>,
+    {
+        let mut overlaps: Vec<Vec<BlockIndex>> = (1..segment_length)
+            .map(|overlap| {
+                let block_count = tolerated_mismatches(overlap, max_mismatches) + 1;
+                (0..block_count).map(|_| BlockIndex::default()).collect()
+            })
+            .collect();
+
+        let mut powers = vec![1u64; segment_length + 1];
+        for i in 1..powers.len() {
+            powers[i] = powers[i - 1].wrapping_mul(BASE);
+        }
+
+        let mut hashes = vec![0u64; segment_length + 1];
+        for (id, segment) in segments.into_iter().enumerate() {
+            // `hashes[i]` is the hash of the first `i` nucleotides
+            for (i, &n) in segment.iter().enumerate() {
+                hashes[i + 1] = hashes[i].wrapping_mul(BASE).wrapping_add(code(n));
+            }
+            let substring = |from: usize, to: usize| {
+                hashes[to].wrapping_sub(hashes[from].wrapping_mul(powers[to - from]))
+            };
+
+            for (overlap, blocks) in (1..segment_length).zip(overlaps.iter_mut()) {
+                let suffix_start = segment_length - overlap;
+                let block_count = blocks.len();
+                for (block, index) in blocks.iter_mut().enumerate() {
+                    let (from, to) = block_bounds(overlap, block_count, block);
+                    index.prefixes.push(substring(from, to));
+                    index
+                        .suffixes
+                        .entry(substring(suffix_start + from, suffix_start + to))
+                        .or_default()
+                        .push(id);
+                }
+            }
+        }
+
+        SegmentIndex {
+            max_mismatches,
+            overlaps,
+        }
+    }
+
+    /// The segments whose suffix of length `overlap` may match the prefix of `segment`, in
+    /// ascending order. The list can contain `segment` itself and segments that do not match
+    /// (because of hash collisions or too many mismatches), so the candidates still have to be
+    /// checked with `is_match`.
+    pub fn candidates(&self, segment: usize, overlap: usize) -> Cow<'_, [usize]> {
+        match self.overlaps[overlap - 1].as_slice() {
+            [block] => Cow::Borrowed(block.lookup(segment)),
+            blocks => {
+                let mut candidates: Vec<usize> = blocks
+                    .iter()
+                    .flat_map(|b| b.lookup(segment))
+                    .copied()
+                    .collect();
+                candidates.sort_unstable();
+                candidates.dedup();
+                Cow::Owned(candidates)
+            }
+        }
+    }
+
+    /// Checks whether a prefix and a suffix of the same length differ in at most as many
+    /// nucleotides as tolerated for their length.
+    pub fn is_match(&self, prefix: &[Nucleotide], suffix: &[Nucleotide]) -> bool {
+        let max_mismatches = tolerated_mismatches(prefix.len(), self.max_mismatches);
+        if max_mismatches == 0 {
+            return prefix == suffix;
+        }
+
+        let mut mismatches = 0;
+        for (a, b) in prefix.iter().zip(suffix) {
+            if a != b {
+                mismatches += 1;
+                if mismatches > max_mismatches {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+    use rand_chacha::rand_core::SeedableRng;
+    use rand_chacha::ChaCha12Rng;
+
+    #[test]
+    fn finds_all_matches() {
+        let mut rng = ChaCha12Rng::seed_from_u64(7);
+        let length = 6;
+        let segments: Vec<Vec<Nucleotide>> = (0..200)
+            .map(|_| (0..length).map(|_| rng.gen()).collect())
+            .collect();
+
+        for &max_mismatches in &[0, 1, 2] {
+            let index =
+                SegmentIndex::new(segments.iter().map(Vec::as_slice), length, max_mismatches);
+            let is_match = |cur: &Vec<Nucleotide>, it: usize, overlap: usize| {
+                let suffix = &segments[it][(length - overlap)..];
+                let mismatches = suffix.iter().zip(cur).filter(|(a, b)| a != b).count();
+                // short overlaps have to match exactly
+                mismatches <= max_mismatches && (mismatches == 0 || overlap > 2 * max_mismatches)
+            };
+
+            for overlap in 1..length {
+                for (cur, segment) in segments.iter().enumerate() {
+                    let expected: Vec<usize> = (0..segments.len())
+                        .filter(|&it| is_match(segment, it, overlap))
+                        .collect();
+                    let found: Vec<usize> = index
+                        .candidates(cur, overlap)
+                        .iter()
+                        .copied()
+                        .filter(|&it| {
+                            index.is_match(&segment[..overlap], &segments[it][(length - overlap)..])
+                        })
+                        .collect();
+                    assert_eq!(found, expected);
+                }
+            }
+        }
+    }
+}
@@ -2,7 +2,10 @@ use rand::distributions::{Distribution, Standard};
 use rand::Rng;
 
 mod bitmap;
+pub mod fasta;
 pub mod gene;
+pub mod index;
+pub mod quality;
 pub mod segments;
 
 pub mod sequencer;
@@ -21,6 +24,79 @@ pub enum Nucleotide {
     Thyamine,
 }
 
+/// The result of a sequencer run.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Reconstruction {
+    /// The sequence assembled from the first chain of linked segments
+    pub sequence: Vec<Nucleotide>,
+    /// Number of chains the segments have been linked into, 1 for a complete reconstruction
+    pub contigs: usize,
+}
+
+/// Counts the chains the segments have been linked into, given the index of the successor of
+/// every segment. Segments that have been linked into a cycle form a chain, too.
+pub fn count_contigs(next: &[Option<usize>]) -> usize {
+    let mut has_prev = vec![false; next.len()];
+    for &n in next.iter().flatten() {
+        has_prev[n] = true;
+    }
+
+    // walk the chains with a start first, the remaining segments are part of cycles
+    let starts: Vec<usize> = (0..next.len()).filter(|&i| !has_prev[i]).collect();
+    let mut visited = vec![false; next.len()];
+    let mut contigs = 0;
+    for start in starts.into_iter().chain(0..next.len()) {
+        if visited[start] {
+            continue;
+        }
+        contigs += 1;
+
+        let mut cur = Some(start);
+        while let Some(idx) = cur {
+            if visited[idx] {
+                break;
+            }
+            visited[idx] = true;
+            cur = next[idx];
+        }
+    }
+
+    contigs
+}
+
+impl Nucleotide {
+    /// Parses a nucleotide from its one-letter code, ignoring the case.
+    pub fn from_ascii(code: u8) -> Option<Self> {
+        match code.to_ascii_uppercase() {
+            b'A' => Some(Nucleotide::Adenine),
+            b'C' => Some(Nucleotide::Cytosine),
+            b'G' => Some(Nucleotide::Guanine),
+            b'T' => Some(Nucleotide::Thyamine),
+            _ => None,
+        }
+    }
+
+    /// The nucleotide on the opposite strand.
+    pub fn complement(self) -> Self {
+        match self {
+            Nucleotide::Adenine => Nucleotide::Thyamine,
+            Nucleotide::Cytosine => Nucleotide::Guanine,
+            Nucleotide::Guanine => Nucleotide::Cytosine,
+            Nucleotide::Thyamine => Nucleotide::Adenine,
+        }
+    }
+
+    /// The one-letter code of the nucleotide.
+    pub fn to_ascii(self) -> u8 {
+        match self {
+            Nucleotide::Adenine => b'A',
+            Nucleotide::Cytosine => b'C',
+            Nucleotide::Guanine => b'G',
+            Nucleotide::Thyamine => b'T',
+        }
+    }
+}
+
 impl Distribution<Nucleotide> for Standard {
     fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Nucleotide {
         match rng.gen_range(0, 4) {
@@ -32,3 +108,17 @@ impl Distribution<Nucleotide> for Standard {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn contigs() {
+        assert_eq!(count_contigs(&[]), 0);
+        assert_eq!(count_contigs(&[Some(2), None, Some(1)]), 1);
+        // a chain 3 -> 0 and a cycle 1 -> 2 -> 1
+        assert_eq!(count_contigs(&[None, Some(2), Some(1), Some(0)]), 2);
+        assert_eq!(count_contigs(&[None, None, None]), 3);
+    }
+}
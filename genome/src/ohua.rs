@@ -1,12 +1,16 @@
 #![allow(non_snake_case, unused_mut)]
 use crate::ohua_sequencer::*;
 use crate::segments::Segments;
-use crate::Nucleotide;
+use crate::Reconstruction;
 use std::sync::Arc;
 
-pub fn sequencer(segments: Segments, starting_overlap: usize) -> Vec<Nucleotide> {
+pub fn sequencer(
+    segments: Segments,
+    starting_overlap: usize,
+    max_mismatches: usize,
+) -> Reconstruction {
     // phase 1: deduplication. Can't really do that in parallel.
-    let unique_segments: SequencerData = deduplicate(segments);
+    let unique_segments: SequencerData = deduplicate(segments, max_mismatches);
 
     let matched: SequencerData = match_segments(unique_segments, starting_overlap);
 
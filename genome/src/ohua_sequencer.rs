@@ -1,10 +1,13 @@
+use crate::index::SegmentIndex;
 use crate::segments::Segments;
-use crate::Nucleotide;
+use crate::{count_contigs, Nucleotide, Reconstruction};
 use itertools::Itertools;
 use std::sync::Arc;
 
 pub struct SequencerData {
     pub data: Vec<SequencerItem>,
+    /// Prefix/suffix index of the segments in `data`, only read by the matching
+    pub index: SegmentIndex,
 }
 
 impl SequencerData {
@@ -47,15 +50,20 @@ impl From<Vec<Nucleotide>> for SequencerItem {
     }
 }
 
-pub fn deduplicate(segments: Segments) -> SequencerData {
-    let data = segments
+pub fn deduplicate(segments: Segments, max_mismatches: usize) -> SequencerData {
+    let data: Vec<SequencerItem> = segments
         .contents
         .into_iter()
         .unique() // itertools magic for deduplication: fast because of the enums representation in memory as u8
         .map(SequencerItem::from)
         .collect();
+    let index = SegmentIndex::new(
+        data.iter().map(|item| item.segment.as_slice()),
+        segments.length,
+        max_mismatches,
+    );
 
-    SequencerData { data }
+    SequencerData { data, index }
 }
 
 /// Searches a segment match for a single sequencer item with a given overlap.
@@ -70,8 +78,12 @@ pub fn search_match(
     if current.prev.is_none() {
         let slice = &current.segment[0..overlap];
 
-        // go over all items in Vec and test whether we can append our `current` to the item. If so, stop
-        for idx in 0..segments.data.len() {
+        // go over the items whose suffix may match and test whether we can append our `current`
+        // to the item. If so, stop
+        for &idx in segments.index.candidates(elem, overlap).iter() {
+            if idx == elem {
+                continue;
+            }
             let item = &segments.data[idx];
 
             // skip the current item when it already has an appended segment
@@ -80,7 +92,7 @@ pub fn search_match(
             }
 
             let cur_slice = &item.segment[(segments_length - overlap)..segments_length];
-            if slice == cur_slice {
+            if segments.index.is_match(slice, cur_slice) {
                 return Some((idx, elem));
             }
         }
@@ -92,8 +104,9 @@ pub fn search_match(
 pub fn reassemble(
     SequencerData {
         data: unique_segments,
+        ..
     }: SequencerData,
-) -> Vec<Nucleotide> {
+) -> Reconstruction {
     if cfg!(feature = "verify") {
         println!("[TEST] checking segment links");
         let mut forward_links = 0;
@@ -110,28 +123,29 @@ pub fn reassemble(
         assert_eq!(backward_links, 1);
     }
 
+    let next: Vec<Option<usize>> = unique_segments.iter().map(|seg| seg.next).collect();
+    let contigs = count_contigs(&next);
+
     // Step 3 link together sequence
-    // find first element
+    // find first element, if all segments have been linked into cycles, start anywhere
     let first = unique_segments
         .iter()
-        .find(|seg| seg.prev.is_none())
-        .unwrap();
-    let mut nxt = first.next.unwrap();
-    let mut reconstructed_sequence = first.segment.clone();
-
-    loop {
-        let cur = &unique_segments[nxt];
+        .position(|seg| seg.prev.is_none())
+        .unwrap_or(0);
+    let mut reconstructed_sequence = unique_segments[first].segment.clone();
+    let mut nxt = unique_segments[first].next;
+
+    // stop at the end of the chain or after going around a cycle
+    while let Some(n) = nxt.filter(|&n| n != first) {
+        let cur = &unique_segments[n];
         reconstructed_sequence.extend_from_slice(&cur.segment[cur.overlap_with_prev..]);
-
-        if let Some(n) = cur.next {
-            // move to the next value -> have to assign to another let binding first to drop `val` due to ownership issues
-            nxt = n;
-        } else {
-            break;
-        }
+        nxt = cur.next;
     }
 
-    reconstructed_sequence
+    Reconstruction {
+        sequence: reconstructed_sequence,
+        contigs,
+    }
 }
 
 pub fn get_overlap(cur: usize) -> (usize, usize) {
@@ -0,0 +1,91 @@
+//! Comparison of the reconstructed sequence with the original gene.
+
+use crate::{Nucleotide, Reconstruction};
+use std::fmt::Display;
+
+/// How well a sequencer run reconstructed the gene.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Quality {
+    /// Length of the reconstructed sequence
+    pub length: usize,
+    /// Number of chains the segments have been linked into
+    pub contigs: usize,
+    /// Share of the positions that agree with the gene, relative to the longer of both sequences.
+    /// Only known if the gene is.
+    pub identity: Option<f64>,
+    /// Positions that differ from the gene, including the ones missing in or added to the
+    /// reconstruction. Only known if the gene is.
+    pub mismatches: Option<usize>,
+}
+
+impl Quality {
+    /// Compares the `reconstruction` position by position with the `gene`, if there is one.
+    pub fn evaluate(reconstruction: &Reconstruction, gene: Option<&[Nucleotide]>) -> Self {
+        let sequence = &reconstruction.sequence;
+        let (identity, mismatches) = match gene {
+            Some(gene) => {
+                let matching = sequence.iter().zip(gene).filter(|(a, b)| a == b).count();
+                let longer = sequence.len().max(gene.len());
+                let identity = match longer {
+                    0 => 1.0,
+                    _ => matching as f64 / longer as f64,
+                };
+                (Some(identity), Some(longer - matching))
+            }
+            None => (None, None),
+        };
+
+        Quality {
+            length: sequence.len(),
+            contigs: reconstruction.contigs,
+            identity,
+            mismatches,
+        }
+    }
+}
+
+/// Formats optional values as JSON array, unknown values become `null`.
+pub fn json_array<T: Display, I: IntoIterator<Item = Option<T>>>(values: I) -> String {
+    let entries: Vec<String> = values
+        .into_iter()
+        .map(|v| match v {
+            Some(v) => v.to_string(),
+            None => "null".to_owned(),
+        })
+        .collect();
+    format!("[{}]", entries.join(", "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Nucleotide::*;
+
+    #[test]
+    fn compare_with_gene() {
+        let gene = vec![Adenine, Cytosine, Guanine, Thyamine];
+        let reconstruction = Reconstruction {
+            sequence: vec![Adenine, Guanine, Guanine],
+            contigs: 2,
+        };
+
+        let quality = Quality::evaluate(&reconstruction, Some(&gene));
+        assert_eq!(quality.length, 3);
+        assert_eq!(quality.contigs, 2);
+        assert_eq!(quality.identity, Some(0.5));
+        assert_eq!(quality.mismatches, Some(2));
+
+        let exact = Reconstruction {
+            sequence: gene.clone(),
+            contigs: 1,
+        };
+        assert_eq!(Quality::evaluate(&exact, Some(&gene)).mismatches, Some(0));
+
+        let unknown = Quality::evaluate(&reconstruction, None);
+        assert_eq!((unknown.identity, unknown.mismatches), (None, None));
+        assert_eq!(
+            json_array(vec![Some(1.0), None, Some(0.5)]),
+            "[1, null, 0.5]"
+        );
+    }
+}
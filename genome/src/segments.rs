@@ -11,6 +11,78 @@ pub struct Segments {
     pub contents: Vec<Vec<Nucleotide>>,
 }
 
+/// Sequencing errors and orientation of generated segments. All rates are probabilities between
+/// 0 and 1, the insertion and deletion rate must add up to less than 1.
+///
+/// Segments keep their length: after an insertion the last nucleotide is cut off, after a
+/// deletion the segment is extended by the next nucleotide of the gene.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ReadErrors {
+    /// Per nucleotide: it is replaced by a different one
+    pub substitution: f64,
+    /// Per nucleotide: a random nucleotide is inserted before it
+    pub insertion: f64,
+    /// Per nucleotide: it is left out
+    pub deletion: f64,
+    /// Per segment: the reverse complement is emitted instead
+    pub reverse_complement: f64,
+}
+
+impl ReadErrors {
+    /// Segments are exact copies of the gene.
+    pub fn is_exact(&self) -> bool {
+        *self == ReadErrors::default()
+    }
+}
+
+/// Reads `length` nucleotides of the gene from position `start` with sequencing errors.
+fn read_with_errors(
+    gene: &[Nucleotide],
+    start: usize,
+    length: usize,
+    errors: &ReadErrors,
+    rng: &mut ChaCha12Rng,
+) -> Vec<Nucleotide> {
+    let mut read = Vec::with_capacity(length);
+    let mut pos = start;
+
+    while read.len() < length {
+        // past the end of the gene, a deletion pulls in a random nucleotide
+        let nucleotide = gene.get(pos).copied().unwrap_or_else(|| rng.gen());
+        pos += 1;
+
+        let indel: f64 = rng.gen();
+        if indel < errors.deletion {
+            continue;
+        }
+        if indel < errors.deletion + errors.insertion {
+            read.push(rng.gen());
+            if read.len() == length {
+                break;
+            }
+        }
+
+        if rng.gen::<f64>() < errors.substitution {
+            let others = [
+                Nucleotide::Adenine,
+                Nucleotide::Cytosine,
+                Nucleotide::Guanine,
+                Nucleotide::Thyamine,
+            ];
+            read.push(others[(nucleotide as usize + rng.gen_range(1, 4)) % 4]);
+        } else {
+            read.push(nucleotide);
+        }
+    }
+
+    if rng.gen::<f64>() < errors.reverse_complement {
+        read.reverse();
+        read.iter_mut().for_each(|n| *n = n.complement());
+    }
+
+    read
+}
+
 impl Segments {
     pub fn create(
         length: usize,
@@ -18,7 +90,23 @@ impl Segments {
         gene: &mut Gene,
         rng: &mut ChaCha12Rng,
     ) -> Self {
-        let mut cont = Vec::with_capacity(minimal_count);
+        Segments::create_with_errors(length, minimal_count, gene, ReadErrors::default(), rng)
+    }
+
+    /// Samples segments like `create`, but reads them with the given sequencing `errors`. The
+    /// segments start at the same positions as without errors.
+    pub fn create_with_errors(
+        length: usize,
+        minimal_count: usize,
+        gene: &mut Gene,
+        errors: ReadErrors,
+        rng: &mut ChaCha12Rng,
+    ) -> Self {
+        assert!(
+            errors.insertion + errors.deletion < 1.0,
+            "insertion and deletion rate must add up to less than 1"
+        );
+        let mut starts = Vec::with_capacity(minimal_count);
 
         let start_number = gene.length - length + 1;
 
@@ -26,13 +114,12 @@ impl Segments {
         for _ in 0..minimal_count {
             let j = rng.gen_range(0, start_number);
             gene.bitmap.set_bit(j);
-            let slice = &gene.contents[j..(j + length)];
-            cont.push(slice.to_owned());
+            starts.push(j);
         }
 
         // make sure the start is covered, one way or the other
         if !gene.bitmap.get_bit(0) {
-            cont.push(gene.contents[0..length].to_owned());
+            starts.push(0);
             gene.bitmap.set_bit(0);
         }
 
@@ -49,12 +136,24 @@ impl Segments {
             if idx == upper_bound {
                 // hole is big enough, fill it
                 idx -= 1;
-                cont.push(gene.contents[idx..(idx + length)].to_owned());
+                starts.push(idx);
                 gene.bitmap.set_bit(idx);
             }
             idx += 1;
         }
 
+        // read the segments, errors are drawn after the positions to keep them independent
+        let cont = starts
+            .into_iter()
+            .map(|j| {
+                if errors.is_exact() {
+                    gene.contents[j..(j + length)].to_owned()
+                } else {
+                    read_with_errors(&gene.contents, j, length, &errors, rng)
+                }
+            })
+            .collect();
+
         // done. Return the finished segments part
         Segments {
             length,
@@ -64,3 +163,45 @@ impl Segments {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand_chacha::rand_core::SeedableRng;
+
+    fn segments(errors: ReadErrors) -> Segments {
+        let mut rng = ChaCha12Rng::seed_from_u64(0);
+        let mut gene = Gene::create(256, &mut rng);
+        Segments::create_with_errors(16, 64, &mut gene, errors, &mut rng)
+    }
+
+    #[test]
+    fn reads_with_errors() {
+        let exact = segments(ReadErrors::default());
+        let reversed = segments(ReadErrors {
+            reverse_complement: 1.0,
+            ..ReadErrors::default()
+        });
+        for (e, r) in exact.contents.iter().zip(&reversed.contents) {
+            let complement: Vec<Nucleotide> = r.iter().rev().map(|n| n.complement()).collect();
+            assert_eq!(e, &complement);
+        }
+
+        for &errors in &[
+            ReadErrors {
+                substitution: 0.1,
+                ..ReadErrors::default()
+            },
+            ReadErrors {
+                insertion: 0.1,
+                deletion: 0.1,
+                ..ReadErrors::default()
+            },
+        ] {
+            let erroneous = segments(errors);
+            assert_eq!(erroneous.contents.len(), exact.contents.len());
+            assert!(erroneous.contents.iter().all(|s| s.len() == 16));
+            assert_ne!(erroneous.contents, exact.contents);
+        }
+    }
+}
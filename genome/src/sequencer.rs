@@ -1,8 +1,9 @@
+use crate::index::SegmentIndex;
 use crate::segments::Segments;
-use crate::Nucleotide;
+use crate::{count_contigs, Nucleotide, Reconstruction};
 use itertools::Itertools;
 use std::cell::RefCell;
-use std::collections::VecDeque;
+use std::collections::HashMap;
 use std::rc::Rc;
 
 #[derive(Debug, Eq, PartialEq)]
@@ -30,17 +31,25 @@ impl From<Vec<Nucleotide>> for SequencerItem {
 
 // Is this really a port of the STAMP version or a re-implementation of your own?
 
-pub fn run_sequencer(segments: Segments) -> Vec<Nucleotide> {
+/// Overlaps between segments may contain up to `max_mismatches` differing nucleotides.
+pub fn run_sequencer(segments: Segments, max_mismatches: usize) -> Reconstruction {
     // Step 1: deduplicate all segments
-    let mut unique_segments: VecDeque<Rc<RefCell<SequencerItem>>> = segments
+    let deduplicated: Vec<Vec<Nucleotide>> = segments
         .contents
         .into_iter()
         .unique() // itertools magic for deduplication: fast because of the enums representation in memory as u8
+        .collect();
+    let index = SegmentIndex::new(
+        deduplicated.iter().map(Vec::as_slice),
+        segments.length,
+        max_mismatches,
+    );
+    let unique_segments: Vec<Rc<RefCell<SequencerItem>>> = deduplicated
+        .into_iter()
         .map(SequencerItem::from)
         .map(RefCell::new)
         .map(Rc::new)
         .collect();
-    let segment_count = unique_segments.len();
 
     // Step 2: go through the prefixes and suffixes of the genomes in descending size and stitch the genome back together
     for match_length in (1..segments.length).rev() {
@@ -54,19 +63,26 @@ pub fn run_sequencer(segments: Segments) -> Vec<Nucleotide> {
          * -> Should be parallelizable for STM and Ohua.
          */
 
-        for _ in 0..segment_count {
-            let cs = unique_segments
-                .pop_front()
-                .expect("No segments were generated");
+        for (pos, cs) in unique_segments.iter().enumerate() {
             let mut cur_seg = cs.borrow_mut();
 
             // only continue if the current segment is not linked already
             if cur_seg.prev.is_none() {
                 let slice = &cur_seg.segment[0..match_length];
 
-                // go over all items in Vec and test whether we can append our `cur_seg` to the item. If so, stop
-                'inner: for it in unique_segments.iter() {
-                    let mut item = it.borrow_mut();
+                // go over the segments whose suffix may match and test whether we can append our
+                // `cur_seg` to the item. The ones after the current segment are tried first, as
+                // if the segments were kept in a rotating queue. If one matches, stop
+                let candidates = index.candidates(pos, match_length);
+                let split = candidates
+                    .iter()
+                    .position(|&it| it > pos)
+                    .unwrap_or(candidates.len());
+                'inner: for &it in candidates[split..].iter().chain(&candidates[..split]) {
+                    if it == pos {
+                        continue;
+                    }
+                    let mut item = unique_segments[it].borrow_mut();
                     // skip the current item when it already has an appended segment
                     if item.next.is_some() {
                         continue;
@@ -74,18 +90,15 @@ pub fn run_sequencer(segments: Segments) -> Vec<Nucleotide> {
 
                     let cur_slice =
                         &item.segment[(segments.length - match_length)..segments.length];
-                    if slice == cur_slice {
+                    if index.is_match(slice, cur_slice) {
                         // link both items together
                         item.next = Some(cs.clone());
-                        cur_seg.prev = Some(it.clone());
+                        cur_seg.prev = Some(unique_segments[it].clone());
                         cur_seg.overlap_with_prev = match_length;
                         break 'inner;
                     }
                 }
             }
-
-            std::mem::drop(cur_seg);
-            unique_segments.push_back(cs);
         }
     }
 
@@ -107,28 +120,117 @@ pub fn run_sequencer(segments: Segments) -> Vec<Nucleotide> {
         assert_eq!(backward_links, 1);
     }
 
+    let indices: HashMap<*const RefCell<SequencerItem>, usize> = unique_segments
+        .iter()
+        .enumerate()
+        .map(|(idx, seg)| (Rc::as_ptr(seg), idx))
+        .collect();
+    let next: Vec<Option<usize>> = unique_segments
+        .iter()
+        .map(|seg| seg.borrow().next.as_ref().map(|n| indices[&Rc::as_ptr(n)]))
+        .collect();
+    let contigs = count_contigs(&next);
+
     // Step 3 link together sequence
-    // find first element
-    let mut cur = unique_segments
+    // find first element, if all segments have been linked into cycles, start anywhere
+    let first = unique_segments
         .iter()
         .find(|seg| seg.borrow().prev.is_none())
-        .unwrap()
+        .unwrap_or(&unique_segments[0])
         .clone();
-    let mut reconstructed_sequence = Vec::new();
+    let mut cur = first.clone();
+    let mut reconstructed_sequence = first.borrow().segment.clone();
 
     loop {
         let val = cur.borrow();
-        reconstructed_sequence.extend_from_slice(&val.segment[val.overlap_with_prev..]);
-
-        if val.next.is_some() {
-            // move to the next value -> have to assign to another let binding first to drop `val` due to ownership issues
-            let next = val.next.as_ref().unwrap().clone();
-            std::mem::drop(val);
-            cur = next;
-        } else {
-            break;
+        // move to the next value -> have to assign to another let binding first to drop `val` due to ownership issues
+        let next = val.next.clone();
+        std::mem::drop(val);
+
+        match next {
+            // stop at the end of the chain or after going around a cycle
+            Some(next) if !Rc::ptr_eq(&next, &first) => {
+                let val = next.borrow();
+                reconstructed_sequence.extend_from_slice(&val.segment[val.overlap_with_prev..]);
+                std::mem::drop(val);
+                cur = next;
+            }
+            _ => break,
+        }
+    }
+
+    Reconstruction {
+        sequence: reconstructed_sequence,
+        contigs,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+    use rand_chacha::rand_core::SeedableRng;
+    use rand_chacha::ChaCha12Rng;
+
+    /// Reads of length 32 every 8 nucleotides of a gene of length 200, every third read with a
+    /// substitution in the middle, and three unrelated reads. The sequences are chosen so that the
+    /// ends of the three contigs cannot overlap by chance:
+    ///
+    /// - the gene is `GCC`, followed by random `A`s and `C`s and a final `T`
+    /// - a periodic read `GTGT...`, which overlaps with itself
+    /// - its extension by 8 `T`s
+    /// - a read of `G`s ending with two `A`s
+    fn reads_with_substitutions() -> Segments {
+        use Nucleotide::*;
+
+        let mut rng = ChaCha12Rng::seed_from_u64(3);
+        let mut gene = vec![Guanine, Cytosine, Cytosine];
+        gene.extend((0..196).map(|_| if rng.gen() { Adenine } else { Cytosine }));
+        gene.push(Thyamine);
+
+        let mut contents: Vec<Vec<Nucleotide>> = (0..=168)
+            .step_by(8)
+            .map(|start| gene[start..(start + 32)].to_vec())
+            .collect();
+        for read in contents.iter_mut().step_by(3) {
+            read[16] = read[16].complement();
+        }
+
+        let periodic: Vec<Nucleotide> = (0..32).map(|i| [Guanine, Thyamine][i % 2]).collect();
+        let mut extension = periodic[8..].to_vec();
+        extension.extend(vec![Thyamine; 8]);
+        let mut unrelated = vec![Guanine; 30];
+        unrelated.extend(vec![Adenine; 2]);
+        contents.insert(1, extension);
+        contents.insert(2, periodic);
+        contents.push(unrelated);
+
+        Segments {
+            length: 32,
+            orig_gene_length: 200,
+            minimal_segmentcount: contents.len(),
+            contents,
         }
     }
 
-    reconstructed_sequence
+    #[test]
+    fn links_reads_with_substitutions() {
+        // without tolerating mismatches, the gene breaks apart at the substitutions
+        for exact in &[
+            run_sequencer(reads_with_substitutions(), 0),
+            crate::ohua::sequencer(reads_with_substitutions(), 31, 0),
+        ] {
+            assert_eq!(exact.contigs, 4);
+            assert_eq!(exact.sequence.len(), 56);
+        }
+
+        for result in &[
+            run_sequencer(reads_with_substitutions(), 1),
+            crate::ohua::sequencer(reads_with_substitutions(), 31, 1),
+        ] {
+            // the unrelated reads must neither be glued to the gene nor linked to themselves
+            assert_eq!(result.contigs, 3);
+            assert_eq!(result.sequence.len(), 200);
+        }
+    }
 }
@@ -0,0 +1,92 @@
+//! Evaluation of the alerts of a run against the flows that actually contain an attack.
+
+use crate::detector::Alert;
+use std::collections::{BTreeMap, HashSet};
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Accuracy {
+    /// Attacked flows with an alert
+    pub true_positives: usize,
+    /// Alerts for flows without an attack
+    pub false_positives: usize,
+    /// Attacked flows without an alert, including evicted flows
+    pub false_negatives: usize,
+    /// Number of alerts per rule id
+    pub hits: BTreeMap<u32, usize>,
+}
+
+impl Accuracy {
+    /// Compares the `alerts` of a run with the ground truth, the ids of the attacked flows.
+    /// Every flow is counted once, even if it has been reported several times.
+    pub fn evaluate(alerts: &[Alert], attacks: &HashSet<usize>) -> Self {
+        let mut hits = BTreeMap::new();
+        for alert in alerts {
+            *hits.entry(alert.rule_id).or_insert(0) += 1;
+        }
+
+        let found: HashSet<usize> = alerts.iter().map(|a| a.flow_id).collect();
+        let true_positives = found.intersection(attacks).count();
+
+        Accuracy {
+            true_positives,
+            false_positives: found.len() - true_positives,
+            false_negatives: attacks.len() - true_positives,
+            hits,
+        }
+    }
+
+    /// The share of the alerts that are correct, 1 without alerts.
+    pub fn precision(&self) -> f64 {
+        match self.true_positives + self.false_positives {
+            0 => 1.0,
+            alerts => self.true_positives as f64 / alerts as f64,
+        }
+    }
+
+    /// The share of the attacks that have been found, 1 without attacks.
+    pub fn recall(&self) -> f64 {
+        match self.true_positives + self.false_negatives {
+            0 => 1.0,
+            attacks => self.true_positives as f64 / attacks as f64,
+        }
+    }
+
+    /// The hits as JSON object, mapping the rule ids to the number of alerts.
+    pub fn hits_json(&self) -> String {
+        let entries: Vec<String> = self
+            .hits
+            .iter()
+            .map(|(id, count)| format!("\"{}\": {}", id, count))
+            .collect();
+        format!("{{{}}}", entries.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn alert(flow_id: usize, rule_id: u32) -> Alert {
+        Alert { flow_id, rule_id }
+    }
+
+    #[test]
+    fn evaluate_alerts() {
+        let attacks: HashSet<usize> = vec![1, 2, 3, 4].into_iter().collect();
+        let accuracy = Accuracy::evaluate(
+            &[alert(1, 7), alert(2, 7), alert(5, 3), alert(2, 7)],
+            &attacks,
+        );
+
+        assert_eq!(accuracy.true_positives, 2);
+        assert_eq!(accuracy.false_positives, 1);
+        assert_eq!(accuracy.false_negatives, 2);
+        assert_eq!(accuracy.precision(), 2.0 / 3.0);
+        assert_eq!(accuracy.recall(), 0.5);
+        assert_eq!(accuracy.hits_json(), "{\"3\": 1, \"7\": 3}");
+
+        let empty = Accuracy::evaluate(&[], &HashSet::new());
+        assert_eq!(empty.hits_json(), "{}");
+        assert_eq!((empty.precision(), empty.recall()), (1.0, 1.0));
+    }
+}
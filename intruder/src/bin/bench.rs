@@ -1,22 +1,31 @@
 use clap::{App, Arg};
 use cpu_time::ProcessTime;
 use std::str::FromStr;
+use std::collections::HashSet;
 use std::fs::{create_dir_all, File};
 use std::io::Write;
+use std::process;
+use std::sync::Arc;
 use time::PreciseTime;
 use strum_macros::EnumString;
 
 use intruder::seq;
 use intruder::stm;
 use intruder::dstm;
+use intruder::sharded;
 use intruder::ohua;
 use intruder::generated;
+use intruder::accuracy::Accuracy;
+use intruder::decoder::{DecoderStats, EvictionPolicy, ReassemblyPolicy};
+use intruder::detector::{Detector, Engine, RuleSet};
+use intruder::stream::{self, LatencyStats};
 
 #[derive(EnumString)]
 enum Runtime {
     Seq,
     STM,
     DSTM,
+    Sharded,
     OhuaSeq,
     Ohua
 }
@@ -92,10 +101,90 @@ fn main() {
             Arg::with_name("runtime")
             .long("runtime")
             .short("rt")
-            .help("The runtime to be executed: Seq | STM | DSTM | OhuaSeq | Ohua")
+            .help("The runtime to be executed: Seq | STM | DSTM | Sharded | OhuaSeq | Ohua")
             .takes_value(true)
             .default_value("Seq")
         )
+        .arg(
+            Arg::with_name("engine")
+            .long("engine")
+            .help("The signature matching engine: scan | aho-corasick")
+            .takes_value(true)
+            .default_value("aho-corasick")
+        )
+        .arg(
+            Arg::with_name("obfuscation")
+            .long("obfuscate")
+            .help("Percentage of the generated attacks to hide with escape sequences (URL, HTML and backslash encoding).")
+            .takes_value(true)
+            .default_value("0")
+        )
+        .arg(
+            Arg::with_name("binary")
+            .long("binary")
+            .help("Generates flows of arbitrary bytes instead of printable ASCII text.")
+        )
+        .arg(
+            Arg::with_name("nonormalize")
+            .long("no-normalize")
+            .help("Matches the signatures against the raw flows, without decoding escape sequences first.")
+        )
+        .arg(
+            Arg::with_name("rules")
+            .long("rules")
+            .help("Loads the signatures from the given rules file instead of using the built-in word list. Each line holds a rule: <id> [nocase] \"<content>\"")
+            .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("pcap")
+            .long("pcap")
+            .help("Reads the packet stream from the given pcap file instead of generating it. The flows are reassembled from IPv4 fragments, TCP segments and UDP datagrams.")
+            .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("dumppcap")
+            .long("dump-pcap")
+            .help("Writes the packet stream to the given pcap file before running the benchmark.")
+            .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("stream")
+            .long("stream")
+            .help("Feeds the packets to the analysis through a channel, like they would arrive from the network, and measures the latency per packet. Supported by the Seq and STM runtimes.")
+        )
+        .arg(
+            Arg::with_name("rate")
+            .long("rate")
+            .help("The number of packets per second fed in streaming mode, 0 feeds them as fast as they are consumed.")
+            .takes_value(true)
+            .default_value("0")
+        )
+        .arg(
+            Arg::with_name("timeout")
+            .long("timeout")
+            .help("Evicts incomplete flows that have not received a fragment for the given number of packets.")
+            .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("maxbytes")
+            .long("max-bytes")
+            .help("Limits the bytes buffered for incomplete flows, evicting the least recently used flows.")
+            .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("anomalies")
+            .long("anomalies")
+            .help("Percentage of the flows to which a duplicated, overlapping or malformed fragment is added.")
+            .takes_value(true)
+            .default_value("0")
+        )
+        .arg(
+            Arg::with_name("reassembly")
+            .long("reassembly")
+            .help("Which data the decoders keep for fragments received twice with different data: first-wins | last-wins")
+            .takes_value(true)
+            .default_value("first-wins")
+        )
        .get_matches();
 
     // parse benchmark parameters
@@ -103,10 +192,14 @@ fn main() {
         .expect("provided invalid input for `attacks`");
     let max_packet_len = u64::from_str(matches.value_of("max_length").unwrap())
         .expect("provided invalid input for `length`");
-    let flowcount = usize::from_str(matches.value_of("flowcount").unwrap())
+    let mut flowcount = usize::from_str(matches.value_of("flowcount").unwrap())
         .expect("provided invalid input for `number_flows`");
     let rng_seed = u64::from_str(matches.value_of("seed").unwrap())
         .expect("provided invalid input for `seed`");
+    let obfuscation_percentage = u8::from_str(matches.value_of("obfuscation").unwrap())
+        .expect("provided invalid input for `obfuscate`");
+    let anomaly_percentage = u8::from_str(matches.value_of("anomalies").unwrap())
+        .expect("provided invalid input for `anomalies`");
 
     // parse runtime parameters
     let runs =
@@ -117,37 +210,186 @@ fn main() {
         .expect("Could not parse number of threads");
     let rt = Runtime::from_str(matches.value_of("runtime").unwrap())
         .expect("Could not parse runtime");
+    let engine = Engine::from_str(matches.value_of("engine").unwrap())
+        .expect("Could not parse the signature matching engine");
+    let normalize = !matches.is_present("nonormalize");
+    let binary = matches.is_present("binary");
+    let reassembly = ReassemblyPolicy::from_str(matches.value_of("reassembly").unwrap())
+        .expect("Could not parse the reassembly policy");
+    let streaming = matches.is_present("stream");
+    let rate = u64::from_str(matches.value_of("rate").unwrap())
+        .expect("Could not parse the packet rate");
+    let policy = EvictionPolicy {
+        timeout: matches
+            .value_of("timeout")
+            .map(|t| u64::from_str(t).expect("Could not parse the eviction timeout")),
+        max_bytes: matches
+            .value_of("maxbytes")
+            .map(|b| usize::from_str(b).expect("Could not parse the reassembly memory limit")),
+    };
+
+    match rt {
+        Runtime::STM if streaming && threads > 1 && anomaly_percentage > 0 => {
+            eprintln!("[ERROR] Streaming mode of the STM runtime does not support anomalies on more than one thread.");
+            process::exit(1);
+        }
+        Runtime::Seq | Runtime::STM => (),
+        Runtime::DSTM | Runtime::Sharded if !streaming => (),
+        _ if streaming => {
+            eprintln!("[ERROR] Streaming mode is only supported by the Seq and STM runtimes.");
+            process::exit(1);
+        }
+        _ if !policy.is_unbounded() => {
+            eprintln!("[ERROR] The Ohua runtimes do not support evicting flows.");
+            process::exit(1);
+        }
+        _ if reassembly != ReassemblyPolicy::default() => {
+            eprintln!("[ERROR] The Ohua runtimes only support the {} reassembly policy.", ReassemblyPolicy::default());
+            process::exit(1);
+        }
+        _ => (),
+    }
 
 
-    // generate the input data
-    let (input, attacks) = intruder::generate_stream(flowcount, attack_percentage, max_packet_len, rng_seed);
+    // signatures
+    let rules_file = matches.value_of("rules");
+    let rules = match rules_file {
+        Some(file) => match RuleSet::read_file(file) {
+            Ok(rules) => rules,
+            Err(e) => {
+                eprintln!("[ERROR] Failed to read the rules from {}: {}", file, e);
+                process::exit(1);
+            }
+        },
+        None => RuleSet::default(),
+    };
     if !json_dump {
+        println!("[INFO] Loaded {} rules.", rules.len());
+    }
+
+    // generate or load the input data
+    let pcap_file = matches.value_of("pcap");
+    let (mut input, mut attacks) = match pcap_file {
+        Some(file) => match intruder::pcap::read_stream(file, &rules) {
+            Ok(stream) => stream,
+            Err(e) => {
+                eprintln!("[ERROR] Failed to read {}: {}", file, e);
+                process::exit(1);
+            }
+        },
+        None => intruder::generate_stream(
+            flowcount,
+            attack_percentage,
+            max_packet_len,
+            rng_seed,
+            &rules,
+            obfuscation_percentage,
+            binary,
+        ),
+    };
+    if pcap_file.is_some() {
+        flowcount = input.iter().filter(|p| p.fragment_id == 0).count();
+        if !json_dump {
+            println!(
+                "[INFO] Loaded {} packets in {} flows, flows containing an attack: {}",
+                input.len(),
+                flowcount,
+                attacks.len()
+            );
+        }
+    } else if !json_dump {
         println!(
             "[INFO] Generated flows containing an attack: {}",
             attacks.len()
         );
     }
 
+    if let Some(file) = matches.value_of("dumppcap") {
+        if let Err(e) = intruder::pcap::write_stream(file, &input) {
+            eprintln!("[ERROR] Could not write the packet stream: {}", e);
+        }
+    }
+
+    // the anomalies are added after the dump, the capture only holds valid fragments
+    let injected = intruder::inject_anomalies(
+        &mut input,
+        &mut attacks,
+        anomaly_percentage,
+        reassembly,
+        rng_seed,
+        &rules,
+    );
+    if anomaly_percentage > 0 && !json_dump {
+        println!(
+            "[INFO] Added {} duplicated, {} overlapping and {} malformed fragments, flows containing an attack: {}",
+            injected.duplicates,
+            injected.overlaps,
+            injected.malformed,
+            attacks.len()
+        );
+    }
+
+    // compile the signatures once, outside of the timed section
+    let detector = Arc::new(Detector::new(engine, &rules, normalize));
+
     let mut results = Vec::with_capacity(runs);
     let mut cpu_results = Vec::with_capacity(runs);
+    let mut evicted_counts = Vec::with_capacity(runs);
+    let mut decoder_stats = Vec::with_capacity(runs);
+    let mut accuracies = Vec::with_capacity(runs);
+    let mut throughputs = Vec::with_capacity(runs);
+    let mut latency_stats = Vec::with_capacity(runs);
 
     for r in 0..runs {
         // prepare the data for the run
         // let input_data = TVar::new(input.clone());
         let input_data = input.clone();
         let input_vec: Vec<_> = input.clone().into_iter().collect();
+        // the source thread starts feeding right away, so it is started with the clock
+        let input_stream = if streaming {
+            Some(input.clone())
+        } else {
+            None
+        };
 
         // start the clock
         let start = PreciseTime::now();
         let cpu_start = ProcessTime::now();
 
         // run the algorithm
-        let result = match rt {
-            Runtime::Seq => seq::analyze_flow(input_data),
-            Runtime::STM => stm::run_eval(input_data, threads),
-            Runtime::DSTM => dstm::run_eval(input_vec, threads),
-            Runtime::OhuaSeq => ohua::analyze_flow_3(input_data),
-            Runtime::Ohua => generated::ohua::analyze_flow_3(input_data)
+        let mut latencies = Vec::new();
+        let (result, stats) = match input_stream {
+            Some(packets) => {
+                let arrivals = stream::feed(packets, if rate > 0 { Some(rate) } else { None });
+                let (result, stats, l) = match rt {
+                    Runtime::Seq => seq::analyze_stream(arrivals, &detector, policy, reassembly),
+                    Runtime::STM => stm::run_stream(arrivals, threads, detector.clone(), policy, reassembly),
+                    _ => unreachable!(),
+                };
+                latencies = l;
+                (result, Some(stats))
+            }
+            None => match rt {
+                Runtime::Seq => {
+                    let (result, stats) = seq::analyze_flow(input_data, &detector, policy, reassembly);
+                    (result, Some(stats))
+                }
+                Runtime::STM => {
+                    let (result, stats) = stm::run_eval(input_data, threads, detector.clone(), policy, reassembly);
+                    (result, Some(stats))
+                }
+                Runtime::DSTM => {
+                    let (result, stats) = dstm::run_eval(input_vec, threads, detector.clone(), policy, reassembly);
+                    (result, Some(stats))
+                }
+                Runtime::Sharded => {
+                    let (result, stats) = sharded::run_eval(input_data, threads, detector.clone(), policy, reassembly);
+                    (result, Some(stats))
+                }
+                // the Ohua algorithms do not report the statistics of their decoder
+                Runtime::OhuaSeq => (ohua::analyze_flow_3(input_data, detector.clone()), None),
+                Runtime::Ohua => (generated::ohua::analyze_flow_3(input_data, detector.clone()), None),
+            },
         };
 
         // stop the clock
@@ -160,12 +402,26 @@ fn main() {
             println!("[INFO] Routing run {} completed.", r + 1);
         }
 
-        // verify correctness
-        if result.len() != attacks.len() {
-            println!("[ERROR] Output verification failed. An incorrect number of attacks has been found. ({}/{})", result.len(), attacks.len());
+        // verify correctness, attacks in evicted flows cannot be found (a flow can be found and
+        // evicted when a late fragment arrives after it has been reassembled)
+        let evicted_flows: HashSet<usize> = stats
+            .as_ref()
+            .map_or_else(HashSet::new, |s| s.evicted.iter().cloned().collect());
+        let found: HashSet<usize> = result.iter().map(|alert| alert.flow_id).collect();
+        let expected = attacks
+            .iter()
+            .filter(|f| found.contains(f) || !evicted_flows.contains(f))
+            .count();
+        if result.len() != expected || !found.is_subset(&attacks) {
+            println!("[ERROR] Output verification failed. An incorrect number of attacks has been found. ({}/{})", result.len(), expected);
         } else {
             results.push(runtime_ms);
             cpu_results.push(cpu_runtime_ms);
+            evicted_counts.push(evicted_flows.len());
+            decoder_stats.extend(stats);
+            throughputs.push(input.len() as f64 * 1000.0 / runtime_ms.max(1) as f64);
+            latency_stats.push(LatencyStats::from_latencies(latencies));
+            accuracies.push(Accuracy::evaluate(&result, &attacks));
         }
     }
 
@@ -175,6 +431,7 @@ fn main() {
             Runtime::Seq => "sequential",
             Runtime::STM => "rust-stm",
             Runtime::DSTM => "rust-dstm",
+            Runtime::Sharded => "sharded",
             Runtime::OhuaSeq => "ohua-seq",
             Runtime::Ohua => "ohua",
         };
@@ -188,41 +445,144 @@ fn main() {
         f.write_fmt(format_args!(
             "{{
     \"algorithm\": \"{alg}\",
+    \"engine\": \"{engine}\",
+    \"rules\": \"{rules}\",
+    \"rule_count\": {rule_count},
+    \"input\": \"{input}\",
     \"flow_count\": {flows},
     \"attack_percentage\": {attack_perc},
+    \"obfuscation_percentage\": {obfuscation},
+    \"binary\": {binary},
+    \"normalize\": {normalize},
+    \"anomaly_percentage\": {anomalies},
+    \"reassembly\": \"{reassembly}\",
+    \"streaming\": {streaming},
+    \"rate\": {rate},
+    \"timeout\": {timeout},
+    \"max_bytes\": {max_bytes},
     \"attack_count\": {attacks},
     \"runs\": {runs},
     \"prng_seed\": {seed},
     \"max_packet_len\": {packet_len},
     \"threadcount\": {threadcount},
     \"cpu_time\": {cpu:?},
-    \"results\": {res:?}
+    \"results\": {res:?},
+    \"evicted\": {evicted:?},
+    \"duplicates\": {duplicates:?},
+    \"overlaps\": {overlaps:?},
+    \"malformed\": {malformed:?},
+    \"true_positives\": {tp:?},
+    \"false_positives\": {fp:?},
+    \"false_negatives\": {fn_:?},
+    \"precision\": {precision:?},
+    \"recall\": {recall:?},
+    \"signature_hits\": [{hits}],
+    \"throughput\": {throughput:?},
+    \"latency_mean_us\": {latency_mean:?},
+    \"latency_p99_us\": {latency_p99:?}
 }}",
             alg = algo,
+            engine = engine,
+            rules = rules_file.unwrap_or("builtin"),
+            rule_count = rules.len(),
+            input = pcap_file.unwrap_or("generated"),
             flows = flowcount,
             attack_perc = attack_percentage,
+            obfuscation = obfuscation_percentage,
+            binary = binary,
+            normalize = normalize,
+            anomalies = anomaly_percentage,
+            reassembly = reassembly,
+            streaming = streaming,
+            rate = rate,
+            timeout = json_option(policy.timeout),
+            max_bytes = json_option(policy.max_bytes),
             attacks = attacks.len(),
             runs = runs,
             seed = rng_seed,
             packet_len = max_packet_len,
             threadcount = threads,
             cpu = cpu_results,
-            res = results
+            res = results,
+            evicted = evicted_counts,
+            duplicates = decoder_stats.iter().map(|s| s.duplicates).collect::<Vec<_>>(),
+            overlaps = decoder_stats.iter().map(|s| s.overlaps).collect::<Vec<_>>(),
+            malformed = decoder_stats.iter().map(|s| s.malformed).collect::<Vec<_>>(),
+            tp = accuracies.iter().map(|a| a.true_positives).collect::<Vec<_>>(),
+            fp = accuracies.iter().map(|a| a.false_positives).collect::<Vec<_>>(),
+            fn_ = accuracies.iter().map(|a| a.false_negatives).collect::<Vec<_>>(),
+            precision = accuracies.iter().map(Accuracy::precision).collect::<Vec<_>>(),
+            recall = accuracies.iter().map(Accuracy::recall).collect::<Vec<_>>(),
+            hits = accuracies.iter().map(Accuracy::hits_json).collect::<Vec<_>>().join(", "),
+            throughput = throughputs,
+            latency_mean = latency_stats.iter().map(|l| l.mean).collect::<Vec<_>>(),
+            latency_p99 = latency_stats.iter().map(|l| l.p99).collect::<Vec<_>>()
         ))
         .unwrap();
     } else {
         println!("[INFO] All runs completed successfully.");
         println!("\nStatistics:");
+        if let Some(file) = pcap_file {
+            println!("    Input capture:         {}", file);
+        }
         println!("    Number of flows:       {}", flowcount);
+        println!("    Matching engine:       {}", engine);
+        println!("    Normalization:         {}", normalize);
+        println!("    Rules:                 {} ({})", rules.len(), rules_file.unwrap_or("builtin"));
         println!("    Percentage of attacks: {}%", attack_percentage);
+        println!("    Obfuscated attacks:    {}%", obfuscation_percentage);
+        println!("    Binary payloads:       {}", binary);
+        println!("    Anomalous flows:       {}%", anomaly_percentage);
+        println!("    Reassembly policy:     {}", reassembly);
         println!("    PRNG seed:             {}", rng_seed);
         println!("    Maximal Packet Length: {}", max_packet_len);
         println!("    Generated Attacks:     {}", attacks.len());
         println!("    Threads used:          {}", threads);
         println!("    Runs:                  {}", runs);
+        if streaming {
+            println!("    Packet rate:           {}", if rate > 0 { rate.to_string() } else { "unlimited".into() });
+        }
+        println!("    Eviction timeout:      {}", policy.timeout.map_or("none".into(), |t| format!("{} packets", t)));
+        println!("    Reassembly memory:     {}", policy.max_bytes.map_or("unbounded".into(), |b| format!("{} bytes", b)));
         println!("\nCPU-time used (ms): {:?}", cpu_results);
         println!("Runtime in ms: {:?}", results);
+        println!("Evicted flows: {:?}", evicted_counts);
+        for (run, accuracy) in accuracies.iter().enumerate() {
+            println!(
+                "Accuracy run {}: {} true positives, {} false positives, {} false negatives (precision {:.4}, recall {:.4})",
+                run + 1,
+                accuracy.true_positives,
+                accuracy.false_positives,
+                accuracy.false_negatives,
+                accuracy.precision(),
+                accuracy.recall()
+            );
+        }
+        if let Some(accuracy) = accuracies.last() {
+            let mut hits: Vec<(&u32, &usize)> = accuracy.hits.iter().collect();
+            hits.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+            let top: Vec<String> = hits.iter().take(10).map(|(id, count)| format!("{}: {}", id, count)).collect();
+            println!("Most frequent signatures (rule id: alerts): {}", top.join(", "));
+        }
+        if anomaly_percentage > 0 && !decoder_stats.is_empty() {
+            let counters = |f: fn(&DecoderStats) -> usize| decoder_stats.iter().map(f).collect::<Vec<_>>();
+            println!("Duplicated fragments: {:?} (added {})", counters(|s| s.duplicates), injected.duplicates);
+            println!("Overlapping fragments: {:?} (added {})", counters(|s| s.overlaps), injected.overlaps);
+            println!("Malformed packets: {:?} (added {})", counters(|s| s.malformed), injected.malformed);
+        }
+        println!("Throughput (packets/s): {:?}", throughputs);
+        for (run, stats) in latency_stats.iter().enumerate().filter(|_| streaming) {
+            println!(
+                "Latency run {} (us): mean {:.1}, p50 {:.1}, p99 {:.1}, max {:.1}",
+                run + 1, stats.mean, stats.p50, stats.p99, stats.max
+            );
+        }
     }
 }
 
+/// Formats an optional limit for the output, `null` meaning unbounded.
+fn json_option<T: ToString>(value: Option<T>) -> String {
+    value.map_or_else(|| "null".into(), |v| v.to_string())
+}
+
 
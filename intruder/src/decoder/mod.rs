@@ -1,3 +1,6 @@
+use crate::Packet;
+use strum_macros::{Display, EnumString};
+
 //#[cfg(not(feature = "transactional"))]
 pub mod simple;
 
@@ -7,5 +10,165 @@ pub mod stm_decoder;
 #[derive(PartialEq, Debug)]
 pub struct DecodedFlow {
     pub flow_id: usize,
-    pub data: String,
+    pub data: Vec<u8>,
+}
+
+/// Limits for the fragments of incomplete flows a decoder keeps. Flows that exceed them are
+/// dropped (evicted) and can not be reassembled anymore.
+///
+/// Time is counted in packets: every packet handed to a decoder advances its clock by one.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct EvictionPolicy {
+    /// Incomplete flows that did not receive a fragment for this many packets are dropped
+    pub timeout: Option<u64>,
+    /// Upper bound for the payload bytes of all buffered fragments. When exceeded, the least
+    /// recently updated flows are dropped first.
+    pub max_bytes: Option<usize>,
+}
+
+impl EvictionPolicy {
+    pub fn is_unbounded(&self) -> bool {
+        self.timeout.is_none() && self.max_bytes.is_none()
+    }
+
+    fn is_expired(&self, last_seen: u64, now: u64) -> bool {
+        matches!(self.timeout, Some(t) if now.saturating_sub(last_seen) >= t)
+    }
+}
+
+/// Decides which data is kept when a fragment is received again with different data.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Display, EnumString)]
+#[strum(serialize_all = "kebab-case")]
+pub enum ReassemblyPolicy {
+    /// The fragment received first is kept
+    #[default]
+    FirstWins,
+    /// The fragment received last replaces the buffered one
+    LastWins,
+}
+
+/// What a decoder did besides reassembling flows.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DecoderStats {
+    /// Ids of the incomplete flows that have been dropped
+    pub evicted: Vec<usize>,
+    /// Fragments that had already been received with the same data
+    pub duplicates: usize,
+    /// Fragments that had already been received with different data
+    pub overlaps: usize,
+    /// Packets that have been dropped because of inconsistent fragment information
+    pub malformed: usize,
+}
+
+impl DecoderStats {
+    /// Adds the statistics of another decoder, e.g., of another shard.
+    pub fn merge(&mut self, other: DecoderStats) {
+        self.evicted.extend(other.evicted);
+        self.duplicates += other.duplicates;
+        self.overlaps += other.overlaps;
+        self.malformed += other.malformed;
+    }
+
+    fn record(&mut self, merge: &Merge) {
+        match merge {
+            Merge::Inserted => (),
+            Merge::Duplicate => self.duplicates += 1,
+            Merge::Overlap { .. } => self.overlaps += 1,
+            Merge::Malformed => self.malformed += 1,
+        }
+    }
+}
+
+/// Checks the fragment information of a packet on its own.
+fn is_malformed(packet: &Packet) -> bool {
+    packet.fragment_id >= packet.packets_in_flow || packet.length != packet.data.len()
+}
+
+/// The outcome of adding a fragment to the buffered fragments of its flow.
+#[derive(Debug, PartialEq, Eq)]
+enum Merge {
+    /// The fragment is new and has been inserted
+    Inserted,
+    /// The fragment had been buffered already, the packet is dropped
+    Duplicate,
+    /// The fragment had been buffered with different data, the data kept is chosen by the
+    /// `ReassemblyPolicy`. Carries the payload bytes that have been dropped.
+    Overlap { dropped: usize },
+    /// The fragment does not belong to the buffered ones, the packet is dropped
+    Malformed,
+}
+
+/// Adds the `packet` to the `fragments` of its flow, which are sorted by the fragment id.
+fn merge_fragment(fragments: &mut Vec<Packet>, packet: Packet, policy: ReassemblyPolicy) -> Merge {
+    if let Some(first) = fragments.first() {
+        if first.packets_in_flow != packet.packets_in_flow {
+            return Merge::Malformed;
+        }
+    }
+
+    match fragments.binary_search_by_key(&packet.fragment_id, |p| p.fragment_id) {
+        Ok(idx) if fragments[idx].data == packet.data => Merge::Duplicate,
+        Ok(idx) => {
+            let dropped = match policy {
+                ReassemblyPolicy::FirstWins => packet.data.len(),
+                ReassemblyPolicy::LastWins => {
+                    std::mem::replace(&mut fragments[idx], packet).data.len()
+                }
+            };
+            Merge::Overlap { dropped }
+        }
+        Err(idx) => {
+            fragments.insert(idx, packet);
+            Merge::Inserted
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fragment(fragment_id: usize, packets_in_flow: usize, data: &str) -> Packet {
+        Packet {
+            flow_id: 1,
+            fragment_id,
+            packets_in_flow,
+            length: data.len(),
+            data: data.into(),
+        }
+    }
+
+    #[test]
+    fn merge_fragments() {
+        for &policy in &[ReassemblyPolicy::FirstWins, ReassemblyPolicy::LastWins] {
+            let mut fragments = Vec::new();
+            assert_eq!(merge_fragment(&mut fragments, fragment(2, 3, "c"), policy), Merge::Inserted);
+            assert_eq!(merge_fragment(&mut fragments, fragment(0, 3, "a"), policy), Merge::Inserted);
+            assert_eq!(merge_fragment(&mut fragments, fragment(0, 3, "a"), policy), Merge::Duplicate);
+            assert_eq!(
+                merge_fragment(&mut fragments, fragment(2, 3, "xy"), policy),
+                Merge::Overlap {
+                    dropped: if policy == ReassemblyPolicy::FirstWins { 2 } else { 1 }
+                }
+            );
+            assert_eq!(merge_fragment(&mut fragments, fragment(1, 4, "b"), policy), Merge::Malformed);
+
+            let data: Vec<&[u8]> = fragments.iter().map(|p| p.data.as_slice()).collect();
+            match policy {
+                ReassemblyPolicy::FirstWins => assert_eq!(data, vec![b"a", b"c"]),
+                ReassemblyPolicy::LastWins => assert_eq!(data, vec![&b"a"[..], b"xy"]),
+            }
+        }
+    }
+
+    #[test]
+    fn malformed_packets() {
+        assert!(!is_malformed(&fragment(0, 1, "a")));
+        assert!(is_malformed(&fragment(1, 1, "a")));
+        assert!(is_malformed(&fragment(0, 0, "a")));
+        assert!(is_malformed(&Packet {
+            length: 2,
+            ..fragment(0, 2, "a")
+        }));
+    }
 }
@@ -1,60 +1,161 @@
-use super::DecodedFlow;
+use super::{
+    is_malformed, merge_fragment, DecodedFlow, DecoderStats, EvictionPolicy, Merge,
+    ReassemblyPolicy,
+};
 use crate::Packet;
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 
 pub struct Decoder {
     pub fragments_map: HashMap<usize, Vec<Packet>>,
+    pub stats: DecoderStats,
+    policy: EvictionPolicy,
+    reassembly: ReassemblyPolicy,
+    /// Number of packets decoded so far
+    clock: u64,
+    /// Time of the latest fragment of every incomplete flow (only kept for bounded policies)
+    last_seen: HashMap<usize, u64>,
+    /// The incomplete flows ordered by the time of their latest fragment
+    lru: BTreeSet<(u64, usize)>,
+    buffered_bytes: usize,
 }
 
 impl Decoder {
     pub fn new() -> Self {
+        Decoder::with_policy(EvictionPolicy::default(), ReassemblyPolicy::default())
+    }
+
+    pub fn with_policy(policy: EvictionPolicy, reassembly: ReassemblyPolicy) -> Self {
         Self {
             fragments_map: HashMap::new(),
+            stats: DecoderStats::default(),
+            policy,
+            reassembly,
+            clock: 0,
+            last_seen: HashMap::new(),
+            lru: BTreeSet::new(),
+            buffered_bytes: 0,
         }
     }
 
     /// Decodes a packet. If the packet is part of a fragmented flow and the flow is
     /// not completely decoded yet, the function will return `None` and place the
     /// packet in its internal storage
+    ///
+    /// Fragments that have been received before are dropped or replace the buffered ones,
+    /// depending on the `ReassemblyPolicy`. Packets with inconsistent fragment information are
+    /// dropped. Both are counted in the `stats`.
     pub fn decode_flow(&mut self, packet: Packet) -> Option<DecodedFlow> {
-        if packet.packets_in_flow != 1 {
+        self.clock += 1;
+        let bounded = !self.policy.is_unbounded();
+
+        let result = if is_malformed(&packet) {
+            self.stats.malformed += 1;
+            None
+        } else if packet.packets_in_flow != 1 {
             // this is part of a fragmented flow
+            let flow_id = packet.flow_id;
+            let length = packet.data.len();
             let decoded = self
                 .fragments_map
                 .entry(packet.flow_id)
                 .or_insert(Vec::new());
-    
+
             // insert the current element into the queue
-            let idx = decoded
-                .iter()
-                .position(|p| packet.fragment_id < p.fragment_id)
-                .unwrap_or(decoded.len());
-            decoded.insert(idx, packet);
-    
+            let merge = merge_fragment(decoded, packet, self.reassembly);
+            let complete = merge == Merge::Inserted && decoded.len() == decoded[0].packets_in_flow;
+            self.stats.record(&merge);
+
             // reassemble the flow if all fragments are present
-            if decoded.len() == decoded[0].packets_in_flow {
-                let flow_id = decoded[0].flow_id;
+            if complete {
+                let decoded = self.fragments_map.get_mut(&flow_id).unwrap();
                 let reconstructed_data = decoded
                     .drain(..)
-                    .fold(String::new(), |acc, p| acc + &p.data);
-    
+                    .fold(Vec::new(), |mut acc, p| {
+                        acc.extend_from_slice(&p.data);
+                        acc
+                    });
+
                 // TODO: Remove assertion?
                 assert!(self.fragments_map.remove(&flow_id).is_some());
+                if bounded {
+                    // the current packet has never been buffered
+                    self.untrack(flow_id, reconstructed_data.len() - length);
+                }
                 Some(DecodedFlow {
                     flow_id,
                     data: reconstructed_data,
                 })
             } else {
+                if bounded {
+                    match merge {
+                        Merge::Inserted => self.track(flow_id, length, 0),
+                        Merge::Overlap { dropped } => self.track(flow_id, length, dropped),
+                        Merge::Duplicate | Merge::Malformed => (),
+                    }
+                }
                 None
             }
         } else {
             // this is the only packet
-            assert!(packet.fragment_id == 0);
-    
             Some(DecodedFlow {
                 flow_id: packet.flow_id,
                 data: packet.data,
             })
+        };
+
+        if bounded {
+            self.evict();
+        }
+
+        result
+    }
+
+    /// Drops all incomplete flows, e.g., at the end of the stream.
+    pub fn flush(&mut self) {
+        let mut remaining: Vec<usize> = self.fragments_map.keys().copied().collect();
+        remaining.sort_unstable();
+        for flow_id in remaining {
+            self.drop_flow(flow_id);
+        }
+    }
+
+    /// Records the arrival of a buffered fragment with `bytes` payload for `flow_id`, which
+    /// replaced `dropped` bytes of buffered payload.
+    fn track(&mut self, flow_id: usize, bytes: usize, dropped: usize) {
+        if let Some(last) = self.last_seen.insert(flow_id, self.clock) {
+            self.lru.remove(&(last, flow_id));
+        }
+        self.lru.insert((self.clock, flow_id));
+        self.buffered_bytes = self.buffered_bytes + bytes - dropped;
+    }
+
+    /// Removes the bookkeeping for a flow with `bytes` buffered payload.
+    fn untrack(&mut self, flow_id: usize, bytes: usize) {
+        if let Some(last) = self.last_seen.remove(&flow_id) {
+            self.lru.remove(&(last, flow_id));
+        }
+        self.buffered_bytes -= bytes;
+    }
+
+    /// Drops expired flows and the least recently updated ones while the buffer is too large.
+    fn evict(&mut self) {
+        while let Some(&(last, flow_id)) = self.lru.iter().next() {
+            let expired = self.policy.is_expired(last, self.clock);
+            let too_large = matches!(self.policy.max_bytes, Some(max) if self.buffered_bytes > max);
+            if !expired && !too_large {
+                break;
+            }
+            self.drop_flow(flow_id);
+        }
+    }
+
+    fn drop_flow(&mut self, flow_id: usize) {
+        if let Some(fragments) = self.fragments_map.remove(&flow_id) {
+            let bytes = fragments.iter().map(|p| p.data.len()).sum();
+            if !self.policy.is_unbounded() {
+                self.untrack(flow_id, bytes);
+            }
+            self.stats.evicted.push(flow_id);
         }
     }
 }
@@ -100,4 +201,103 @@ mod tests {
             })
         );
     }
+
+    fn fragment(flow_id: usize, fragment_id: usize, packets_in_flow: usize, data: &str) -> Packet {
+        Packet {
+            flow_id,
+            fragment_id,
+            packets_in_flow,
+            length: data.len(),
+            data: data.into(),
+        }
+    }
+
+    #[test]
+    fn evict_after_timeout() {
+        let mut dec = Decoder::with_policy(
+            EvictionPolicy {
+                timeout: Some(2),
+                max_bytes: None,
+            },
+            ReassemblyPolicy::default(),
+        );
+
+        assert_eq!(dec.decode_flow(fragment(1, 0, 2, "a")), None);
+        assert_eq!(dec.decode_flow(fragment(2, 0, 2, "b")), None);
+        // flow 1 has not seen a fragment for 2 packets
+        assert!(dec.decode_flow(fragment(3, 0, 1, "c")).is_some());
+        assert_eq!(dec.stats.evicted, vec![1]);
+
+        assert_eq!(
+            dec.decode_flow(fragment(2, 1, 2, "b")),
+            Some(DecodedFlow {
+                flow_id: 2,
+                data: "bb".into()
+            })
+        );
+        // the late fragment of flow 1 can not complete it anymore
+        assert_eq!(dec.decode_flow(fragment(1, 1, 2, "a")), None);
+        dec.flush();
+        assert_eq!(dec.stats.evicted, vec![1, 1]);
+        assert!(dec.fragments_map.is_empty());
+    }
+
+    #[test]
+    fn evict_least_recently_used() {
+        let mut dec = Decoder::with_policy(
+            EvictionPolicy {
+                timeout: None,
+                max_bytes: Some(5),
+            },
+            ReassemblyPolicy::default(),
+        );
+
+        assert_eq!(dec.decode_flow(fragment(1, 0, 3, "aa")), None);
+        assert_eq!(dec.decode_flow(fragment(2, 0, 2, "bb")), None);
+        assert_eq!(dec.decode_flow(fragment(1, 1, 3, "a")), None);
+        // 7 bytes buffered, flow 2 is the least recently updated one
+        assert_eq!(dec.decode_flow(fragment(3, 0, 2, "cc")), None);
+        assert_eq!(dec.stats.evicted, vec![2]);
+        assert!(dec.decode_flow(fragment(1, 2, 3, "a")).is_some());
+        assert_eq!(dec.buffered_bytes, 2);
+    }
+
+    #[test]
+    fn reassembly_anomalies() {
+        let mut first_wins = Decoder::new();
+        let mut last_wins = Decoder::with_policy(
+            EvictionPolicy {
+                timeout: None,
+                max_bytes: Some(100),
+            },
+            ReassemblyPolicy::LastWins,
+        );
+
+        for dec in &mut [&mut first_wins, &mut last_wins] {
+            assert_eq!(dec.decode_flow(fragment(1, 0, 3, "ab")), None);
+            assert_eq!(dec.decode_flow(fragment(1, 0, 3, "ab")), None);
+            assert_eq!(dec.decode_flow(fragment(1, 0, 3, "xyz")), None);
+            // out of range, inconsistent number of fragments and a single packet with an id
+            assert_eq!(dec.decode_flow(fragment(1, 3, 3, "c")), None);
+            assert_eq!(dec.decode_flow(fragment(1, 1, 4, "c")), None);
+            assert_eq!(dec.decode_flow(fragment(2, 1, 1, "c")), None);
+            assert_eq!(dec.decode_flow(fragment(1, 1, 3, "c")), None);
+        }
+
+        assert_eq!(first_wins.decode_flow(fragment(1, 2, 3, "d")).unwrap().data, b"abcd");
+        assert_eq!(last_wins.decode_flow(fragment(1, 2, 3, "d")).unwrap().data, b"xyzcd");
+        for dec in &[first_wins, last_wins] {
+            assert_eq!(
+                dec.stats,
+                DecoderStats {
+                    evicted: Vec::new(),
+                    duplicates: 1,
+                    overlaps: 1,
+                    malformed: 3,
+                }
+            );
+            assert!(dec.fragments_map.is_empty());
+            assert_eq!(dec.buffered_bytes, 0);
+        }
+    }
 }
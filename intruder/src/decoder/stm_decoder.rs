@@ -1,20 +1,131 @@
-use crate::decoder::DecodedFlow;
+use crate::decoder::{
+    is_malformed, merge_fragment, DecodedFlow, DecoderStats, EvictionPolicy, Merge,
+    ReassemblyPolicy,
+};
 use crate::Packet;
 use std::collections::HashMap;
-use stm::{StmError, StmResult, TVar, Transaction};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use stm::{atomically, StmError, StmResult, TVar, Transaction};
 use stm_datastructures::THashMap;
 
+/// The fragments of an incomplete flow.
+#[derive(Clone, Debug)]
+pub struct PendingFlow {
+    /// Time of the latest fragment
+    pub last_seen: u64,
+    pub fragments: Vec<Packet>,
+    /// Set when the flow has been evicted, for threads that still hold a reference to it
+    pub dropped: bool,
+}
+
 #[derive(Clone)]
 pub struct StmDecoderState {
-    pub fragments_map: THashMap<usize, TVar<Vec<Packet>>>,
+    pub fragments_map: THashMap<usize, TVar<PendingFlow>>,
+    pub stats: TVar<DecoderStats>,
+    policy: EvictionPolicy,
+    reassembly: ReassemblyPolicy,
+    bucket_no: usize,
+    /// Number of packets handed to the decoder so far, advanced with `tick`
+    clock: Arc<AtomicU64>,
 }
 
 impl StmDecoderState {
     pub fn new(bucket_no: usize) -> Self {
+        StmDecoderState::with_policy(
+            bucket_no,
+            EvictionPolicy::default(),
+            ReassemblyPolicy::default(),
+        )
+    }
+
+    /// Creates a decoder that bounds the buffered fragments with the given `policy`. The limits
+    /// are enforced per bucket whenever a new flow is added to it, each bucket gets the same
+    /// share of the `max_bytes`. Fragments received again are handled with the `reassembly`
+    /// policy, in the order the transactions commit.
+    pub fn with_policy(
+        bucket_no: usize,
+        policy: EvictionPolicy,
+        reassembly: ReassemblyPolicy,
+    ) -> Self {
         Self {
             fragments_map: THashMap::new(bucket_no),
+            stats: TVar::new(DecoderStats::default()),
+            policy,
+            reassembly,
+            bucket_no,
+            clock: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Advances the clock of the decoder. Must be called once for every packet, before it is
+    /// decoded.
+    pub fn tick(&self) -> u64 {
+        self.clock.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    /// Drops all incomplete flows, e.g., at the end of the stream.
+    pub fn flush(&self) {
+        let mut remaining: Vec<usize> = self.fragments_map.get_contents().keys().copied().collect();
+        remaining.sort_unstable();
+
+        for flow_id in remaining {
+            atomically(|trans| {
+                self.fragments_map.get_bucket(&flow_id).modify(trans, |mut hm| {
+                    let _ = hm.remove(&flow_id);
+                    hm
+                })?;
+                self.stats.modify(trans, |mut s| {
+                    s.evicted.push(flow_id);
+                    s
+                })
+            });
         }
     }
+
+    /// Drops the expired flows of a bucket and, if the bucket exceeds its share of the byte
+    /// limit, the least recently updated ones.
+    fn evict(
+        &self,
+        bucket: &mut HashMap<usize, TVar<PendingFlow>>,
+        now: u64,
+        transaction: &mut Transaction,
+    ) -> StmResult<()> {
+        let mut flows = Vec::with_capacity(bucket.len());
+        for (flow_id, flow_tv) in bucket.iter() {
+            let flow = flow_tv.read(transaction)?;
+            let bytes: usize = flow.fragments.iter().map(|p| p.data.len()).sum();
+            flows.push((flow.last_seen, *flow_id, bytes, flow));
+        }
+        flows.sort_unstable_by_key(|(last_seen, flow_id, ..)| (*last_seen, *flow_id));
+
+        let budget = self.policy.max_bytes.map(|max| max / self.bucket_no.max(1));
+        let mut buffered: usize = flows.iter().map(|(_, _, bytes, _)| bytes).sum();
+        let mut dropped = Vec::new();
+
+        for (last_seen, flow_id, bytes, mut flow) in flows {
+            let expired = self.policy.is_expired(last_seen, now);
+            let too_large = matches!(budget, Some(max) if buffered > max);
+            if !expired && !too_large {
+                break;
+            }
+
+            let flow_tv = bucket.remove(&flow_id).unwrap();
+            flow.dropped = true;
+            flow_tv.write(transaction, flow)?;
+            buffered -= bytes;
+            dropped.push(flow_id);
+        }
+
+        if !dropped.is_empty() {
+            self.stats.modify(transaction, |mut s| {
+                s.evicted.extend(dropped);
+                s
+            })?;
+        }
+
+        Ok(())
+    }
 }
 
 /// Decodes a packet. If the packet is part of a fragmented flow and the flow is
@@ -25,31 +136,52 @@ pub fn decode_packet(
     state: &StmDecoderState,
     transaction: &mut Transaction,
 ) -> StmResult<Option<DecodedFlow>> {
-    if packet.packets_in_flow != 1 {
+    let now = state.clock.load(Ordering::Relaxed);
+
+    if is_malformed(packet) {
+        state.stats.modify(transaction, |mut s| {
+            s.malformed += 1;
+            s
+        })?;
+        Ok(None)
+    } else if packet.packets_in_flow != 1 {
         // get the matching TVar (== bucket) and read it
         let bucket = state.fragments_map.get_bucket(&packet.flow_id);
         let frags = bucket
             .read_ref_atomic()
-            .downcast::<HashMap<usize, TVar<Vec<Packet>>>>()
+            .downcast::<HashMap<usize, TVar<PendingFlow>>>()
             .unwrap();
 
         if let Some(decoded_tv) = frags.get(&packet.flow_id) {
             // we already have some packets with that ID
 
             let mut decoded = decoded_tv.read(transaction)?;
+            if decoded.dropped {
+                // the flow has been evicted since the bucket was read
+                return start_flow(packet, state, bucket, now, transaction);
+            }
+
             // insert the current element into the queue
-            let idx = decoded
-                .iter()
-                .position(|p| packet.fragment_id < p.fragment_id)
-                .unwrap_or(decoded.len());
-            decoded.insert(idx, packet.to_owned());
+            let merge = merge_fragment(&mut decoded.fragments, packet.to_owned(), state.reassembly);
+            if merge != Merge::Inserted {
+                state.stats.modify(transaction, |mut s| {
+                    s.record(&merge);
+                    s
+                })?;
+            }
 
             // reassemble the flow if all fragments are present
-            if decoded.len() == decoded[0].packets_in_flow {
-                let flow_id = decoded[0].flow_id;
+            if merge == Merge::Inserted
+                && decoded.fragments.len() == decoded.fragments[0].packets_in_flow
+            {
+                let flow_id = decoded.fragments[0].flow_id;
                 let reconstructed_data = decoded
+                    .fragments
                     .into_iter()
-                    .fold(String::new(), |acc, p| acc + &p.data);
+                    .fold(Vec::new(), |mut acc, p| {
+                        acc.extend_from_slice(&p.data);
+                        acc
+                    });
 
                 // remove the flow from the hashmap & write that back
                 bucket.modify(transaction, |mut hm| {
@@ -62,34 +194,18 @@ pub fn decode_packet(
                     data: reconstructed_data,
                 }))
             } else {
-                decoded_tv.write(transaction, decoded)?;
+                if let Merge::Inserted | Merge::Overlap { .. } = merge {
+                    decoded.last_seen = now;
+                    decoded_tv.write(transaction, decoded)?;
+                }
                 Ok(None)
             }
         } else {
             // This is the first Item in the flow we see
-            let flow_id = packet.flow_id;
-            let mut v = Vec::with_capacity(packet.packets_in_flow);
-            v.push(packet.to_owned());
-            let state = TVar::new(v);
-
-            let mut hm = bucket.read(transaction)?;
-            // must do this test since we did an atomic read before and with many threads it can
-            // happen that we construct data races :facepalm:
-            if hm.contains_key(&flow_id) {
-                return Err(StmError::Retry);
-            }
-
-            hm.insert(flow_id, state);
-            bucket.write(transaction, hm)?;
-
-            // it can by definition never happen that this branch will complete a flow, so that's
-            // it for this one
-            Ok(None)
+            start_flow(packet, state, bucket, now, transaction)
         }
     } else {
         // this is the only packet
-        assert!(packet.fragment_id == 0);
-
         Ok(Some(DecodedFlow {
             flow_id: packet.flow_id,
             data: packet.data.clone(),
@@ -97,6 +213,41 @@ pub fn decode_packet(
     }
 }
 
+/// Adds a new incomplete flow with the `packet` as first fragment to its `bucket`.
+fn start_flow(
+    packet: &Packet,
+    state: &StmDecoderState,
+    bucket: &TVar<HashMap<usize, TVar<PendingFlow>>>,
+    now: u64,
+    transaction: &mut Transaction,
+) -> StmResult<Option<DecodedFlow>> {
+    let flow_id = packet.flow_id;
+    let mut v = Vec::with_capacity(packet.packets_in_flow);
+    v.push(packet.to_owned());
+    let flow = TVar::new(PendingFlow {
+        last_seen: now,
+        fragments: v,
+        dropped: false,
+    });
+
+    let mut hm = bucket.read(transaction)?;
+    // must do this test since we did an atomic read before and with many threads it can
+    // happen that we construct data races :facepalm:
+    if hm.contains_key(&flow_id) {
+        return Err(StmError::Retry);
+    }
+
+    hm.insert(flow_id, flow);
+    if !state.policy.is_unbounded() {
+        state.evict(&mut hm, now, transaction)?;
+    }
+    bucket.write(transaction, hm)?;
+
+    // it can by definition never happen that this branch will complete a flow, so that's
+    // it for this one
+    Ok(None)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -127,7 +278,7 @@ mod tests {
             data: "o".into(),
         };
 
-        let dec = StmDecoderState::new();
+        let dec = StmDecoderState::new(16);
 
         assert_eq!(atomically(|trans| decode_packet(&inp1, &dec, trans)), None);
         assert_eq!(atomically(|trans| decode_packet(&inp2, &dec, trans)), None);
@@ -139,4 +290,80 @@ mod tests {
             })
         );
     }
+
+    fn fragment(flow_id: usize, fragment_id: usize, packets_in_flow: usize, data: &str) -> Packet {
+        Packet {
+            flow_id,
+            fragment_id,
+            packets_in_flow,
+            length: data.len(),
+            data: data.into(),
+        }
+    }
+
+    #[test]
+    fn eviction() {
+        // a single bucket, so that every new flow checks all others
+        let dec = StmDecoderState::with_policy(
+            1,
+            EvictionPolicy {
+                timeout: Some(3),
+                max_bytes: Some(4),
+            },
+            ReassemblyPolicy::default(),
+        );
+        let decode = |p: Packet| {
+            dec.tick();
+            atomically(|trans| decode_packet(&p, &dec, trans))
+        };
+
+        assert_eq!(decode(fragment(1, 0, 2, "aa")), None);
+        assert_eq!(decode(fragment(2, 0, 2, "bb")), None);
+        // 6 bytes, flow 1 is the least recently updated one
+        assert_eq!(decode(fragment(3, 0, 2, "cc")), None);
+        assert_eq!(dec.stats.read_atomic().evicted, vec![1]);
+
+        assert_eq!(decode(fragment(3, 1, 2, "c")).unwrap().data, b"ccc");
+        // flow 2 has not been updated for 3 packets
+        assert_eq!(decode(fragment(4, 0, 2, "d")), None);
+        assert_eq!(dec.stats.read_atomic().evicted, vec![1, 2]);
+
+        // late fragments start the flow again
+        assert_eq!(decode(fragment(2, 1, 2, "b")), None);
+        dec.flush();
+        let mut evicted = dec.stats.read_atomic().evicted;
+        evicted.sort_unstable();
+        assert_eq!(evicted, vec![1, 2, 2, 4]);
+        assert!(atomically(|trans| dec.fragments_map.is_empty(trans)));
+    }
+
+    #[test]
+    fn reassembly_anomalies() {
+        for &(policy, expected) in &[
+            (ReassemblyPolicy::FirstWins, &b"abcd"[..]),
+            (ReassemblyPolicy::LastWins, b"xyzcd"),
+        ] {
+            let dec = StmDecoderState::with_policy(4, EvictionPolicy::default(), policy);
+            let decode = |p: Packet| atomically(|trans| decode_packet(&p, &dec, trans));
+
+            assert_eq!(decode(fragment(1, 0, 3, "ab")), None);
+            assert_eq!(decode(fragment(1, 0, 3, "ab")), None);
+            assert_eq!(decode(fragment(1, 0, 3, "xyz")), None);
+            assert_eq!(decode(fragment(1, 3, 3, "c")), None);
+            assert_eq!(decode(fragment(1, 1, 4, "c")), None);
+            assert_eq!(decode(fragment(2, 1, 1, "c")), None);
+            assert_eq!(decode(fragment(1, 1, 3, "c")), None);
+            assert_eq!(decode(fragment(1, 2, 3, "d")).unwrap().data, expected);
+
+            assert_eq!(
+                dec.stats.read_atomic(),
+                DecoderStats {
+                    evicted: Vec::new(),
+                    duplicates: 1,
+                    overlaps: 1,
+                    malformed: 3,
+                }
+            );
+        }
+    }
 }
@@ -0,0 +1,188 @@
+//! A multi-pattern matcher after Aho and Corasick.
+//!
+//! The patterns are compiled into a deterministic automaton over byte classes, so scanning a
+//! flow takes a single table lookup per byte, independent of the number of patterns. Matching
+//! ignores the (ASCII) case of the patterns and the scanned data, case sensitive patterns have
+//! to be verified by the caller.
+
+use std::collections::{BTreeMap, VecDeque};
+
+const ROOT: u32 = 0;
+
+#[derive(Clone, Debug)]
+pub struct Automaton {
+    /// Maps every (lowercased) byte to its class. All bytes not occurring in any pattern share
+    /// class 0.
+    classes: [u8; 256],
+    class_count: usize,
+    /// Transition table, `class_count` entries per state
+    transitions: Vec<u32>,
+    /// Indices of the patterns ending in a state (including those ending in suffixes of the
+    /// state) in ascending order, the ones of state `s` are stored at
+    /// `outputs[output_start[s]..output_start[s + 1]]`
+    output_start: Vec<u32>,
+    outputs: Vec<u32>,
+}
+
+impl Automaton {
+    pub fn new<S: AsRef<[u8]>>(patterns: &[S]) -> Self {
+        // byte classes
+        let mut classes = [0u8; 256];
+        let mut class_count = 1;
+        for pattern in patterns {
+            for b in pattern.as_ref() {
+                let b = b.to_ascii_lowercase() as usize;
+                if classes[b] == 0 {
+                    classes[b] = class_count as u8;
+                    class_count += 1;
+                }
+            }
+        }
+        for b in b'A'..=b'Z' {
+            classes[b as usize] = classes[b.to_ascii_lowercase() as usize];
+        }
+
+        // trie of the patterns
+        let mut trie: Vec<BTreeMap<u8, u32>> = vec![BTreeMap::new()];
+        let mut matches: Vec<Vec<u32>> = vec![Vec::new()];
+        for (idx, pattern) in patterns.iter().enumerate() {
+            let mut state = ROOT;
+            for b in pattern.as_ref() {
+                let class = classes[*b as usize];
+                let next = trie.len() as u32;
+                state = *trie[state as usize].entry(class).or_insert(next);
+                if state == next {
+                    trie.push(BTreeMap::new());
+                    matches.push(Vec::new());
+                }
+            }
+            matches[state as usize].push(idx as u32);
+        }
+
+        // fill in the missing transitions along the failure links, breadth first so that the
+        // transitions of the failure state are complete when a state is visited
+        let mut transitions = vec![ROOT; trie.len() * class_count];
+        let mut queue = VecDeque::new();
+        for (&class, &next) in &trie[ROOT as usize] {
+            transitions[class as usize] = next;
+            // failure link of depth 1 states is the root
+            queue.push_back((next, ROOT));
+        }
+
+        while let Some((state, fail)) = queue.pop_front() {
+            let (s, f) = (state as usize, fail as usize);
+            let inherited = matches[f].clone();
+            matches[s].extend(inherited);
+            matches[s].sort_unstable();
+
+            for class in 0..class_count {
+                transitions[s * class_count + class] = transitions[f * class_count + class];
+            }
+            for (&class, &next) in &trie[s] {
+                let class = class as usize;
+                // the failure link of the successor follows the same class from our failure state
+                queue.push_back((next, transitions[f * class_count + class]));
+                transitions[s * class_count + class] = next;
+            }
+        }
+
+        let mut output_start = Vec::with_capacity(matches.len() + 1);
+        let mut outputs = Vec::new();
+        for m in matches {
+            output_start.push(outputs.len() as u32);
+            outputs.extend(m);
+        }
+        output_start.push(outputs.len() as u32);
+
+        Automaton {
+            classes,
+            class_count,
+            transitions,
+            output_start,
+            outputs,
+        }
+    }
+
+    /// Calls `f` with the index and the end position of every pattern occurrence in `haystack`,
+    /// ordered by end position and pattern index, until `f` returns `false`.
+    pub fn for_each_match<F: FnMut(usize, usize) -> bool>(&self, haystack: &[u8], mut f: F) {
+        let mut state = ROOT as usize;
+        for end in 0..=haystack.len() {
+            if end > 0 {
+                let class = self.classes[haystack[end - 1] as usize] as usize;
+                state = self.transitions[state * self.class_count + class] as usize;
+            }
+
+            let outputs = self.output_start[state] as usize..self.output_start[state + 1] as usize;
+            for &pattern in &self.outputs[outputs] {
+                if !f(pattern as usize, end) {
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Returns the index of a pattern occurring in `haystack`, the first one (in pattern order)
+    /// among those ending at the earliest position.
+    pub fn find(&self, haystack: &[u8]) -> Option<usize> {
+        let mut found = None;
+        self.for_each_match(haystack, |pattern, _| {
+            found = Some(pattern);
+            false
+        });
+
+        found
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_overlapping_patterns() {
+        let ac = Automaton::new(&["he", "she", "his", "hers"]);
+        assert_eq!(ac.find(b"ushers"), Some(0));
+        assert_eq!(ac.find(b"sh"), None);
+        assert_eq!(ac.find(b"ahis"), Some(2));
+        assert_eq!(ac.find(b"hxrs"), None);
+        assert_eq!(ac.find(b""), None);
+    }
+
+    #[test]
+    fn reports_all_occurrences() {
+        let ac = Automaton::new(&["he", "she", "his", "hers", "e"]);
+        let mut found = Vec::new();
+        ac.for_each_match(b"ushers", |pattern, end| {
+            found.push((pattern, end));
+            true
+        });
+
+        assert_eq!(found, vec![(0, 4), (1, 4), (4, 4), (3, 6)]);
+    }
+
+    #[test]
+    fn ignores_case() {
+        let ac = Automaton::new(&["About", "time"]);
+        assert_eq!(ac.find(b"what ABOUT it"), Some(0));
+        assert_eq!(ac.find(b"TiMe"), Some(1));
+        assert_eq!(ac.find(b"abou"), None);
+    }
+
+    #[test]
+    fn agrees_with_scan() {
+        let patterns = ["abab", "bab", "aab", "ba"];
+        let ac = Automaton::new(&patterns);
+
+        // all strings over {a, b} up to length 6
+        for len in 0..=6 {
+            for bits in 0..(1 << len) {
+                let s: String = (0..len)
+                    .map(|i| if bits >> i & 1 == 1 { 'b' } else { 'a' })
+                    .collect();
+                let expected = patterns.iter().any(|p| s.contains(p));
+                assert_eq!(ac.find(s.as_bytes()).is_some(), expected, "{}", s);
+            }
+        }
+    }
+}
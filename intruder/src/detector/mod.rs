@@ -0,0 +1,235 @@
+use crate::decoder::DecodedFlow;
+use std::borrow::Cow;
+use std::sync::Arc;
+use strum_macros::{Display, EnumString};
+
+mod aho_corasick;
+mod normalize;
+mod rules;
+
+pub use aho_corasick::Automaton;
+pub use normalize::normalize;
+pub use rules::{Rule, RuleSet};
+
+/// the wordlist the attacks are taken from
+pub static ATTACK_WORDLIST: &[&str] = &[
+    "about", "after", "all", "also", "and", "any", "back", "because", "but", "can", "come",
+    "could", "day", "even", "first", "for", "from", "get", "give", "good", "have", "him", "how",
+    "into", "its", "just", "know", "like", "look", "make", "most", "new", "not", "now", "one",
+    "only", "other", "out", "over", "people", "say", "see", "she", "some", "take", "than", "that",
+    "their", "them", "then", "there", "these", "they", "think", "this", "time", "two", "use",
+    "want", "way", "well", "what", "when", "which", "who", "will", "with", "work", "would", "year",
+    "your",
+];
+
+#[derive(Debug, PartialEq)]
+pub enum DetectorResult {
+    /// The flow contains the signature of the rule with the given id
+    SignatureMatch(u32),
+    Clear,
+}
+
+/// Reported for a flow that contains the signature of a rule.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Alert {
+    pub flow_id: usize,
+    pub rule_id: u32,
+}
+
+/// The algorithm used to match the signatures against a flow.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Display, EnumString)]
+#[strum(serialize_all = "kebab-case")]
+pub enum Engine {
+    /// Searches the flow for every signature separately, like the original STAMP code
+    Scan,
+    /// Matches all signatures in a single pass with an Aho-Corasick automaton
+    AhoCorasick,
+}
+
+/// The rules compiled for one of the engines. A detector is built once before the benchmark
+/// and shared read-only between all threads.
+///
+/// Both engines report the first rule of the set that occurs in a flow.
+#[derive(Clone, Debug)]
+pub struct Detector {
+    matcher: Matcher,
+    /// Whether escape sequences are decoded before matching
+    normalize: bool,
+}
+
+#[derive(Clone, Debug)]
+enum Matcher {
+    Scan(Vec<Rule>),
+    AhoCorasick(Box<Automaton>, Vec<Rule>),
+}
+
+impl Detector {
+    pub fn new(engine: Engine, rules: &RuleSet, normalize: bool) -> Self {
+        let rules = rules.rules.clone();
+        let matcher = match engine {
+            Engine::Scan => Matcher::Scan(rules),
+            Engine::AhoCorasick => {
+                let patterns: Vec<&[u8]> = rules.iter().map(|r| r.pattern.as_slice()).collect();
+                Matcher::AhoCorasick(Box::new(Automaton::new(&patterns)), rules)
+            }
+        };
+
+        Detector { matcher, normalize }
+    }
+
+    pub fn engine(&self) -> Engine {
+        match self.matcher {
+            Matcher::Scan(_) => Engine::Scan,
+            Matcher::AhoCorasick(..) => Engine::AhoCorasick,
+        }
+    }
+
+    pub fn detect(&self, flow: &[u8]) -> DetectorResult {
+        // run preprocessing
+        let flow = if self.normalize {
+            normalize(flow)
+        } else {
+            Cow::Borrowed(flow)
+        };
+        let flow = flow.as_ref();
+
+        // run the signature detection
+        let found = match &self.matcher {
+            Matcher::Scan(rules) => rules.iter().find(|r| r.occurs_in(flow)),
+            Matcher::AhoCorasick(automaton, rules) => {
+                // the automaton ignores case, so matches of case sensitive rules are verified
+                let mut first: Option<usize> = None;
+                automaton.for_each_match(flow, |idx, end| {
+                    if !matches!(first, Some(f) if f <= idx) && rules[idx].matches_at(flow, end) {
+                        first = Some(idx);
+                    }
+                    first != Some(0)
+                });
+                first.map(|idx| &rules[idx])
+            }
+        };
+
+        match found {
+            Some(rule) => DetectorResult::SignatureMatch(rule.id),
+            None => DetectorResult::Clear,
+        }
+    }
+}
+
+pub fn bind_detect(flow: Option<DecodedFlow>, detector: Arc<Detector>) -> Option<Alert> {
+    flow.and_then(|decoded_flow| {
+        if let DetectorResult::SignatureMatch(rule_id) = detector.detect(&decoded_flow.data) {
+            Some(Alert {
+                flow_id: decoded_flow.flow_id,
+                rule_id,
+            })
+        } else {
+            None
+        }
+    })
+}
+
+pub struct AttackDetector {
+    detector: Arc<Detector>,
+    attacks: Vec<Alert>,
+}
+
+impl AttackDetector {
+    pub fn new(detector: Arc<Detector>) -> Self {
+        AttackDetector {
+            detector,
+            attacks: Vec::new(),
+        }
+    }
+
+    pub fn detect(&mut self, decoded: Option<DecodedFlow>) {
+        match bind_detect(decoded, self.detector.clone()) {
+            Some(alert) => self.attacks.push(alert),
+            _ => ()
+        }
+    }
+
+    pub fn get_attacks(self) -> Vec<Alert> {
+        self.attacks
+    }
+}
+
+pub fn get_attacks(detected:Vec<Option<Alert>>) -> Vec<Alert> {
+    let mut rs = Vec::new();
+    for d in detected {
+        match d {
+            Some(a) => rs.push(a),
+            _ => ()
+        }
+    }
+    rs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn detect(flow: &str) -> DetectorResult {
+        Detector::new(Engine::Scan, &RuleSet::default(), true).detect(flow.as_bytes())
+    }
+
+    #[test]
+    fn simple_recognition() {
+        assert_eq!(detect("test"), DetectorResult::Clear);
+        assert_eq!(detect("abouts"), DetectorResult::SignatureMatch(1));
+    }
+
+    #[test]
+    fn capitalization_normalization() {
+        assert_eq!(detect("aBoUt"), DetectorResult::SignatureMatch(1));
+    }
+
+    #[test]
+    fn engines_agree() {
+        let rules = RuleSet::parse(
+            "10 \"Cmd\"\n\
+             20 nocase \"exe\"\n\
+             30 \"|00 ff|\"\n\
+             40 nocase \"cmd.exe\"\n",
+        )
+        .unwrap();
+        let scan = Detector::new(Engine::Scan, &rules, true);
+        let aho_corasick = Detector::new(Engine::AhoCorasick, &rules, true);
+
+        for (flow, expected) in &[
+            (&b"Cmd"[..], DetectorResult::SignatureMatch(10)),
+            (b"cmd", DetectorResult::Clear),
+            (b"CMD.EXE", DetectorResult::SignatureMatch(20)),
+            // the first rule of the set wins, not the first occurrence
+            (b"cmd.exe Cmd", DetectorResult::SignatureMatch(10)),
+            (b"a\xff", DetectorResult::Clear),
+            (b"\x01\x00\xff\x02", DetectorResult::SignatureMatch(30)),
+            (b"\x00\xfe", DetectorResult::Clear),
+            (b"", DetectorResult::Clear),
+        ] {
+            assert_eq!(&scan.detect(flow), expected, "{:?}", flow);
+            assert_eq!(&aho_corasick.detect(flow), expected, "{:?}", flow);
+        }
+
+        let default_scan = Detector::new(Engine::Scan, &RuleSet::default(), true);
+        let default_aho_corasick = Detector::new(Engine::AhoCorasick, &RuleSet::default(), true);
+        for flow in &["test", "abouts", "aBoUt", "xyz THEIR", "n o t", "", "ouryou"] {
+            assert_eq!(
+                default_scan.detect(flow.as_bytes()),
+                default_aho_corasick.detect(flow.as_bytes()),
+                "{}",
+                flow
+            );
+        }
+    }
+
+    #[test]
+    fn escape_sequence_normalization() {
+        assert_eq!(detect("%41Bout"), DetectorResult::SignatureMatch(1));
+        assert_eq!(detect("&#97;b\\x6fut"), DetectorResult::SignatureMatch(1));
+
+        let raw = Detector::new(Engine::AhoCorasick, &RuleSet::default(), false);
+        assert_eq!(raw.detect(b"%41bo%75t"), DetectorResult::Clear);
+        assert_eq!(detect("%41bo%75t"), DetectorResult::SignatureMatch(1));
+    }
+}
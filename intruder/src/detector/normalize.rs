@@ -0,0 +1,167 @@
+//! Decoding of escape sequences before the signatures are matched, so that encoded attacks are
+//! found as well.
+//!
+//! The following sequences are decoded in a single pass (so doubly encoded data stays encoded
+//! once):
+//!
+//! - URL percent-encoding: `%HH` and `%uHHHH`
+//! - HTML entities: `&amp;`, `&lt;`, `&gt;`, `&quot;`, `&apos;`, `&#DD;` and `&#xHH;`
+//! - backslash escapes: `\xHH`, `\uHHHH`, `\n`, `\r`, `\t`, `\0`, `\\`, `\'` and `\"`
+//!
+//! Characters are encoded as UTF-8. Malformed sequences are kept as they are.
+
+use std::borrow::Cow;
+
+/// Returns `flow` with all escape sequences decoded.
+pub fn normalize(flow: &[u8]) -> Cow<'_, [u8]> {
+    if !flow.iter().any(|b| matches!(b, b'%' | b'&' | b'\\')) {
+        return Cow::Borrowed(flow);
+    }
+
+    let mut out = Vec::with_capacity(flow.len());
+    let mut pos = 0;
+    while pos < flow.len() {
+        let decoded = match flow[pos] {
+            b'%' => percent(&flow[pos + 1..]),
+            b'&' => entity(&flow[pos + 1..]),
+            b'\\' => backslash(&flow[pos + 1..]),
+            _ => None,
+        };
+
+        match decoded {
+            Some((decoded, consumed)) => {
+                push_char(&mut out, decoded);
+                pos += 1 + consumed;
+            }
+            None => {
+                out.push(flow[pos]);
+                pos += 1;
+            }
+        }
+    }
+
+    Cow::Owned(out)
+}
+
+/// A decoded sequence: either a raw byte or a character.
+enum Decoded {
+    Byte(u8),
+    Char(char),
+}
+
+fn push_char(out: &mut Vec<u8>, decoded: Decoded) {
+    match decoded {
+        Decoded::Byte(b) => out.push(b),
+        Decoded::Char(c) => {
+            let mut buf = [0; 4];
+            out.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+        }
+    }
+}
+
+/// Parses exactly `digits` hexadecimal digits from the start of `s`.
+fn hex(s: &[u8], digits: usize) -> Option<u32> {
+    let s = s.get(..digits)?;
+    if !s.iter().all(u8::is_ascii_hexdigit) {
+        return None;
+    }
+    u32::from_str_radix(std::str::from_utf8(s).ok()?, 16).ok()
+}
+
+/// Decodes the sequence following a `%`. Returns the result and the number of bytes consumed.
+fn percent(s: &[u8]) -> Option<(Decoded, usize)> {
+    if let Some(b'u') | Some(b'U') = s.first() {
+        let c = std::char::from_u32(hex(&s[1..], 4)?)?;
+        return Some((Decoded::Char(c), 5));
+    }
+
+    Some((Decoded::Byte(hex(s, 2)? as u8), 2))
+}
+
+/// Decodes the sequence following a `&`.
+fn entity(s: &[u8]) -> Option<(Decoded, usize)> {
+    // entities are short, longer references are not considered
+    let end = s.iter().take(10).position(|&b| b == b';')?;
+    let name = &s[..end];
+
+    let decoded = match name {
+        b"amp" => Decoded::Byte(b'&'),
+        b"lt" => Decoded::Byte(b'<'),
+        b"gt" => Decoded::Byte(b'>'),
+        b"quot" => Decoded::Byte(b'"'),
+        b"apos" => Decoded::Byte(b'\''),
+        [b'#', b'x', digits @ ..] | [b'#', b'X', digits @ ..] if !digits.is_empty() => {
+            Decoded::Char(std::char::from_u32(hex(digits, digits.len())?)?)
+        }
+        [b'#', digits @ ..] if !digits.is_empty() && digits.iter().all(u8::is_ascii_digit) => {
+            let code = std::str::from_utf8(digits).ok()?.parse::<u32>().ok()?;
+            Decoded::Char(std::char::from_u32(code)?)
+        }
+        _ => return None,
+    };
+
+    Some((decoded, end + 1))
+}
+
+/// Decodes the sequence following a `\`.
+fn backslash(s: &[u8]) -> Option<(Decoded, usize)> {
+    let decoded = match s.first()? {
+        b'x' => return Some((Decoded::Byte(hex(&s[1..], 2)? as u8), 3)),
+        b'u' => return Some((Decoded::Char(std::char::from_u32(hex(&s[1..], 4)?)?), 5)),
+        b'n' => b'\n',
+        b'r' => b'\r',
+        b't' => b'\t',
+        b'0' => 0,
+        b @ b'\\' | b @ b'\'' | b @ b'"' => *b,
+        _ => return None,
+    };
+
+    Some((Decoded::Byte(decoded), 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn norm(s: &str) -> String {
+        String::from_utf8(normalize(s.as_bytes()).into_owned()).unwrap()
+    }
+
+    #[test]
+    fn decode_sequences() {
+        assert_eq!(norm("%41bout%2f%u0041"), "About/A");
+        assert_eq!(norm("&lt;a&gt; &amp;&#97;&#x62;&#X63;"), "<a> &abc");
+        assert_eq!(norm(r#"\x61b\n\t\\\"\'"#), "ab\n\t\\\"'");
+        assert_eq!(norm("caf&#233;"), "café");
+    }
+
+    #[test]
+    fn keep_malformed_sequences() {
+        for s in &[
+            "100%",
+            "%4",
+            "%zz",
+            "%u12",
+            "a & b",
+            "&unknown;",
+            "&#;",
+            "&#x;",
+            "\\q",
+            "\\x1",
+            "&#1114112;",
+        ] {
+            assert_eq!(&norm(s), s);
+        }
+    }
+
+    #[test]
+    fn single_pass() {
+        assert_eq!(norm("%2541"), "%41");
+        assert_eq!(norm("&amp;lt;"), "&lt;");
+    }
+
+    #[test]
+    fn borrow_without_sequences() {
+        assert!(matches!(normalize(b"plain text"), Cow::Borrowed(_)));
+    }
+}
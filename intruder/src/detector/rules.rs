@@ -0,0 +1,258 @@
+//! The signatures the detector looks for, optionally loaded from a rules file.
+//!
+//! A rules file contains one rule per line, empty lines and lines starting with `#` are
+//! ignored:
+//!
+//! ```text
+//! # <id> [nocase] "<content>"
+//! 1001 nocase "about"
+//! 1002 "GET |2f|etc|2f|passwd"
+//! 1003 "|de ad be ef|"
+//! ```
+//!
+//! The rule id is an unsigned integer and must be unique. Rules match case sensitively unless
+//! the `nocase` flag (ASCII case folding) is given. Within the quoted content, bytes can be given
+//! in hexadecimal between two `|`, and `\"`, `\|` and `\\` escape the respective character.
+
+use super::ATTACK_WORDLIST;
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Rule {
+    pub id: u32,
+    pub pattern: Vec<u8>,
+    pub case_sensitive: bool,
+}
+
+impl Rule {
+    /// Checks whether the pattern occurs at the end of `haystack[..end]`.
+    pub fn matches_at(&self, haystack: &[u8], end: usize) -> bool {
+        end >= self.pattern.len() && {
+            let candidate = &haystack[end - self.pattern.len()..end];
+            if self.case_sensitive {
+                candidate == self.pattern.as_slice()
+            } else {
+                candidate.eq_ignore_ascii_case(&self.pattern)
+            }
+        }
+    }
+
+    /// Checks whether the pattern occurs anywhere in `haystack`.
+    pub fn occurs_in(&self, haystack: &[u8]) -> bool {
+        (self.pattern.len()..=haystack.len()).any(|end| self.matches_at(haystack, end))
+    }
+}
+
+/// An ordered set of rules. If several rules match a flow, the first one is reported.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RuleSet {
+    pub rules: Vec<Rule>,
+}
+
+impl Default for RuleSet {
+    /// The `ATTACK_WORDLIST` from STAMP, with the ids 1, 2, ... and case insensitive matching.
+    fn default() -> Self {
+        RuleSet {
+            rules: ATTACK_WORDLIST
+                .iter()
+                .enumerate()
+                .map(|(idx, word)| Rule {
+                    id: idx as u32 + 1,
+                    pattern: word.as_bytes().to_vec(),
+                    case_sensitive: false,
+                })
+                .collect(),
+        }
+    }
+}
+
+impl RuleSet {
+    pub fn read_file<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let contents = fs::read_to_string(path)?;
+        RuleSet::parse(&contents).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn parse(contents: &str) -> Result<Self, String> {
+        let mut rules = Vec::new();
+        let mut ids = HashSet::new();
+
+        for (idx, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let rule = parse_rule(line).map_err(|e| format!("line {}: {}", idx + 1, e))?;
+            if !ids.insert(rule.id) {
+                return Err(format!("line {}: duplicate rule id {}", idx + 1, rule.id));
+            }
+            rules.push(rule);
+        }
+
+        if rules.is_empty() {
+            return Err("no rules found".into());
+        }
+
+        Ok(RuleSet { rules })
+    }
+
+    pub fn len(&self) -> usize {
+        self.rules.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+}
+
+fn parse_rule(line: &str) -> Result<Rule, String> {
+    let (id, rest) = split_token(line);
+    let id = id
+        .parse::<u32>()
+        .map_err(|_| format!("invalid rule id `{}`", id))?;
+
+    let (flag, content) = if rest.starts_with('"') {
+        ("", rest)
+    } else {
+        split_token(rest)
+    };
+    let case_sensitive = match flag {
+        "" => true,
+        "nocase" => false,
+        f => return Err(format!("unknown flag `{}`", f)),
+    };
+
+    let pattern = parse_content(content)?;
+    if pattern.is_empty() {
+        return Err("empty content".into());
+    }
+
+    Ok(Rule {
+        id,
+        pattern,
+        case_sensitive,
+    })
+}
+
+/// Splits off the first whitespace separated token.
+fn split_token(s: &str) -> (&str, &str) {
+    match s.find(char::is_whitespace) {
+        Some(pos) => (&s[..pos], s[pos..].trim_start()),
+        None => (s, ""),
+    }
+}
+
+/// Parses a quoted content with hexadecimal sections into its bytes.
+fn parse_content(content: &str) -> Result<Vec<u8>, String> {
+    if content.len() < 2 || !content.starts_with('"') || !content.ends_with('"') {
+        return Err("the content must be enclosed in double quotes".into());
+    }
+
+    let mut pattern = Vec::new();
+    let mut chars = content[1..content.len() - 1].chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(e @ '"') | Some(e @ '|') | Some(e @ '\\') => pattern.push(e as u8),
+                Some(e) => return Err(format!("unknown escape sequence `\\{}`", e)),
+                None => return Err("unterminated escape sequence".into()),
+            },
+            '"' => return Err("unescaped `\"` in the content".into()),
+            '|' => {
+                let mut hex = String::new();
+                loop {
+                    match chars.next() {
+                        Some('|') => break,
+                        Some(h) => hex.push(h),
+                        None => return Err("unterminated hex section".into()),
+                    }
+                }
+                for byte in hex.split_whitespace() {
+                    let b = u8::from_str_radix(byte, 16)
+                        .ok()
+                        .filter(|_| byte.len() == 2)
+                        .ok_or_else(|| format!("invalid hex byte `{}`", byte))?;
+                    pattern.push(b);
+                }
+            }
+            c => {
+                let mut buf = [0; 4];
+                pattern.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+            }
+        }
+    }
+
+    Ok(pattern)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_rules() {
+        let rules = RuleSet::parse(
+            "# comment\n\
+             \n\
+             7 nocase \"about\"\n\
+             8 \"GET |2f|etc|2F 70|asswd\"\n\
+             9   \"a \\\"quoted\\\" \\| pipe\"\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            rules.rules,
+            vec![
+                Rule {
+                    id: 7,
+                    pattern: b"about".to_vec(),
+                    case_sensitive: false
+                },
+                Rule {
+                    id: 8,
+                    pattern: b"GET /etc/passwd".to_vec(),
+                    case_sensitive: true
+                },
+                Rule {
+                    id: 9,
+                    pattern: b"a \"quoted\" | pipe".to_vec(),
+                    case_sensitive: true
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn reject_invalid_rules() {
+        for invalid in &[
+            "x \"a\"",
+            "1 \"a\"\n1 \"b\"",
+            "1 upcase \"a\"",
+            "1 about",
+            "1 \"\"",
+            "1 \"|4|\"",
+            "1 \"|41\"",
+            "1 \"\\n\"",
+            "# nothing",
+        ] {
+            assert!(RuleSet::parse(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn case_sensitivity() {
+        let rule = |case_sensitive| Rule {
+            id: 1,
+            pattern: b"Cmd".to_vec(),
+            case_sensitive,
+        };
+
+        assert!(rule(true).occurs_in(b"run Cmd"));
+        assert!(!rule(true).occurs_in(b"run cmd"));
+        assert!(rule(false).occurs_in(b"run CMD"));
+        assert!(!rule(false).occurs_in(b"Cm"));
+    }
+}
@@ -1,6 +1,8 @@
 use crate::decoder::stm_decoder::{decode_packet, StmDecoderState};
-use crate::detector::{detect, DetectorResult};
+use crate::decoder::{DecoderStats, EvictionPolicy, ReassemblyPolicy};
+use crate::detector::{Alert, Detector, DetectorResult};
 use crate::*;
+use std::sync::Arc;
 use std::thread::{self, JoinHandle};
 use ::stm::{atomically, det_atomically, dtm, freeze, DTMHandle};
 
@@ -19,8 +21,9 @@ use ::stm::{atomically, det_atomically, dtm, freeze, DTMHandle};
 fn analyze_stream(
     packet: Packet,
     decoder_state: StmDecoderState,
+    detector: Arc<Detector>,
     handle: DTMHandle,
-) -> Option<usize> {
+) -> Option<Alert> {
     let decoder_result = det_atomically(handle, |trans| {
         decode_packet(&packet, &decoder_state, trans)
         //let mut ds = Vec::new();
@@ -37,9 +40,12 @@ fn analyze_stream(
     //for decoder_result in decoder_results {
     if let Some(decoded_flow) = decoder_result {
         // process the output -> run the detector
-        if detect(&decoded_flow.data) == DetectorResult::SignatureMatch {
+        if let DetectorResult::SignatureMatch(rule_id) = detector.detect(&decoded_flow.data) {
             //found_attacks.push(decoded_flow.flow_id);
-            return Some(decoded_flow.flow_id);
+            return Some(Alert {
+                flow_id: decoded_flow.flow_id,
+                rule_id,
+            });
         }
     }
 
@@ -49,9 +55,18 @@ fn analyze_stream(
     //found_attacks
 }
 
-pub fn run_eval(packets: Vec<Packet>, threadcount: usize) -> Vec<usize> {
+/// Returns the alerts for the flows that contained an attack and the statistics of the
+/// decoder, including the incomplete flows that have been evicted according to the `policy` or
+/// because they were still incomplete at the end of the stream.
+pub fn run_eval(
+    packets: Vec<Packet>,
+    threadcount: usize,
+    detector: Arc<Detector>,
+    policy: EvictionPolicy,
+    reassembly: ReassemblyPolicy,
+) -> (Vec<Alert>, DecoderStats) {
     let mut found_attacks = Vec::new();
-    let decoder_state = StmDecoderState::new(threadcount);
+    let decoder_state = StmDecoderState::with_policy(threadcount, policy, reassembly);
 
     // TODO(feliix42): This could be improved by processing multiple elements in a single Tx. But
     // that would already be an optimization.
@@ -69,8 +84,10 @@ pub fn run_eval(packets: Vec<Packet>, threadcount: usize) -> Vec<usize> {
         let mut threads = Vec::with_capacity(threadcount);
         for item in work {
             let ds = decoder_state.clone();
+            let d = detector.clone();
             let (packet, handle) = item;
-            threads.push(thread::spawn(move || analyze_stream(packet, ds, handle)));
+            ds.tick();
+            threads.push(thread::spawn(move || analyze_stream(packet, ds, d, handle)));
         }
 
         // collect work
@@ -85,9 +102,10 @@ pub fn run_eval(packets: Vec<Packet>, threadcount: usize) -> Vec<usize> {
     }
 
     // State verification
+    decoder_state.flush();
     assert!(atomically(|trans3| decoder_state
         .fragments_map
         .is_empty(trans3)));
 
-    found_attacks
+    (found_attacks, decoder_state.stats.read_atomic())
 }
@@ -1,29 +1,34 @@
-use crate::detector::{DetectorResult, ATTACK_WORDLIST};
+use crate::decoder::{DecoderStats, ReassemblyPolicy};
+use crate::detector::{Detector, DetectorResult, Engine, RuleSet};
 use rand_chacha::rand_core::{RngCore, SeedableRng};
 use rand_chacha::ChaCha12Rng;
-use std::collections::{HashSet, VecDeque};
-use std::iter::FromIterator;
+use std::collections::{BTreeMap, HashSet, VecDeque};
 
-mod detector;
-mod decoder; 
+pub mod decoder;
+
+pub mod detector;
 
 pub mod seq;
 pub mod stm;
 pub mod dstm;
+pub mod sharded;
 pub mod ohua;
 pub mod generated;
+pub mod pcap;
+pub mod stream;
+pub mod accuracy;
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct Packet {
     pub flow_id: usize,
     pub fragment_id: usize,
     pub packets_in_flow: usize,
     pub length: usize,
-    pub data: String,
+    pub data: Vec<u8>,
 }
 
 fn split_into_packets(
-    mut flow: String,
+    mut flow: Vec<u8>,
     flow_number: usize,
     rng: &mut ChaCha12Rng,
 ) -> VecDeque<Packet> {
@@ -62,14 +67,25 @@ fn split_into_packets(
 /// `attack_percentage` determines roughly he percentage of attacks that are to
 /// be generated. This may vary based on the `seed` used for the PRNG. The
 /// `maximal_packet_len` determines, how long a non-attack flow may become.
+///
+/// Attacks are drawn from the patterns of the `rules`. The `obfuscation_percentage` of the
+/// attacks is hidden with escape sequences (see `obfuscate`). Non-attack flows consist of
+/// printable ASCII characters, or of arbitrary bytes if `binary` is set.
 pub fn generate_stream(
     flowcount: usize,
     attack_percentage: u8,
     max_packet_len: u64,
     seed: u64,
+    rules: &RuleSet,
+    obfuscation_percentage: u8,
+    binary: bool,
 ) -> (VecDeque<Packet>, HashSet<usize>) {
     // this is just asserted to be safe and b/c this is outside the benchmark itself
     assert!(attack_percentage <= 100);
+    assert!(obfuscation_percentage <= 100);
+
+    let attack_patterns: Vec<&[u8]> = rules.rules.iter().map(|r| r.pattern.as_slice()).collect();
+    let reference = Detector::new(Engine::Scan, rules, true);
 
     // initialize the PRNG
     let mut rng = ChaCha12Rng::seed_from_u64(seed);
@@ -87,18 +103,30 @@ pub fn generate_stream(
             // mark flow number as attacked
             attacks.insert(flow_number);
             // randomly choose a string from the set above
-            let pos = rng.next_u32() as usize % ATTACK_WORDLIST.len();
-            String::from(ATTACK_WORDLIST[pos])
+            let pos = rng.next_u32() as usize % attack_patterns.len();
+            // (no random numbers are drawn without obfuscation to keep the streams of a seed)
+            if obfuscation_percentage > 0
+                && (rng.next_u32() % 100) < obfuscation_percentage.into()
+            {
+                obfuscate(attack_patterns[pos], &mut rng)
+            } else {
+                attack_patterns[pos].to_vec()
+            }
         } else {
             // generate a random string
             let char_range = b'~' - b' ';
-            let generated = String::from_iter(
-                (0..max_packet_len)
-                    .map(|_| char::from((rng.next_u32() % char_range as u32) as u8 + b' ')),
-            );
+            let generated: Vec<u8> = (0..max_packet_len)
+                .map(|_| {
+                    if binary {
+                        rng.next_u32() as u8
+                    } else {
+                        (rng.next_u32() % char_range as u32) as u8 + b' '
+                    }
+                })
+                .collect();
 
             // check if an attack was generated
-            if detector::detect(&generated) == DetectorResult::SignatureMatch {
+            if let DetectorResult::SignatureMatch(_) = reference.detect(&generated) {
                 attacks.insert(flow_number);
             }
 
@@ -120,6 +148,195 @@ pub fn generate_stream(
     (stream, attacks)
 }
 
+/// Hides the `pattern` from a plain signature search by replacing random bytes (but at least
+/// one) with escape sequences: URL percent-encoding, decimal and hexadecimal HTML character
+/// references and backslash escapes. The characters `%`, `&` and `\` are always encoded, so
+/// that the normalized result equals the pattern. Bytes outside of ASCII are not encoded as
+/// HTML character references, which would decode to a (multi-byte) character.
+pub fn obfuscate(pattern: &[u8], rng: &mut ChaCha12Rng) -> Vec<u8> {
+    let forced = rng.next_u32() as usize % pattern.len().max(1);
+
+    let mut obfuscated = Vec::with_capacity(pattern.len() * 4);
+    for (idx, &b) in pattern.iter().enumerate() {
+        let special = b == b'%' || b == b'&' || b == b'\\';
+        let encoding = if idx == forced || special {
+            rng.next_u32() % 4 + 1
+        } else {
+            rng.next_u32() % 5
+        };
+
+        match encoding {
+            0 => obfuscated.push(b),
+            1 => obfuscated.extend(format!("%{:02x}", b).bytes()),
+            2 if b.is_ascii() => obfuscated.extend(format!("&#{};", b).bytes()),
+            3 if b.is_ascii() => obfuscated.extend(format!("&#x{:x};", b).bytes()),
+            2 => obfuscated.extend(format!("%{:02X}", b).bytes()),
+            _ => obfuscated.extend(format!("\\x{:02X}", b).bytes()),
+        }
+    }
+
+    obfuscated
+}
+
+/// Adds an anomalous packet to roughly `anomaly_percentage` of the flows in the `stream`: a
+/// duplicate of one of its fragments, a copy with different data (overlapping the original), or
+/// a malformed copy with an invalid fragment id or length.
+///
+/// Duplicates and overlapping copies are placed after the original fragment but before the
+/// flow is complete, malformed packets anywhere. The `attacks` are updated for the data the
+/// `reassembly` policy keeps. Returns the counters a decoder is expected to report when
+/// processing the packets in order.
+pub fn inject_anomalies(
+    stream: &mut VecDeque<Packet>,
+    attacks: &mut HashSet<usize>,
+    anomaly_percentage: u8,
+    reassembly: ReassemblyPolicy,
+    seed: u64,
+    rules: &RuleSet,
+) -> DecoderStats {
+    assert!(anomaly_percentage <= 100);
+    if anomaly_percentage == 0 {
+        return DecoderStats::default();
+    }
+
+    let reference = Detector::new(Engine::Scan, rules, true);
+    let mut rng = ChaCha12Rng::seed_from_u64(seed);
+    let mut expected = DecoderStats::default();
+
+    // the positions of the packets of every flow (ordered, so that the result is reproducible)
+    let mut positions: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+    for (pos, packet) in stream.iter().enumerate() {
+        positions.entry(packet.flow_id).or_default().push(pos);
+    }
+
+    // the anomalous packets and the position they are inserted at
+    let mut injected: Vec<(usize, Packet)> = Vec::new();
+    for (flow_id, pos) in &positions {
+        if (rng.next_u32() % 100) >= anomaly_percentage.into() {
+            continue;
+        }
+
+        let kind = rng.next_u32() % 3;
+        if kind < 2 && pos.len() > 1 {
+            // a copy of any fragment but the one completing the flow
+            let last = pos[pos.len() - 1];
+            let original = pos[rng.next_u32() as usize % (pos.len() - 1)];
+            let at = original + 1 + rng.next_u32() as usize % (last - original);
+            let mut copy = stream[original].clone();
+
+            if kind == 0 || copy.data.is_empty() {
+                expected.duplicates += 1;
+            } else {
+                expected.overlaps += 1;
+                let changed = rng.next_u32() as usize % copy.data.len();
+                copy.data[changed] = different_byte(copy.data[changed], &mut rng);
+
+                if reassembly == ReassemblyPolicy::LastWins {
+                    // the copy replaces the original fragment
+                    let mut fragments: Vec<&Packet> = pos.iter().map(|&p| &stream[p]).collect();
+                    fragments.sort_by_key(|p| p.fragment_id);
+                    let flow: Vec<u8> = fragments
+                        .iter()
+                        .map(|p| if p.fragment_id == copy.fragment_id { &copy } else { p })
+                        .flat_map(|p| p.data.iter().copied())
+                        .collect();
+                    if let DetectorResult::SignatureMatch(_) = reference.detect(&flow) {
+                        attacks.insert(*flow_id);
+                    } else {
+                        attacks.remove(flow_id);
+                    }
+                }
+            }
+
+            injected.push((at, copy));
+        } else {
+            expected.malformed += 1;
+            let mut copy = stream[pos[rng.next_u32() as usize % pos.len()]].clone();
+            if rng.next_u32() % 2 == 0 {
+                copy.fragment_id = copy.packets_in_flow + rng.next_u32() as usize % 4;
+            } else {
+                copy.length = copy.data.len() + 1 + rng.next_u32() as usize % 8;
+            }
+            injected.push((rng.next_u32() as usize % (stream.len() + 1), copy));
+        }
+    }
+
+    injected.sort_by_key(|(at, _)| *at);
+    let mut injected = injected.into_iter().peekable();
+    let original = std::mem::take(stream);
+    for (pos, packet) in original.into_iter().enumerate() {
+        while matches!(injected.peek(), Some((at, _)) if *at == pos) {
+            stream.push_back(injected.next().unwrap().1);
+        }
+        stream.push_back(packet);
+    }
+    stream.extend(injected.map(|(_, packet)| packet));
+
+    expected
+}
+
+/// Draws a printable ASCII character other than `b`.
+fn different_byte(b: u8, rng: &mut ChaCha12Rng) -> u8 {
+    loop {
+        let candidate = (rng.next_u32() % 95) as u8 + b' ';
+        if candidate != b {
+            return candidate;
+        }
+    }
+}
+
 pub fn id<T>(data: T) -> T {
     data
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoder::simple::Decoder;
+    use crate::decoder::EvictionPolicy;
+    use crate::detector::normalize;
+
+    #[test]
+    fn obfuscated_attacks_normalize_to_the_pattern() {
+        let mut rng = ChaCha12Rng::seed_from_u64(3);
+        for pattern in &[&b"about"[..], b"a", b"100% & \\ more", b"\xde\xad\x00\xef"] {
+            for _ in 0..20 {
+                let obfuscated = obfuscate(pattern, &mut rng);
+                assert_ne!(&obfuscated, pattern);
+                assert_eq!(normalize(&obfuscated).as_ref(), *pattern);
+            }
+        }
+    }
+
+    #[test]
+    fn decoders_handle_injected_anomalies() {
+        let rules = RuleSet::default();
+        let detector = Detector::new(Engine::Scan, &rules, true);
+
+        for &reassembly in &[ReassemblyPolicy::FirstWins, ReassemblyPolicy::LastWins] {
+            let (mut stream, mut attacks) = generate_stream(500, 20, 32, 7, &rules, 0, false);
+            let packets = stream.len();
+            let expected =
+                inject_anomalies(&mut stream, &mut attacks, 30, reassembly, 7, &rules);
+            assert!(expected.duplicates > 0 && expected.overlaps > 0 && expected.malformed > 0);
+            assert_eq!(
+                stream.len(),
+                packets + expected.duplicates + expected.overlaps + expected.malformed
+            );
+
+            let mut decoder = Decoder::with_policy(EvictionPolicy::default(), reassembly);
+            let mut found = HashSet::new();
+            for packet in stream {
+                if let Some(flow) = decoder.decode_flow(packet) {
+                    if let DetectorResult::SignatureMatch(_) = detector.detect(&flow.data) {
+                        found.insert(flow.flow_id);
+                    }
+                }
+            }
+
+            assert!(decoder.fragments_map.is_empty());
+            assert_eq!(decoder.stats, expected);
+            assert_eq!(found, attacks);
+        }
+    }
+}
@@ -3,11 +3,12 @@ use crate::detector::*;
 use crate::decoder::*;
 use crate::decoder::simple::*;
 use std::collections::VecDeque;
+use std::sync::Arc;
 
 /// A 2-stage pipeline parallel algorithm.
-pub fn analyze_flow_2(mut packets: VecDeque<Packet>) -> Vec<usize> {
+pub fn analyze_flow_2(mut packets: VecDeque<Packet>, detector: Arc<Detector>) -> Vec<Alert> {
     let mut decoder: Decoder = Decoder::new();
-    let mut detector: AttackDetector = AttackDetector::new();
+    let mut attack_detector: AttackDetector = AttackDetector::new(detector);
 
     let packets0: VecDeque<Packet> = id(packets);
     for packet0 in packets0 {
@@ -15,17 +16,17 @@ pub fn analyze_flow_2(mut packets: VecDeque<Packet>) -> Vec<usize> {
         // decode the data (state!) --> decoder.c
         let decoded_flow:Option<DecodedFlow> = decoder.decode_flow(packet);
         // process the output -> run the detector
-        detector.detect(decoded_flow);
+        attack_detector.detect(decoded_flow);
     }
 
-    detector.get_attacks()
+    attack_detector.get_attacks()
 }
 
 /// A 3-stage pipeline parallel algorithm where the heavy compute step
 /// benefits from data parallelism.
-pub fn analyze_flow_3(mut packets: VecDeque<Packet>) -> Vec<usize> {
+pub fn analyze_flow_3(mut packets: VecDeque<Packet>, detector: Arc<Detector>) -> Vec<Alert> {
     let mut decoder: Decoder = Decoder::new();
-    let mut found: Vec<Option<usize>> = Vec::new();
+    let mut found: Vec<Option<Alert>> = Vec::new();
 
     //let packets0: VecDeque<Packet> = id(packets);
     for packet0 in packets {
@@ -33,7 +34,8 @@ pub fn analyze_flow_3(mut packets: VecDeque<Packet>) -> Vec<usize> {
         // decode the data (state!) --> decoder.c
         let decoded_flow:Option<DecodedFlow> = decoder.decode_flow(packet);
         // 2nd stage: intrusion detection (is stateless and runs data parallel)
-        let detected: Option<usize> = bind_detect(decoded_flow); 
+        let detector_c: Arc<Detector> = detector.clone();
+        let detected: Option<Alert> = bind_detect(decoded_flow, detector_c);
         // just collect
         found.push(detected)
     }
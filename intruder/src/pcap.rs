@@ -0,0 +1,511 @@
+//! Reading and writing of packet streams in the libpcap file format.
+//!
+//! Captures are reassembled into the flows the analyzers work on: the fragments of a
+//! fragmented IPv4 datagram, the payload segments of a TCP connection and every single
+//! unfragmented UDP datagram each form one flow. Fragments are numbered by their fragment offset
+//! or TCP sequence number, the packets keep the order in which they have been captured.
+
+use crate::detector::{Detector, DetectorResult, Engine, RuleSet};
+use crate::Packet;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
+use std::path::Path;
+
+const MAGIC_MICROS: u32 = 0xa1b2_c3d4;
+const MAGIC_NANOS: u32 = 0xa1b2_3c4d;
+
+const LINKTYPE_ETHERNET: u32 = 1;
+const LINKTYPE_RAW: u32 = 101;
+const LINKTYPE_IPV4: u32 = 228;
+
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_VLAN: u16 = 0x8100;
+
+const PROTO_TCP: u8 = 6;
+const PROTO_UDP: u8 = 17;
+
+/// Largest packet written by `write_stream`
+const SNAPLEN: u32 = 65535;
+/// Largest payload that fits into a single frame: Ethernet, IPv4 and TCP headers take 54 bytes
+const MAX_PAYLOAD: usize = SNAPLEN as usize - 54;
+
+/// Identifies the flow a captured packet belongs to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum FlowKey {
+    /// Fragments of an IPv4 datagram: source, destination, protocol and identification
+    Datagram([u8; 4], [u8; 4], u8, u16),
+    /// A TCP connection (one direction): source address and port, destination address and port
+    Connection([u8; 4], u16, [u8; 4], u16),
+    /// An unfragmented datagram, identified by its position in the capture
+    Single(usize),
+}
+
+/// A payload extracted from the capture, before fragment numbers are assigned.
+struct Fragment {
+    key: FlowKey,
+    /// Position of the payload within its flow (byte offset or TCP sequence number)
+    position: u32,
+    data: Vec<u8>,
+}
+
+/// Reads the capture at `path` into a packet stream. Also returns the ids of all flows that
+/// contain an attack according to the reference detector with the `rules`, like
+/// `generate_stream` does.
+///
+/// Packets that are not IPv4 with a TCP or UDP payload are skipped, as are TCP segments
+/// without payload and retransmissions of already captured sequence numbers.
+pub fn read_stream<P: AsRef<Path>>(
+    path: P,
+    rules: &RuleSet,
+) -> io::Result<(VecDeque<Packet>, HashSet<usize>)> {
+    let mut raw = Vec::new();
+    File::open(path)?.read_to_end(&mut raw)?;
+
+    let fragments = parse_capture(&raw)?;
+    Ok(assemble_stream(fragments, rules))
+}
+
+/// Writes the packet `stream` as capture to `path`. Every flow becomes a TCP connection from
+/// an address derived from its flow id to port 80, each packet one segment of it.
+///
+/// Fails without writing anything if a packet carries more than `MAX_PAYLOAD` bytes, since the
+/// capture could not be read back into the same stream then.
+pub fn write_stream<P: AsRef<Path>>(path: P, stream: &VecDeque<Packet>) -> io::Result<()> {
+    if let Some(p) = stream.iter().find(|p| p.data.len() > MAX_PAYLOAD) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "fragment {} of flow {} has {} bytes, a captured packet holds at most {}",
+                p.fragment_id,
+                p.flow_id,
+                p.data.len(),
+                MAX_PAYLOAD
+            ),
+        ));
+    }
+
+    let mut out = BufWriter::new(File::create(path)?);
+
+    // global header
+    out.write_all(&MAGIC_MICROS.to_le_bytes())?;
+    out.write_all(&2u16.to_le_bytes())?;
+    out.write_all(&4u16.to_le_bytes())?;
+    out.write_all(&0i32.to_le_bytes())?;
+    out.write_all(&0u32.to_le_bytes())?;
+    out.write_all(&SNAPLEN.to_le_bytes())?;
+    out.write_all(&LINKTYPE_ETHERNET.to_le_bytes())?;
+
+    // sequence numbers are byte offsets, so the lengths of all preceding fragments are needed
+    let mut lengths: HashMap<usize, Vec<usize>> = HashMap::new();
+    for p in stream {
+        let l = lengths
+            .entry(p.flow_id)
+            .or_insert_with(|| vec![0; p.packets_in_flow]);
+        if p.fragment_id < l.len() {
+            l[p.fragment_id] = p.data.len();
+        }
+    }
+
+    for (idx, p) in stream.iter().enumerate() {
+        let offset: usize = lengths[&p.flow_id].iter().take(p.fragment_id).sum();
+        let frame = tcp_frame(p.flow_id, offset as u32, &p.data);
+
+        // one packet per microsecond
+        out.write_all(&((idx / 1_000_000) as u32).to_le_bytes())?;
+        out.write_all(&((idx % 1_000_000) as u32).to_le_bytes())?;
+        out.write_all(&(frame.len() as u32).to_le_bytes())?;
+        out.write_all(&(frame.len() as u32).to_le_bytes())?;
+        out.write_all(&frame)?;
+    }
+
+    out.flush()
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+/// Extracts all TCP and UDP payloads from the raw capture file.
+fn parse_capture(raw: &[u8]) -> io::Result<Vec<Fragment>> {
+    if raw.len() < 24 {
+        return Err(invalid_data("file too short for a pcap header"));
+    }
+
+    let magic = [raw[0], raw[1], raw[2], raw[3]];
+    let big_endian = match (u32::from_le_bytes(magic), u32::from_be_bytes(magic)) {
+        (MAGIC_MICROS, _) | (MAGIC_NANOS, _) => false,
+        (_, MAGIC_MICROS) | (_, MAGIC_NANOS) => true,
+        _ => return Err(invalid_data("not a pcap file")),
+    };
+    let read_u32 = |b: &[u8]| {
+        let bytes = [b[0], b[1], b[2], b[3]];
+        if big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        }
+    };
+
+    let linktype = read_u32(&raw[20..24]);
+    if ![LINKTYPE_ETHERNET, LINKTYPE_RAW, LINKTYPE_IPV4].contains(&linktype) {
+        return Err(invalid_data(&format!("unsupported link type {}", linktype)));
+    }
+
+    let mut fragments = Vec::new();
+    let mut pos = 24;
+    let mut record = 0;
+    while pos + 16 <= raw.len() {
+        let incl_len = read_u32(&raw[pos + 8..pos + 12]) as usize;
+        let start = pos + 16;
+        if start + incl_len > raw.len() {
+            return Err(invalid_data(&format!("record {} is truncated", record)));
+        }
+
+        let frame = &raw[start..start + incl_len];
+        let ip = if linktype == LINKTYPE_ETHERNET {
+            strip_ethernet(frame)
+        } else {
+            Some(frame)
+        };
+        if let Some(fragment) = ip.and_then(|ip| parse_ipv4(ip, record)) {
+            fragments.push(fragment);
+        }
+
+        pos = start + incl_len;
+        record += 1;
+    }
+
+    Ok(fragments)
+}
+
+/// Returns the IPv4 packet contained in an Ethernet frame, if any.
+fn strip_ethernet(frame: &[u8]) -> Option<&[u8]> {
+    let mut offset = 12;
+    loop {
+        let ethertype = u16::from_be_bytes([*frame.get(offset)?, *frame.get(offset + 1)?]);
+        match ethertype {
+            ETHERTYPE_VLAN => offset += 4,
+            ETHERTYPE_IPV4 => return frame.get(offset + 2..),
+            _ => return None,
+        }
+    }
+}
+
+/// Extracts the payload of an IPv4 packet carrying TCP or UDP. `record` is the position of the
+/// packet in the capture.
+fn parse_ipv4(ip: &[u8], record: usize) -> Option<Fragment> {
+    if ip.len() < 20 || ip[0] >> 4 != 4 {
+        return None;
+    }
+
+    let header_len = (ip[0] & 0x0f) as usize * 4;
+    // trailing bytes after the datagram (e.g., Ethernet padding) are ignored
+    let total_len = (u16::from_be_bytes([ip[2], ip[3]]) as usize).min(ip.len());
+    if header_len < 20 || total_len < header_len {
+        return None;
+    }
+
+    let id = u16::from_be_bytes([ip[4], ip[5]]);
+    let flags_offset = u16::from_be_bytes([ip[6], ip[7]]);
+    let more_fragments = flags_offset & 0x2000 != 0;
+    let fragment_offset = (flags_offset & 0x1fff) as u32 * 8;
+    let protocol = ip[9];
+    let src = [ip[12], ip[13], ip[14], ip[15]];
+    let dst = [ip[16], ip[17], ip[18], ip[19]];
+    let payload = &ip[header_len..total_len];
+
+    if protocol != PROTO_TCP && protocol != PROTO_UDP {
+        return None;
+    }
+
+    if more_fragments || fragment_offset > 0 {
+        // the transport header is only part of the first fragment
+        let data = if fragment_offset == 0 {
+            strip_transport(protocol, payload)?.2
+        } else {
+            payload
+        };
+
+        return Some(Fragment {
+            key: FlowKey::Datagram(src, dst, protocol, id),
+            position: fragment_offset,
+            data: data.to_vec(),
+        });
+    }
+
+    let (src_port, dst_port, data) = strip_transport(protocol, payload)?;
+    if protocol == PROTO_UDP {
+        Some(Fragment {
+            key: FlowKey::Single(record),
+            position: 0,
+            data: data.to_vec(),
+        })
+    } else if data.is_empty() {
+        // connection handling (SYN, FIN, pure ACKs) carries no bytes a signature could be found
+        // in. Such segments are not fragments of the flow either: the generated streams have none
+        // and counting them in `packets_in_flow` would make the detector wait for empty packets.
+        None
+    } else {
+        Some(Fragment {
+            key: FlowKey::Connection(src, src_port, dst, dst_port),
+            position: u32::from_be_bytes([payload[4], payload[5], payload[6], payload[7]]),
+            data: data.to_vec(),
+        })
+    }
+}
+
+/// Splits a TCP or UDP segment into source port, destination port and payload.
+fn strip_transport(protocol: u8, segment: &[u8]) -> Option<(u16, u16, &[u8])> {
+    let header_len = match protocol {
+        PROTO_UDP => 8,
+        _ => (*segment.get(12)? >> 4) as usize * 4,
+    };
+    if header_len < 8 || segment.len() < header_len {
+        return None;
+    }
+
+    let src_port = u16::from_be_bytes([segment[0], segment[1]]);
+    let dst_port = u16::from_be_bytes([segment[2], segment[3]]);
+    Some((src_port, dst_port, &segment[header_len..]))
+}
+
+/// Numbers the fragments of every flow and determines the flows containing an attack.
+fn assemble_stream(fragments: Vec<Fragment>, rules: &RuleSet) -> (VecDeque<Packet>, HashSet<usize>) {
+    // flow ids are assigned in order of appearance
+    let mut flow_ids: HashMap<FlowKey, usize> = HashMap::new();
+    // position of the first captured fragment and the positions of all fragments relative to it
+    let mut flows: Vec<(u32, HashSet<i32>)> = Vec::new();
+    let mut captured = Vec::with_capacity(fragments.len());
+
+    for fragment in fragments {
+        let next_id = flows.len();
+        let flow_id = *flow_ids.entry(fragment.key).or_insert(next_id);
+        if flow_id == next_id {
+            flows.push((fragment.position, HashSet::new()));
+        }
+
+        // TCP sequence numbers may wrap around, so they are compared by their (signed) distance
+        // to the first one captured
+        let (base, positions) = &mut flows[flow_id];
+        let relative = fragment.position.wrapping_sub(*base) as i32;
+        if !positions.insert(relative) {
+            // retransmission
+            continue;
+        }
+        captured.push((flow_id, relative, fragment.data));
+    }
+
+    // fragment id = rank of the position within the flow
+    let ranks: Vec<Vec<i32>> = flows
+        .into_iter()
+        .map(|(_, positions)| {
+            let mut r: Vec<i32> = positions.into_iter().collect();
+            r.sort_unstable();
+            r
+        })
+        .collect();
+
+    let mut stream = VecDeque::with_capacity(captured.len());
+    for (flow_id, relative, data) in captured {
+        let fragment_id = ranks[flow_id].binary_search(&relative).unwrap();
+        stream.push_back(Packet {
+            flow_id,
+            fragment_id,
+            packets_in_flow: ranks[flow_id].len(),
+            length: data.len(),
+            data,
+        });
+    }
+
+    // the ground truth is what the reference detector finds in the reassembled flows
+    let reference = Detector::new(Engine::Scan, rules, true);
+    let mut reassembled: Vec<Vec<(usize, &[u8])>> = vec![Vec::new(); ranks.len()];
+    for p in &stream {
+        reassembled[p.flow_id].push((p.fragment_id, &p.data));
+    }
+    let attacks = reassembled
+        .iter_mut()
+        .enumerate()
+        .filter_map(|(flow_id, parts)| {
+            parts.sort_unstable();
+            let flow: Vec<u8> = parts.iter().flat_map(|(_, d)| d.iter().copied()).collect();
+            if let DetectorResult::SignatureMatch(_) = reference.detect(&flow) {
+                Some(flow_id)
+            } else {
+                None
+            }
+        })
+        .collect();
+
+    (stream, attacks)
+}
+
+/// Builds an Ethernet frame with a TCP segment of the connection for `flow_id`. The `payload`
+/// must not be longer than `MAX_PAYLOAD`.
+fn tcp_frame(flow_id: usize, seq: u32, payload: &[u8]) -> Vec<u8> {
+    let src = [
+        10,
+        (flow_id >> 16) as u8,
+        (flow_id >> 8) as u8,
+        flow_id as u8,
+    ];
+    let dst = [192, 168, 0, 1];
+    // flows beyond 2^24 get distinct source ports
+    let src_port = 1024 + (flow_id >> 24) as u16;
+    let dst_port: u16 = 80;
+
+    let mut tcp = Vec::with_capacity(20 + payload.len());
+    tcp.extend_from_slice(&src_port.to_be_bytes());
+    tcp.extend_from_slice(&dst_port.to_be_bytes());
+    tcp.extend_from_slice(&seq.wrapping_add(1).to_be_bytes());
+    tcp.extend_from_slice(&0u32.to_be_bytes());
+    // data offset 5, flags PSH + ACK, window
+    tcp.extend_from_slice(&[0x50, 0x18, 0xff, 0xff]);
+    // checksum (filled below) and urgent pointer
+    tcp.extend_from_slice(&[0, 0, 0, 0]);
+    tcp.extend_from_slice(payload);
+
+    let mut pseudo = Vec::with_capacity(12 + tcp.len());
+    pseudo.extend_from_slice(&src);
+    pseudo.extend_from_slice(&dst);
+    pseudo.extend_from_slice(&[0, PROTO_TCP]);
+    pseudo.extend_from_slice(&(tcp.len() as u16).to_be_bytes());
+    pseudo.extend_from_slice(&tcp);
+    let checksum = internet_checksum(&pseudo);
+    tcp[16..18].copy_from_slice(&checksum.to_be_bytes());
+
+    let mut ip = Vec::with_capacity(20);
+    ip.extend_from_slice(&[0x45, 0]);
+    ip.extend_from_slice(&((20 + tcp.len()) as u16).to_be_bytes());
+    // identification, don't fragment, TTL 64
+    ip.extend_from_slice(&[0, 0, 0x40, 0, 64, PROTO_TCP, 0, 0]);
+    ip.extend_from_slice(&src);
+    ip.extend_from_slice(&dst);
+    let checksum = internet_checksum(&ip);
+    ip[10..12].copy_from_slice(&checksum.to_be_bytes());
+
+    let mut frame = Vec::with_capacity(14 + ip.len() + tcp.len());
+    frame.extend_from_slice(&[0x02, 0, 0, 0, 0, 0x02]);
+    frame.extend_from_slice(&[0x02, 0, 0, 0, 0, 0x01]);
+    frame.extend_from_slice(&ETHERTYPE_IPV4.to_be_bytes());
+    frame.extend_from_slice(&ip);
+    frame.extend_from_slice(&tcp);
+
+    frame
+}
+
+/// The one's complement checksum used by IPv4 and TCP.
+fn internet_checksum(data: &[u8]) -> u16 {
+    let mut sum: u32 = data
+        .chunks(2)
+        .map(|c| u16::from_be_bytes([c[0], *c.get(1).unwrap_or(&0)]) as u32)
+        .sum();
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+
+    !(sum as u16)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn packet(flow_id: usize, fragment_id: usize, packets_in_flow: usize, data: &str) -> Packet {
+        Packet {
+            flow_id,
+            fragment_id,
+            packets_in_flow,
+            length: data.len(),
+            data: data.into(),
+        }
+    }
+
+    #[test]
+    fn export_and_import() {
+        let stream: VecDeque<Packet> = vec![
+            packet(7, 1, 3, "ou"),
+            packet(3, 0, 1, "plain"),
+            packet(7, 0, 3, "ab"),
+            packet(7, 2, 3, "t!"),
+        ]
+        .into();
+
+        let path = std::env::temp_dir().join("intruder-pcap-roundtrip.pcap");
+        write_stream(&path, &stream).unwrap();
+        let (read, attacks) = read_stream(&path, &RuleSet::default()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        // flows are numbered in order of appearance
+        let expected: VecDeque<Packet> = vec![
+            packet(0, 1, 3, "ou"),
+            packet(1, 0, 1, "plain"),
+            packet(0, 0, 3, "ab"),
+            packet(0, 2, 3, "t!"),
+        ]
+        .into();
+        assert_eq!(read, expected);
+        assert_eq!(attacks, vec![0].into_iter().collect());
+    }
+
+    #[test]
+    fn skip_retransmissions() {
+        // the duplicate has the same sequence number as the original segment
+        let stream: VecDeque<Packet> = vec![
+            packet(0, 1, 2, "cd"),
+            packet(0, 0, 2, "ab"),
+            packet(0, 1, 2, "cd"),
+        ]
+        .into();
+
+        let path = std::env::temp_dir().join("intruder-pcap-retransmission.pcap");
+        write_stream(&path, &stream).unwrap();
+        let (read, _) = read_stream(&path, &RuleSet::default()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let expected: VecDeque<Packet> = vec![packet(0, 1, 2, "cd"), packet(0, 0, 2, "ab")].into();
+        assert_eq!(read, expected);
+    }
+
+    #[test]
+    fn reject_oversized_packets() {
+        let stream: VecDeque<Packet> = vec![
+            packet(0, 0, 2, "a"),
+            Packet {
+                flow_id: 0,
+                fragment_id: 1,
+                packets_in_flow: 2,
+                length: MAX_PAYLOAD + 1,
+                data: vec![b'x'; MAX_PAYLOAD + 1],
+            },
+        ]
+        .into();
+
+        let path = std::env::temp_dir().join("intruder-pcap-oversized.pcap");
+        let err = write_stream(&path, &stream).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(!path.exists());
+
+        // the largest payload still survives the round trip
+        let stream: VecDeque<Packet> = vec![Packet {
+            flow_id: 0,
+            fragment_id: 0,
+            packets_in_flow: 1,
+            length: MAX_PAYLOAD,
+            data: vec![b'x'; MAX_PAYLOAD],
+        }]
+        .into();
+        write_stream(&path, &stream).unwrap();
+        let (read, _) = read_stream(&path, &RuleSet::default()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(read, stream);
+    }
+
+    #[test]
+    fn checksum() {
+        // example from RFC 1071
+        let data = [0x00, 0x01, 0xf2, 0x03, 0xf4, 0xf5, 0xf6, 0xf7];
+        assert_eq!(internet_checksum(&data), !0xddf2);
+    }
+}
@@ -1,27 +1,73 @@
 use crate::Packet;
+use crate::decoder::{DecoderStats, EvictionPolicy, ReassemblyPolicy};
 use crate::detector::*;
 use crate::decoder::simple::*;
+use crate::stream::Arrival;
 use std::collections::VecDeque;
+use std::sync::mpsc::Receiver;
+use std::time::Duration;
 
 /// Function that analyzes the incoming packet stream. The "benchmark" itself.
 /// Everything inside this function is being timed.
 ///
-/// Returns a Vec of flow IDs that contained an attack for later check
-pub fn analyze_flow(mut packets: VecDeque<Packet>) -> Vec<usize> {
+/// Returns the alerts for the flows that contained an attack for later check and the statistics of
+/// the decoder, including the incomplete flows that have been evicted according to the
+/// `policy` or because they were still incomplete at the end of the stream
+pub fn analyze_flow(
+    mut packets: VecDeque<Packet>,
+    detector: &Detector,
+    policy: EvictionPolicy,
+    reassembly: ReassemblyPolicy,
+) -> (Vec<Alert>, DecoderStats) {
     let mut found_attacks = Vec::new();
-    let mut decoder = Decoder::new();
+    let mut decoder = Decoder::with_policy(policy, reassembly);
 
     for packet in packets.drain(..) {
         // decode the data (state!) --> decoder.c
         if let Some(decoded_flow) = decoder.decode_flow(packet) {
             // process the output -> run the detector
-            if detect(&decoded_flow.data) == DetectorResult::SignatureMatch {
-                found_attacks.push(decoded_flow.flow_id);
+            if let DetectorResult::SignatureMatch(rule_id) = detector.detect(&decoded_flow.data) {
+                found_attacks.push(Alert {
+                    flow_id: decoded_flow.flow_id,
+                    rule_id,
+                });
             }
         }
     }
 
+    decoder.flush();
     assert!(decoder.fragments_map.is_empty());
 
-    found_attacks
+    (found_attacks, decoder.stats)
+}
+
+/// Streaming version of `analyze_flow` that processes the packets as they arrive.
+///
+/// Additionally returns the latency of every packet, i.e., the time from its arrival until it
+/// has been decoded and, if it completed a flow, the flow has been checked.
+pub fn analyze_stream(
+    packets: Receiver<Arrival>,
+    detector: &Detector,
+    policy: EvictionPolicy,
+    reassembly: ReassemblyPolicy,
+) -> (Vec<Alert>, DecoderStats, Vec<Duration>) {
+    let mut found_attacks = Vec::new();
+    let mut latencies = Vec::new();
+    let mut decoder = Decoder::with_policy(policy, reassembly);
+
+    for Arrival { packet, time } in packets {
+        if let Some(decoded_flow) = decoder.decode_flow(packet) {
+            if let DetectorResult::SignatureMatch(rule_id) = detector.detect(&decoded_flow.data) {
+                found_attacks.push(Alert {
+                    flow_id: decoded_flow.flow_id,
+                    rule_id,
+                });
+            }
+        }
+        latencies.push(time.elapsed());
+    }
+
+    decoder.flush();
+
+    (found_attacks, decoder.stats, latencies)
 }
@@ -0,0 +1,132 @@
+//! Parallel variant that partitions the decoder state instead of sharing it: the packets are
+//! distributed to the threads by a hash of their flow id, so all fragments of a flow are
+//! decoded by the same, independent `Decoder` and no synchronization is needed.
+//!
+//! The packets of a shard keep their order, so the reassembly policy is applied
+//! deterministically. The eviction limits apply per shard: timeouts are counted in the packets
+//! of the shard and each shard gets the same share of the `max_bytes`.
+
+use crate::decoder::simple::Decoder;
+use crate::decoder::{DecoderStats, EvictionPolicy, ReassemblyPolicy};
+use crate::detector::{Alert, Detector, DetectorResult};
+use crate::Packet;
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::thread;
+
+/// Assigns a flow to one of the `shards` (Fibonacci hashing, so that consecutive flow ids are
+/// spread evenly).
+pub fn shard_of(flow_id: usize, shards: usize) -> usize {
+    ((flow_id as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15) >> 32) as usize % shards
+}
+
+/// Decodes and analyzes the packets of a single shard.
+fn analyze_shard(
+    packets: Vec<Packet>,
+    detector: Arc<Detector>,
+    policy: EvictionPolicy,
+    reassembly: ReassemblyPolicy,
+) -> (Vec<Alert>, DecoderStats) {
+    let mut found_attacks = Vec::new();
+    let mut decoder = Decoder::with_policy(policy, reassembly);
+
+    for packet in packets {
+        if let Some(decoded_flow) = decoder.decode_flow(packet) {
+            if let DetectorResult::SignatureMatch(rule_id) = detector.detect(&decoded_flow.data) {
+                found_attacks.push(Alert {
+                    flow_id: decoded_flow.flow_id,
+                    rule_id,
+                });
+            }
+        }
+    }
+
+    decoder.flush();
+    assert!(decoder.fragments_map.is_empty());
+
+    (found_attacks, decoder.stats)
+}
+
+/// Returns the alerts for the flows that contained an attack and the combined statistics of the
+/// decoders of all shards.
+pub fn run_eval(
+    packets: VecDeque<Packet>,
+    threadcount: usize,
+    detector: Arc<Detector>,
+    policy: EvictionPolicy,
+    reassembly: ReassemblyPolicy,
+) -> (Vec<Alert>, DecoderStats) {
+    let threadcount = threadcount.max(1);
+    let mut found_attacks = Vec::new();
+    let mut stats = DecoderStats::default();
+
+    let shard_policy = EvictionPolicy {
+        timeout: policy.timeout,
+        max_bytes: policy.max_bytes.map(|max| max / threadcount),
+    };
+
+    let mut shards = vec![Vec::with_capacity(packets.len() / threadcount + 1); threadcount];
+    for packet in packets {
+        shards[shard_of(packet.flow_id, threadcount)].push(packet);
+    }
+
+    let mut handles = Vec::with_capacity(threadcount);
+    for packets in shards {
+        let d = detector.clone();
+        handles.push(thread::spawn(move || {
+            analyze_shard(packets, d, shard_policy, reassembly)
+        }));
+    }
+
+    for handle in handles {
+        let (mut attacks, shard_stats) = handle.join().unwrap();
+        found_attacks.append(&mut attacks);
+        stats.merge(shard_stats);
+    }
+    stats.evicted.sort_unstable();
+
+    (found_attacks, stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::detector::{Engine, RuleSet};
+    use crate::{generate_stream, seq};
+
+    #[test]
+    fn agrees_with_sequential() {
+        let rules = RuleSet::default();
+        let detector = Arc::new(Detector::new(Engine::AhoCorasick, &rules, true));
+        let (packets, _) = generate_stream(300, 20, 32, 5, &rules, 0, true);
+
+        let (mut expected, _) = seq::analyze_flow(
+            packets.clone(),
+            &detector,
+            EvictionPolicy::default(),
+            ReassemblyPolicy::default(),
+        );
+        for &threads in &[1, 3, 8] {
+            let (mut alerts, stats) = run_eval(
+                packets.clone(),
+                threads,
+                detector.clone(),
+                EvictionPolicy::default(),
+                ReassemblyPolicy::default(),
+            );
+            alerts.sort_by_key(|a| a.flow_id);
+            expected.sort_by_key(|a| a.flow_id);
+            assert_eq!(alerts, expected);
+            assert_eq!(stats, DecoderStats::default());
+        }
+    }
+
+    #[test]
+    fn shards_are_balanced() {
+        let mut counts = [0; 4];
+        for flow_id in 0..4000 {
+            counts[shard_of(flow_id, 4)] += 1;
+        }
+        assert!(counts.iter().all(|&c| c > 900 && c < 1100), "{:?}", counts);
+    }
+}
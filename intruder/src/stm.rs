@@ -1,15 +1,25 @@
 use crate::decoder::stm_decoder::{decode_packet, StmDecoderState};
-use crate::detector::{detect, DetectorResult};
+use crate::decoder::{DecoderStats, EvictionPolicy, ReassemblyPolicy};
+use crate::detector::{Alert, Detector, DetectorResult};
+use crate::sharded::shard_of;
+use crate::stream::Arrival;
 use crate::*;
 use std::collections::VecDeque;
+use std::sync::mpsc::Receiver;
+use std::sync::{Arc, Mutex};
 use std::thread;
+use std::time::Duration;
 use ::stm::atomically; //, TVar};
 
 /// Function that analyzes the incoming packet stream. The "benchmark" itself.
 /// Everything inside this function is being timed.
 ///
-/// Returns a Vec of flow IDs that contained an attack for later check
-fn analyze_stream(mut packets: VecDeque<Packet>, decoder_state: StmDecoderState) -> Vec<usize> {
+/// Returns the alerts for the flows that contained an attack for later check
+fn analyze_stream(
+    mut packets: VecDeque<Packet>,
+    decoder_state: StmDecoderState,
+    detector: Arc<Detector>,
+) -> Vec<Alert> {
     let mut found_attacks = Vec::new();
 
     // NOTE: This is a deviation from the original code where packets where
@@ -30,11 +40,15 @@ fn analyze_stream(mut packets: VecDeque<Packet>, decoder_state: StmDecoderState)
     for p in packets {
         // if let Some(p) = packet {
         // do the algorithm
+        decoder_state.tick();
         let decoder_result = atomically(|trans2| decode_packet(&p, &decoder_state, trans2));
         if let Some(decoded_flow) = decoder_result {
             // process the output -> run the detector
-            if detect(&decoded_flow.data) == DetectorResult::SignatureMatch {
-                found_attacks.push(decoded_flow.flow_id);
+            if let DetectorResult::SignatureMatch(rule_id) = detector.detect(&decoded_flow.data) {
+                found_attacks.push(Alert {
+                    flow_id: decoded_flow.flow_id,
+                    rule_id,
+                });
             }
         }
         // } else {
@@ -45,38 +59,73 @@ fn analyze_stream(mut packets: VecDeque<Packet>, decoder_state: StmDecoderState)
     found_attacks
 }
 
-fn partition_input_vec(mut packets: VecDeque<Packet>, threadcount: usize) -> Vec<VecDeque<Packet>> {
-    let l = packets.len() / threadcount;
-    let mut rest = packets.len() % threadcount;
-
-    let mut partitioned = vec![VecDeque::with_capacity(l); threadcount];
-
-    for t_num in 0..threadcount {
-        if rest > 0 {
-            partitioned[t_num] = packets.split_off(packets.len() - l - 1);
-            rest -= 1;
-        } else {
-            if packets.len() <= l {
-                partitioned[t_num] = packets.split_off(0);
-            } else {
-                partitioned[t_num] = packets.split_off(packets.len() - l);
+/// Distributes the packets to the threads by their flow, so that all fragments of a flow are
+/// decoded by the same thread in stream order and duplicates and overlaps are resolved as in
+/// the sequential decoder.
+fn partition_input_vec(packets: VecDeque<Packet>, threadcount: usize) -> Vec<VecDeque<Packet>> {
+    let mut partitioned = vec![VecDeque::with_capacity(packets.len() / threadcount); threadcount];
+
+    for p in packets {
+        partitioned[shard_of(p.flow_id, threadcount)].push_back(p);
+    }
+
+    partitioned
+}
+
+/// Streaming version of `analyze_stream`: the threads take the packets from the shared channel
+/// as they arrive and record the latency of every packet.
+fn analyze_arrivals(
+    packets: Arc<Mutex<Receiver<Arrival>>>,
+    decoder_state: StmDecoderState,
+    detector: Arc<Detector>,
+) -> (Vec<Alert>, Vec<Duration>) {
+    let mut found_attacks = Vec::new();
+    let mut latencies = Vec::new();
+
+    loop {
+        // the lock is only held while receiving
+        let arrival = packets.lock().unwrap().recv();
+        let Arrival { packet, time } = match arrival {
+            Ok(a) => a,
+            Err(_) => break,
+        };
+
+        decoder_state.tick();
+        let decoder_result = atomically(|trans| decode_packet(&packet, &decoder_state, trans));
+        if let Some(decoded_flow) = decoder_result {
+            if let DetectorResult::SignatureMatch(rule_id) = detector.detect(&decoded_flow.data) {
+                found_attacks.push(Alert {
+                    flow_id: decoded_flow.flow_id,
+                    rule_id,
+                });
             }
         }
+        latencies.push(time.elapsed());
     }
 
-    partitioned
+    (found_attacks, latencies)
 }
 
-pub fn run_eval(packets: VecDeque<Packet>, threadcount: usize) -> Vec<usize> {
+/// Returns the alerts for the flows that contained an attack and the statistics of the
+/// decoder, including the incomplete flows that have been evicted according to the `policy` or
+/// because they were still incomplete at the end of the stream.
+pub fn run_eval(
+    packets: VecDeque<Packet>,
+    threadcount: usize,
+    detector: Arc<Detector>,
+    policy: EvictionPolicy,
+    reassembly: ReassemblyPolicy,
+) -> (Vec<Alert>, DecoderStats) {
     let mut found_attacks = Vec::new();
-    let decoder_state = StmDecoderState::new(threadcount);
+    let decoder_state = StmDecoderState::with_policy(threadcount, policy, reassembly);
 
     let mut handles = Vec::with_capacity(threadcount);
     let mut inputs = partition_input_vec(packets, threadcount);
 
     for packets in inputs {
         let ds = decoder_state.clone();
-        handles.push(thread::spawn(move || analyze_stream(packets, ds)));
+        let d = detector.clone();
+        handles.push(thread::spawn(move || analyze_stream(packets, ds, d)));
     }
 
     for handle in handles {
@@ -85,6 +134,7 @@ pub fn run_eval(packets: VecDeque<Packet>, threadcount: usize) -> Vec<usize> {
     }
 
     // State verification
+    decoder_state.flush();
     let fmap = decoder_state.fragments_map.get_contents();
     if !fmap.is_empty() {
         println!("{:#?}", fmap);
@@ -93,5 +143,68 @@ pub fn run_eval(packets: VecDeque<Packet>, threadcount: usize) -> Vec<usize> {
         .fragments_map
         .is_empty(trans3)));
 
-    found_attacks
+    (found_attacks, decoder_state.stats.read_atomic())
+}
+
+/// Streaming version of `run_eval`, additionally returns the latency of every packet.
+pub fn run_stream(
+    packets: Receiver<Arrival>,
+    threadcount: usize,
+    detector: Arc<Detector>,
+    policy: EvictionPolicy,
+    reassembly: ReassemblyPolicy,
+) -> (Vec<Alert>, DecoderStats, Vec<Duration>) {
+    let mut found_attacks = Vec::new();
+    let mut latencies = Vec::new();
+    let decoder_state = StmDecoderState::with_policy(threadcount, policy, reassembly);
+    let packets = Arc::new(Mutex::new(packets));
+
+    let mut handles = Vec::with_capacity(threadcount);
+    for _ in 0..threadcount {
+        let p = packets.clone();
+        let ds = decoder_state.clone();
+        let d = detector.clone();
+        handles.push(thread::spawn(move || analyze_arrivals(p, ds, d)));
+    }
+
+    for handle in handles {
+        let (mut attacks, mut l) = handle.join().unwrap();
+        found_attacks.append(&mut attacks);
+        latencies.append(&mut l);
+    }
+
+    decoder_state.flush();
+
+    (found_attacks, decoder_state.stats.read_atomic(), latencies)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::detector::{Engine, RuleSet};
+    use std::collections::HashSet;
+
+    #[test]
+    fn handles_injected_anomalies_on_multiple_threads() {
+        let rules = RuleSet::default();
+        let detector = Arc::new(Detector::new(Engine::Scan, &rules, true));
+
+        for &reassembly in &[ReassemblyPolicy::FirstWins, ReassemblyPolicy::LastWins] {
+            let (mut stream, mut attacks) = generate_stream(500, 20, 32, 7, &rules, 0, false);
+            let expected = inject_anomalies(&mut stream, &mut attacks, 30, reassembly, 7, &rules);
+
+            for &threads in &[2, 4] {
+                let (alerts, stats) = run_eval(
+                    stream.clone(),
+                    threads,
+                    detector.clone(),
+                    EvictionPolicy::default(),
+                    reassembly,
+                );
+                let found: HashSet<usize> = alerts.iter().map(|a| a.flow_id).collect();
+                assert_eq!(stats, expected);
+                assert_eq!(found, attacks);
+            }
+        }
+    }
 }
@@ -0,0 +1,118 @@
+//! Streaming mode: instead of handing the complete packet stream to an analyzer, packets are
+//! fed into it through a channel at a fixed rate, like they would arrive from the network.
+
+use crate::Packet;
+use std::collections::VecDeque;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Number of packets that may wait in the channel before the source blocks.
+const CHANNEL_CAPACITY: usize = 4096;
+
+/// A packet together with the time it arrived at the analyzer.
+pub struct Arrival {
+    pub packet: Packet,
+    pub time: Instant,
+}
+
+/// Sends the `packets` from a separate thread, `rate` packets per second or as fast as they
+/// are consumed if no rate is given. The channel is closed after the last packet.
+///
+/// With a rate, the arrival time of a packet is its scheduled time, so that time spent waiting
+/// for a full channel counts towards its latency.
+pub fn feed(packets: VecDeque<Packet>, rate: Option<u64>) -> Receiver<Arrival> {
+    let (sender, receiver) = mpsc::sync_channel(CHANNEL_CAPACITY);
+
+    thread::spawn(move || {
+        let start = Instant::now();
+        for (idx, packet) in packets.into_iter().enumerate() {
+            let time = match rate {
+                Some(rate) => {
+                    let due = start + Duration::from_nanos(idx as u64 * 1_000_000_000 / rate);
+                    let now = Instant::now();
+                    if due > now {
+                        thread::sleep(due - now);
+                    }
+                    due
+                }
+                None => Instant::now(),
+            };
+
+            if sender.send(Arrival { packet, time }).is_err() {
+                // the analyzer has stopped
+                break;
+            }
+        }
+    });
+
+    receiver
+}
+
+/// Summary of the per-packet latencies of a run, in microseconds.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct LatencyStats {
+    pub mean: f64,
+    pub p50: f64,
+    pub p99: f64,
+    pub max: f64,
+}
+
+impl LatencyStats {
+    pub fn from_latencies(mut latencies: Vec<Duration>) -> Self {
+        if latencies.is_empty() {
+            return LatencyStats::default();
+        }
+        latencies.sort_unstable();
+
+        let micros = |d: &Duration| d.as_nanos() as f64 / 1000.0;
+        let percentile = |p: usize| micros(&latencies[(latencies.len() - 1) * p / 100]);
+
+        LatencyStats {
+            mean: latencies.iter().map(micros).sum::<f64>() / latencies.len() as f64,
+            p50: percentile(50),
+            p99: percentile(99),
+            max: micros(&latencies[latencies.len() - 1]),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn packet(flow_id: usize) -> Packet {
+        Packet {
+            flow_id,
+            fragment_id: 0,
+            packets_in_flow: 1,
+            length: 1,
+            data: "x".into(),
+        }
+    }
+
+    #[test]
+    fn feeds_in_order_at_the_rate() {
+        let start = Instant::now();
+        let arrivals: Vec<Arrival> = feed((0..5).map(packet).collect(), Some(100))
+            .iter()
+            .collect();
+
+        let ids: Vec<usize> = arrivals.iter().map(|a| a.packet.flow_id).collect();
+        assert_eq!(ids, vec![0, 1, 2, 3, 4]);
+        // the last packet is due after 40 ms
+        assert!(start.elapsed() >= Duration::from_millis(40));
+        assert!(arrivals[4].time - arrivals[0].time >= Duration::from_millis(40));
+    }
+
+    #[test]
+    fn latency_summary() {
+        let latencies = (1..=100).map(Duration::from_micros).collect();
+        let stats = LatencyStats::from_latencies(latencies);
+
+        assert_eq!(stats.mean, 50.5);
+        assert_eq!(stats.p50, 50.0);
+        assert_eq!(stats.p99, 99.0);
+        assert_eq!(stats.max, 100.0);
+    }
+}
@@ -3,13 +3,19 @@ static GLOBAL: jemallocator::Jemalloc = jemallocator::Jemalloc;
 
 use clap::{App, Arg};
 use cpu_time::ProcessTime;
+use labyrinth::compare::{self, RoutingResult};
+use labyrinth::export::ImageFormat;
 use labyrinth::parser;
+use labyrinth::router::Router;
+use labyrinth::schedule::PathOrder;
 use labyrinth::pathfinder;
 use labyrinth::stm_grid;
 use labyrinth::stmseq::types::{Maze, Path, StmGrid};
 use labyrinth::types::Point;
+use std::cell::Cell;
 use std::fs::{create_dir_all, File};
 use std::io::Write;
+use std::process;
 use std::str::FromStr;
 use std::thread;
 use stm::{det_atomically, dtm, freeze, DTMHandle};
@@ -55,6 +61,52 @@ fn main() {
                 .takes_value(true)
                 .default_value("results")
         )
+        .arg(
+            Arg::with_name("router")
+                .long("router")
+                .takes_value(true)
+                .help("The routing algorithm to use: bfs | astar | lee[:<x cost>,<y cost>,<via cost>]")
+                .default_value("bfs")
+        )
+        .arg(
+            Arg::with_name("order")
+                .long("order")
+                .takes_value(true)
+                .help("The order in which the pairs are routed: file | longest | shortest | clustered | random[:<seed>]")
+                .default_value("file")
+        )
+        .arg(
+            Arg::with_name("reroute")
+                .long("reroute")
+                .takes_value(true)
+                .help("Maximal number of rip-up-and-reroute rounds for the unmappable paths after routing (0 disables the phase).")
+                .default_value("0")
+        )
+        .arg(
+            Arg::with_name("render")
+                .long("render")
+                .takes_value(true)
+                .help("Writes an image of every layer of the routed maze (of the last run) into the given directory.")
+        )
+        .arg(
+            Arg::with_name("renderformat")
+                .long("render-format")
+                .takes_value(true)
+                .help("Image format for --render: ppm | svg")
+                .default_value("svg")
+        )
+        .arg(
+            Arg::with_name("dumppaths")
+                .long("dump-paths")
+                .takes_value(true)
+                .help("Writes the routed paths and unmappable pairs (of the last run) in canonical form into the given file.")
+        )
+        .arg(
+            Arg::with_name("comparewith")
+                .long("compare-with")
+                .takes_value(true)
+                .help("Compares the routed paths of every run against a file written with --dump-paths (e.g., by the sequential version).")
+        )
         .get_matches();
 
     // thread number
@@ -68,18 +120,65 @@ fn main() {
     // #runs
     let runs = usize::from_str(matches.value_of("runs").unwrap_or("1")).unwrap();
 
+    // rendering of the routed maze
+    let render_dir = matches.value_of("render");
+    let render_format = ImageFormat::from_str(matches.value_of("renderformat").unwrap())
+        .expect("Could not parse the image format");
+
+    // determinism checks
+    let dump_file = matches.value_of("dumppaths");
+    let reference = matches.value_of("comparewith").map(|f| match RoutingResult::read_file(f) {
+        Ok(res) => res,
+        Err(e) => {
+            eprintln!("[ERROR] Failed to read reference {}: {}", f, e);
+            process::exit(1);
+        }
+    });
+
+    // routing algorithm
+    let router = match Router::from_str(matches.value_of("router").unwrap()) {
+        Ok(r) => r,
+        Err(e) => {
+            eprintln!("[ERROR] {}", e);
+            process::exit(1);
+        }
+    };
+
+    // routing order of the pairs
+    let order = match PathOrder::from_str(matches.value_of("order").unwrap()) {
+        Ok(o) => o,
+        Err(e) => {
+            eprintln!("[ERROR] {}", e);
+            process::exit(1);
+        }
+    };
+
+    // rip-up-and-reroute phase
+    let reroute_rounds = usize::from_str(matches.value_of("reroute").unwrap())
+        .expect("The entered number of reroute rounds was not a valid uint");
+
     // input location & parsing
     let input_file = matches.value_of("INPUT").unwrap();
-    let (dimensions, paths) = parser::parse_file(input_file);
+    let (dimensions, mut paths, walls) = match parser::parse_file(input_file) {
+        Ok(input) => input,
+        Err(e) => {
+            eprintln!("[ERROR] Failed to parse {}: {}", input_file, e);
+            process::exit(1);
+        }
+    };
+    order.apply(&mut paths);
 
     let mut results = Vec::with_capacity(runs);
     let mut cpu_results = Vec::with_capacity(runs);
     let mut mapped_paths = Vec::with_capacity(runs);
+    let mut identical = Vec::with_capacity(runs);
+    let mut rerouted = Vec::with_capacity(runs);
+    let mut iterations = Vec::with_capacity(runs);
     let mut retry_counts = Vec::with_capacity(runs);
     let mut computations = Vec::with_capacity(runs);
 
-    for _ in 0..runs {
-        let maze = Maze::new(dimensions.clone(), None);
+    for r in 0..runs {
+        let maze = Maze::new(dimensions.clone(), walls.clone());
 
         if !json_dump {
             println!("[INFO] Loaded maze data from file.");
@@ -87,7 +186,8 @@ fn main() {
 
         let start = PreciseTime::now();
         let cpu_start = ProcessTime::now();
-        let filled_maze = route_paths(maze, paths.clone(), thread_number);
+        let (mut filled_maze, retries, rounds) = route_paths(maze, paths.clone(), router, thread_number);
+        let reroute_stats = filled_maze.reroute(&router, reroute_rounds);
         let cpu_end = ProcessTime::now();
         let end = PreciseTime::now();
 
@@ -102,16 +202,42 @@ fn main() {
             results.push(runtime_ms);
             cpu_results.push(cpu_runtime_ms);
             mapped_paths.push(filled_maze.paths.len());
-            retry_counts.push(0);
-            computations.push(0);
-            //retry_counts.push(retries);
-            //computations.push(retries + paths.len());
+            rerouted.push(reroute_stats.rerouted);
+            iterations.push(rounds);
+            retry_counts.push(retries);
+            computations.push(retries + paths.len());
+            if let Some(reference) = &reference {
+                let differences = filled_maze.routing_result().diff(reference);
+                if !json_dump {
+                    compare::print_differences(&differences);
+                }
+                identical.push(differences.is_empty());
+            }
+            if let Some(file) = dump_file {
+                if r + 1 == runs {
+                    if let Err(e) = filled_maze.routing_result().write_file(file) {
+                        eprintln!("[ERROR] Could not write the routed paths: {}", e);
+                    }
+                }
+            }
+            if let Some(dir) = render_dir {
+                if r + 1 == runs {
+                    if let Err(e) = filled_maze.render(dir, render_format) {
+                        eprintln!("[ERROR] Could not render the maze: {}", e);
+                    }
+                }
+            }
         } else {
             eprintln!("Incorrect path mappings found in maze: {:?}", filled_maze);
             return;
         }
     }
 
+    let mapped_ratio: Vec<f64> = mapped_paths
+        .iter()
+        .map(|&m| m as f64 / paths.len().max(1) as f64)
+        .collect();
+
     if json_dump {
         create_dir_all(out_dir).unwrap();
         let algo = if cfg!(feature = "naive") {
@@ -133,10 +259,17 @@ fn main() {
             "{{
     \"algorithm\": \"{algo}\",
     \"configuration\": \"{conf}\",
+    \"router\": \"{router}\",
+    \"order\": \"{order}\",
     \"paths\": {paths},
     \"threadcount\": {threads},
     \"runs\": {runs},
     \"mapped\": {mapped:?},
+    \"mapped_ratio\": {ratio:?},
+    \"reroute_rounds\": {reroute},
+    \"rerouted\": {rerouted:?},
+    \"matches_reference\": {identical:?},
+    \"iterations\": {its:?},
     \"collisions\": {collisions:?},
     \"computations\": {comps:?},
     \"cpu_time\": {cpu:?},
@@ -144,10 +277,17 @@ fn main() {
 }}",
             algo = algo,
             conf = dimensions,
+            router = router,
+            order = order,
             paths = paths.len(),
             threads = thread_number,
             runs = runs,
             mapped = mapped_paths,
+            ratio = mapped_ratio,
+            reroute = reroute_rounds,
+            rerouted = rerouted,
+            identical = identical,
+            its = iterations,
             collisions = retry_counts,
             comps = computations,
             cpu = cpu_results,
@@ -158,10 +298,15 @@ fn main() {
         println!("[INFO] Successfully validated the maze.");
         println!("\nStatistics:");
         println!("    Maze configuration: {}", dimensions);
+        println!("    Router:             {}", router);
+        println!("    Order:              {}", order);
         println!("    Thread number:      {}", thread_number);
         println!("    Paths overall:      {}", paths.len());
         println!("    Runs:               {}", runs);
         println!("    Mapped:             {:?}", mapped_paths);
+        println!("    Mapped ratio:       {:?}", mapped_ratio);
+        println!("    Rerouted:           {:?}", rerouted);
+        println!("    Iterations:         {:?}", iterations);
         println!("    Collisions:         {:?}", retry_counts);
         println!("    Computations:       {:?}", computations);
         println!("\nCPU time: {:?} ms", cpu_results);
@@ -169,9 +314,19 @@ fn main() {
     }
 }
 
-fn route_paths(mut maze: Maze, to_map: Vec<(Point, Point)>, thread_number: usize) -> Maze {
+fn route_paths(
+    mut maze: Maze,
+    to_map: Vec<(Point, Point)>,
+    router: Router,
+    thread_number: usize,
+) -> (Maze, usize, usize) {
+    let mut global_retries = 0;
+    let mut rounds = 0;
+
     // iterate over chunks of size up to thread_number
     for chunk in to_map.chunks(thread_number) {
+        rounds += 1;
+
         // register dtm handles
         let mut dtm = dtm();
         let work: Vec<((Point, Point), DTMHandle)> = chunk
@@ -185,32 +340,44 @@ fn route_paths(mut maze: Maze, to_map: Vec<(Point, Point)>, thread_number: usize
         let mut handles = Vec::with_capacity(thread_number);
         for item in work {
             let g = maze.grid.clone();
-            handles.push(thread::spawn(move || route(&g, item)));
+            handles.push(thread::spawn(move || route(&g, item, &router)));
         }
 
         // collect results (unmappable?
         for h in handles {
-            match h.join().unwrap() {
+            let (result, retries) = h.join().unwrap();
+            match result {
                 Ok(path) => maze.paths.push(path),
                 Err(path) => maze.unmappable_paths.push(path),
             }
+            global_retries += retries;
         }
     }
 
-    maze
+    (maze, global_retries, rounds)
 }
 
-/// Attempts to route the paths from `to_map` on he grid using STM.
-fn route(grid: &StmGrid, input: ((Point, Point), DTMHandle)) -> Result<Path, (Point, Point)> {
+/// Attempts to route the paths from `to_map` on he grid using STM. Also returns the number of
+/// retries of the transaction.
+fn route(
+    grid: &StmGrid,
+    input: ((Point, Point), DTMHandle),
+    router: &Router,
+) -> (Result<Path, (Point, Point)>, usize) {
     let (to_map, handle) = input;
+    // every execution of the transaction body beyond the first one is a retry
+    let attempts = Cell::new(0);
 
-    det_atomically(handle, |trans| {
+    let result = det_atomically(handle, |trans| {
+        attempts.set(attempts.get() + 1);
         let copy_grid = stm_grid::create_working_copy(&grid);
-        if let Some(path) = pathfinder::find_path(to_map.clone(), &copy_grid) {
+        if let Some(path) = pathfinder::find_path(to_map.clone(), &copy_grid, router) {
             stm_grid::update_grid(&grid, &path, trans)?;
             Ok(Ok(path))
         } else {
             Ok(Err(to_map))
         }
-    })
+    });
+
+    (result, attempts.get() - 1)
 }
@@ -0,0 +1,127 @@
+use clap::{App, Arg};
+use labyrinth::generator::{self, GeneratorConfig};
+use labyrinth::types::Point;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::process;
+use std::str::FromStr;
+
+fn main() {
+    let matches = App::new("Labyrinth Input Generator")
+        .version("1.0")
+        .author("Felix Suchert <dev@felixsuchert.de>")
+        .about("Generates reproducible random inputs for the labyrinth benchmarks.")
+        .arg(
+            Arg::with_name("x")
+                .help("Width of the maze (x-axis).")
+                .required(true)
+                .index(1),
+        )
+        .arg(
+            Arg::with_name("y")
+                .help("Height of the maze (y-axis).")
+                .required(true)
+                .index(2),
+        )
+        .arg(
+            Arg::with_name("z")
+                .help("Depth of the maze (z-axis).")
+                .required(true)
+                .index(3),
+        )
+        .arg(
+            Arg::with_name("paths")
+                .help("Number of paths to generate.")
+                .required(true)
+                .index(4),
+        )
+        .arg(
+            Arg::with_name("seed")
+                .long("seed")
+                .short("s")
+                .takes_value(true)
+                .help("Seed for the random number generator.")
+                .default_value("0")
+        )
+        .arg(
+            Arg::with_name("maxlength")
+                .long("max-length")
+                .short("l")
+                .takes_value(true)
+                .help("Maximal Manhattan distance between the two endpoints of a path.")
+        )
+        .arg(
+            Arg::with_name("density")
+                .long("density")
+                .short("d")
+                .takes_value(true)
+                .help("Fraction of fields (0.0 - 1.0) that are turned into walls.")
+                .default_value("0.0")
+        )
+        .arg(
+            Arg::with_name("output")
+                .long("output")
+                .short("o")
+                .takes_value(true)
+                .help("Output file. Writes to stdout if omitted.")
+        )
+        .get_matches();
+
+    let dimensions = Point {
+        x: parse_arg(matches.value_of("x").unwrap(), "x dimension"),
+        y: parse_arg(matches.value_of("y").unwrap(), "y dimension"),
+        z: parse_arg(matches.value_of("z").unwrap(), "z dimension"),
+    };
+    let paths: usize = parse_arg(matches.value_of("paths").unwrap(), "path count");
+    let seed: u64 = parse_arg(matches.value_of("seed").unwrap(), "seed");
+    let max_path_length: Option<usize> = matches
+        .value_of("maxlength")
+        .map(|l| parse_arg(l, "maximal path length"));
+    let obstacle_density: f64 = parse_arg(matches.value_of("density").unwrap(), "density");
+
+    if dimensions.x == 0 || dimensions.y == 0 || dimensions.z == 0 {
+        fail("All dimensions must be at least 1");
+    }
+    if !(0.0..=1.0).contains(&obstacle_density) {
+        fail("The obstacle density must be between 0.0 and 1.0");
+    }
+
+    let config = GeneratorConfig {
+        seed,
+        dimensions,
+        paths,
+        max_path_length,
+        obstacle_density,
+    };
+    let (dimensions, paths, walls) = generator::generate(&config)
+        .unwrap_or_else(|e| fail(&format!("Failed to generate the maze: {}", e)));
+
+    let res = match matches.value_of("output") {
+        Some(path) => File::create(path).and_then(|f| {
+            let mut out = BufWriter::new(f);
+            generator::write_input(&mut out, &dimensions, &paths, &walls)?;
+            out.flush()
+        }),
+        None => {
+            let stdout = io::stdout();
+            let mut out = stdout.lock();
+            generator::write_input(&mut out, &dimensions, &paths, &walls)
+        }
+    };
+
+    if let Err(e) = res {
+        fail(&format!("Failed to write the maze: {}", e));
+    }
+}
+
+fn parse_arg<T: FromStr>(value: &str, name: &str) -> T {
+    match T::from_str(value) {
+        Ok(v) => v,
+        Err(_) => fail(&format!("`{}` is not a valid {}", value, name)),
+    }
+}
+
+fn fail(msg: &str) -> ! {
+    eprintln!("[ERROR] {}", msg);
+    process::exit(1);
+}
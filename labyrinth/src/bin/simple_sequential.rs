@@ -3,10 +3,15 @@ static GLOBAL: jemallocator::Jemalloc = jemallocator::Jemalloc;
 
 use clap::{App, Arg};
 use labyrinth::grid;
+use labyrinth::compare::{self, RoutingResult};
+use labyrinth::export::ImageFormat;
 use labyrinth::parser;
+use labyrinth::router::Router;
+use labyrinth::schedule::PathOrder;
 use labyrinth::stmseq::types::Maze;
 use std::fs::{create_dir_all, File};
 use std::io::Write;
+use std::process;
 use std::str::FromStr;
 use time::PreciseTime;
 
@@ -42,6 +47,52 @@ fn main() {
                 .takes_value(true)
                 .default_value("results")
         )
+        .arg(
+            Arg::with_name("router")
+                .long("router")
+                .takes_value(true)
+                .help("The routing algorithm to use: bfs | astar | lee[:<x cost>,<y cost>,<via cost>]")
+                .default_value("bfs")
+        )
+        .arg(
+            Arg::with_name("order")
+                .long("order")
+                .takes_value(true)
+                .help("The order in which the pairs are routed: file | longest | shortest | clustered | random[:<seed>]")
+                .default_value("file")
+        )
+        .arg(
+            Arg::with_name("reroute")
+                .long("reroute")
+                .takes_value(true)
+                .help("Maximal number of rip-up-and-reroute rounds for the unmappable paths after routing (0 disables the phase).")
+                .default_value("0")
+        )
+        .arg(
+            Arg::with_name("render")
+                .long("render")
+                .takes_value(true)
+                .help("Writes an image of every layer of the routed maze (of the last run) into the given directory.")
+        )
+        .arg(
+            Arg::with_name("renderformat")
+                .long("render-format")
+                .takes_value(true)
+                .help("Image format for --render: ppm | svg")
+                .default_value("svg")
+        )
+        .arg(
+            Arg::with_name("dumppaths")
+                .long("dump-paths")
+                .takes_value(true)
+                .help("Writes the routed paths and unmappable pairs (of the last run) in canonical form into the given file.")
+        )
+        .arg(
+            Arg::with_name("comparewith")
+                .long("compare-with")
+                .takes_value(true)
+                .help("Compares the routed paths of every run against a file written with --dump-paths (e.g., by the sequential version).")
+        )
         .get_matches();
 
     // JSON Dump?
@@ -51,22 +102,70 @@ fn main() {
     // #runs
     let runs = usize::from_str(matches.value_of("runs").unwrap_or("1")).unwrap();
 
+    // rendering of the routed maze
+    let render_dir = matches.value_of("render");
+    let render_format = ImageFormat::from_str(matches.value_of("renderformat").unwrap())
+        .expect("Could not parse the image format");
+
+    // determinism checks
+    let dump_file = matches.value_of("dumppaths");
+    let reference = matches.value_of("comparewith").map(|f| match RoutingResult::read_file(f) {
+        Ok(res) => res,
+        Err(e) => {
+            eprintln!("[ERROR] Failed to read reference {}: {}", f, e);
+            process::exit(1);
+        }
+    });
+
+    // routing algorithm
+    let router = match Router::from_str(matches.value_of("router").unwrap()) {
+        Ok(r) => r,
+        Err(e) => {
+            eprintln!("[ERROR] {}", e);
+            process::exit(1);
+        }
+    };
+
+    // routing order of the pairs
+    let order = match PathOrder::from_str(matches.value_of("order").unwrap()) {
+        Ok(o) => o,
+        Err(e) => {
+            eprintln!("[ERROR] {}", e);
+            process::exit(1);
+        }
+    };
+
+    // rip-up-and-reroute phase
+    let reroute_rounds = usize::from_str(matches.value_of("reroute").unwrap())
+        .expect("The entered number of reroute rounds was not a valid uint");
+
     let input_file = matches.value_of("INPUT").unwrap();
-    let (dimensions, paths) = parser::parse_file(input_file);
+    let (dimensions, mut paths, walls) = match parser::parse_file(input_file) {
+        Ok(input) => input,
+        Err(e) => {
+            eprintln!("[ERROR] Failed to parse {}: {}", input_file, e);
+            process::exit(1);
+        }
+    };
+    order.apply(&mut paths);
 
     let mut results = Vec::with_capacity(runs);
     let mut mapped_paths = Vec::with_capacity(runs);
+    let mut identical = Vec::with_capacity(runs);
+    let mut rerouted = Vec::with_capacity(runs);
+    let mut iterations = Vec::with_capacity(runs);
     let mut computations = Vec::with_capacity(runs);
 
-    for _ in 0..runs {
-        let maze = Maze::new(dimensions.clone(), None);
+    for r in 0..runs {
+        let maze = Maze::new(dimensions.clone(), walls.clone());
 
         if !json_dump {
             println!("[INFO] Loaded maze data from file.");
         }
 
         let start = PreciseTime::now();
-        let filled_maze = grid::route_paths(maze, paths.clone());
+        let mut filled_maze = grid::route_paths(maze, paths.clone(), &router);
+        let reroute_stats = filled_maze.reroute(&router, reroute_rounds);
         let end = PreciseTime::now();
 
         if !json_dump {
@@ -78,13 +177,41 @@ fn main() {
         if filled_maze.is_valid() {
             results.push(runtime_ms);
             mapped_paths.push(filled_maze.paths.len());
+            rerouted.push(reroute_stats.rerouted);
+            iterations.push(1);
             computations.push(paths.len());
+            if let Some(reference) = &reference {
+                let differences = filled_maze.routing_result().diff(reference);
+                if !json_dump {
+                    compare::print_differences(&differences);
+                }
+                identical.push(differences.is_empty());
+            }
+            if let Some(file) = dump_file {
+                if r + 1 == runs {
+                    if let Err(e) = filled_maze.routing_result().write_file(file) {
+                        eprintln!("[ERROR] Could not write the routed paths: {}", e);
+                    }
+                }
+            }
+            if let Some(dir) = render_dir {
+                if r + 1 == runs {
+                    if let Err(e) = filled_maze.render(dir, render_format) {
+                        eprintln!("[ERROR] Could not render the maze: {}", e);
+                    }
+                }
+            }
         } else {
             eprintln!("Incorrect path mappings found in maze: {:?}", filled_maze);
             return;
         }
     }
 
+    let mapped_ratio: Vec<f64> = mapped_paths
+        .iter()
+        .map(|&m| m as f64 / paths.len().max(1) as f64)
+        .collect();
+
     if json_dump {
         create_dir_all(out_dir).unwrap();
         let filename = format!(
@@ -100,18 +227,32 @@ fn main() {
     \"application\": \"labyrinth\",
     \"algorithm\": \"sequential\",
     \"configuration\": \"{conf}\",
+    \"router\": \"{router}\",
+    \"order\": \"{order}\",
     \"paths\": {paths},
     \"threads\": 1,
     \"computations\": {comps:?},
     \"runs\": {runs},
     \"mapped\": {mapped:?},
+    \"mapped_ratio\": {ratio:?},
+    \"reroute_rounds\": {reroute},
+    \"rerouted\": {rerouted:?},
+    \"matches_reference\": {identical:?},
+    \"iterations\": {its:?},
     \"results\": {res:?}
 }}",
             conf = dimensions,
+            router = router,
+            order = order,
             paths = paths.len(),
             runs = runs,
             comps = computations,
             mapped = mapped_paths,
+            ratio = mapped_ratio,
+            reroute = reroute_rounds,
+            rerouted = rerouted,
+            identical = identical,
+            its = iterations,
             res = results
         ))
         .unwrap();
@@ -119,9 +260,14 @@ fn main() {
         println!("[INFO] Successfully validated the maze.");
         println!("\nStatistics:");
         println!("    Maze configuration: {}", dimensions);
+        println!("    Router:             {}", router);
+        println!("    Order:              {}", order);
         println!("    Paths overall:      {}", paths.len());
         println!("    Runs:               {}", runs);
         println!("    Mapped:             {:?}", mapped_paths);
+        println!("    Mapped ratio:       {:?}", mapped_ratio);
+        println!("    Rerouted:           {:?}", rerouted);
+        println!("    Iterations:         {:?}", iterations);
         println!("\nRouting Times: {:?} ms", results);
     }
 }
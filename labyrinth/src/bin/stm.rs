@@ -3,18 +3,37 @@ static GLOBAL: jemallocator::Jemalloc = jemallocator::Jemalloc;
 
 use clap::{App, Arg};
 use cpu_time::ProcessTime;
+use labyrinth::compare::{self, RoutingResult};
+use labyrinth::export::ImageFormat;
 use labyrinth::parser;
+use labyrinth::router::Router;
+use labyrinth::schedule::PathOrder;
 use labyrinth::pathfinder;
 use labyrinth::stm_grid;
 use labyrinth::stmseq::types::{Maze, Path, StmGrid};
 use labyrinth::types::Point;
+use std::cell::Cell;
 use std::fs::{create_dir_all, File};
 use std::io::Write;
+use std::process;
 use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
 use std::thread;
 use stm::atomically;
+use strum_macros::{Display, EnumString};
 use time::PreciseTime;
 
+/// How the pairs to route are distributed among the threads.
+#[derive(Clone, Copy, Display, EnumString)]
+#[strum(serialize_all = "lowercase")]
+enum Scheduling {
+    /// Every thread gets a fixed chunk of the input up front
+    Static,
+    /// Threads pull the next pair from a shared queue
+    Queue,
+}
+
 fn main() {
     let matches = App::new("STM Labyrinth Benchmark")
         .version("1.0")
@@ -55,12 +74,70 @@ fn main() {
                 .takes_value(true)
                 .default_value("results")
         )
+        .arg(
+            Arg::with_name("router")
+                .long("router")
+                .takes_value(true)
+                .help("The routing algorithm to use: bfs | astar | lee[:<x cost>,<y cost>,<via cost>]")
+                .default_value("bfs")
+        )
+        .arg(
+            Arg::with_name("order")
+                .long("order")
+                .takes_value(true)
+                .help("The order in which the pairs are routed: file | longest | shortest | clustered | random[:<seed>]")
+                .default_value("file")
+        )
+        .arg(
+            Arg::with_name("reroute")
+                .long("reroute")
+                .takes_value(true)
+                .help("Maximal number of rip-up-and-reroute rounds for the unmappable paths after routing (0 disables the phase).")
+                .default_value("0")
+        )
+        .arg(
+            Arg::with_name("render")
+                .long("render")
+                .takes_value(true)
+                .help("Writes an image of every layer of the routed maze (of the last run) into the given directory.")
+        )
+        .arg(
+            Arg::with_name("renderformat")
+                .long("render-format")
+                .takes_value(true)
+                .help("Image format for --render: ppm | svg")
+                .default_value("svg")
+        )
+        .arg(
+            Arg::with_name("dumppaths")
+                .long("dump-paths")
+                .takes_value(true)
+                .help("Writes the routed paths and unmappable pairs (of the last run) in canonical form into the given file.")
+        )
+        .arg(
+            Arg::with_name("comparewith")
+                .long("compare-with")
+                .takes_value(true)
+                .help("Compares the routed paths of every run against a file written with --dump-paths (e.g., by the sequential version).")
+        )
+        .arg(
+            Arg::with_name("scheduling")
+                .long("scheduling")
+                .short("s")
+                .takes_value(true)
+                .help("How pairs are distributed among threads: static (fixed chunks) | queue (threads pull from a shared queue)")
+                .default_value("static")
+        )
         .get_matches();
 
     // thread number
     let thread_number = usize::from_str(matches.value_of("threadcount").unwrap())
         .expect("The entered thread count was not a valid uint");
 
+    // work distribution
+    let scheduling = Scheduling::from_str(matches.value_of("scheduling").unwrap())
+        .expect("Could not parse the scheduling mode");
+
     // JSON Dump?
     let json_dump = matches.is_present("json");
     let out_dir = matches.value_of("outdir").unwrap();
@@ -68,18 +145,67 @@ fn main() {
     // #runs
     let runs = usize::from_str(matches.value_of("runs").unwrap_or("1")).unwrap();
 
+    // rendering of the routed maze
+    let render_dir = matches.value_of("render");
+    let render_format = ImageFormat::from_str(matches.value_of("renderformat").unwrap())
+        .expect("Could not parse the image format");
+
+    // determinism checks
+    let dump_file = matches.value_of("dumppaths");
+    let reference = matches.value_of("comparewith").map(|f| match RoutingResult::read_file(f) {
+        Ok(res) => res,
+        Err(e) => {
+            eprintln!("[ERROR] Failed to read reference {}: {}", f, e);
+            process::exit(1);
+        }
+    });
+
+    // routing algorithm
+    let router = match Router::from_str(matches.value_of("router").unwrap()) {
+        Ok(r) => r,
+        Err(e) => {
+            eprintln!("[ERROR] {}", e);
+            process::exit(1);
+        }
+    };
+
+    // routing order of the pairs
+    let order = match PathOrder::from_str(matches.value_of("order").unwrap()) {
+        Ok(o) => o,
+        Err(e) => {
+            eprintln!("[ERROR] {}", e);
+            process::exit(1);
+        }
+    };
+
+    // rip-up-and-reroute phase
+    let reroute_rounds = usize::from_str(matches.value_of("reroute").unwrap())
+        .expect("The entered number of reroute rounds was not a valid uint");
+
     // input location & parsing
     let input_file = matches.value_of("INPUT").unwrap();
-    let (dimensions, paths) = parser::parse_file(input_file);
+    let (dimensions, mut paths, walls) = match parser::parse_file(input_file) {
+        Ok(input) => input,
+        Err(e) => {
+            eprintln!("[ERROR] Failed to parse {}: {}", input_file, e);
+            process::exit(1);
+        }
+    };
+    order.apply(&mut paths);
 
     let mut results = Vec::with_capacity(runs);
     let mut cpu_results = Vec::with_capacity(runs);
     let mut mapped_paths = Vec::with_capacity(runs);
+    let mut identical = Vec::with_capacity(runs);
+    let mut rerouted = Vec::with_capacity(runs);
+    let mut iterations = Vec::with_capacity(runs);
     let mut retry_counts = Vec::with_capacity(runs);
     let mut computations = Vec::with_capacity(runs);
+    let mut thread_routed = Vec::with_capacity(runs);
+    let mut thread_retries = Vec::with_capacity(runs);
 
-    for _ in 0..runs {
-        let maze = Maze::new(dimensions.clone(), None);
+    for r in 0..runs {
+        let maze = Maze::new(dimensions.clone(), walls.clone());
 
         if !json_dump {
             println!("[INFO] Loaded maze data from file.");
@@ -87,7 +213,9 @@ fn main() {
 
         let start = PreciseTime::now();
         let cpu_start = ProcessTime::now();
-        let (filled_maze, retries) = route_paths(maze, paths.clone(), thread_number);
+        let (mut filled_maze, thread_stats) =
+            route_paths(maze, paths.clone(), router, thread_number, scheduling);
+        let reroute_stats = filled_maze.reroute(&router, reroute_rounds);
         let cpu_end = ProcessTime::now();
         let end = PreciseTime::now();
 
@@ -97,25 +225,56 @@ fn main() {
 
         let runtime_ms = start.to(end).num_milliseconds();
         let cpu_runtime_ms = cpu_end.duration_since(cpu_start).as_millis();
+        let retries: usize = thread_stats.iter().map(|(_, r)| r).sum();
 
         if filled_maze.is_valid() {
             results.push(runtime_ms);
             cpu_results.push(cpu_runtime_ms);
             mapped_paths.push(filled_maze.paths.len());
+            rerouted.push(reroute_stats.rerouted);
+            iterations.push(1);
             retry_counts.push(retries);
             computations.push(retries + paths.len());
+            thread_routed.push(thread_stats.iter().map(|(p, _)| *p).collect::<Vec<_>>());
+            thread_retries.push(thread_stats.iter().map(|(_, r)| *r).collect::<Vec<_>>());
+            if let Some(reference) = &reference {
+                let differences = filled_maze.routing_result().diff(reference);
+                if !json_dump {
+                    compare::print_differences(&differences);
+                }
+                identical.push(differences.is_empty());
+            }
+            if let Some(file) = dump_file {
+                if r + 1 == runs {
+                    if let Err(e) = filled_maze.routing_result().write_file(file) {
+                        eprintln!("[ERROR] Could not write the routed paths: {}", e);
+                    }
+                }
+            }
+            if let Some(dir) = render_dir {
+                if r + 1 == runs {
+                    if let Err(e) = filled_maze.render(dir, render_format) {
+                        eprintln!("[ERROR] Could not render the maze: {}", e);
+                    }
+                }
+            }
         } else {
             eprintln!("Incorrect path mappings found in maze: {:?}", filled_maze);
             return;
         }
     }
 
+    let mapped_ratio: Vec<f64> = mapped_paths
+        .iter()
+        .map(|&m| m as f64 / paths.len().max(1) as f64)
+        .collect();
+
     if json_dump {
         create_dir_all(out_dir).unwrap();
-        let algo = if cfg!(feature = "naive") {
-            "stm-naive"
-        } else {
-            "rust-stm"
+        let algo = match (cfg!(feature = "naive"), scheduling) {
+            (true, _) => "stm-naive",
+            (false, Scheduling::Static) => "rust-stm",
+            (false, Scheduling::Queue) => "rust-stm-queue",
         };
         let filename = format!(
             "{}/{}-{}-p{}-t{}-r{}_log.json",
@@ -131,23 +290,43 @@ fn main() {
             "{{
     \"algorithm\": \"{algo}\",
     \"configuration\": \"{conf}\",
+    \"router\": \"{router}\",
+    \"order\": \"{order}\",
     \"paths\": {paths},
     \"threadcount\": {threads},
+    \"scheduling\": \"{scheduling}\",
     \"runs\": {runs},
     \"mapped\": {mapped:?},
+    \"mapped_ratio\": {ratio:?},
+    \"reroute_rounds\": {reroute},
+    \"rerouted\": {rerouted:?},
+    \"matches_reference\": {identical:?},
+    \"iterations\": {its:?},
     \"collisions\": {collisions:?},
     \"computations\": {comps:?},
+    \"thread_routed\": {t_routed:?},
+    \"thread_retries\": {t_retries:?},
     \"cpu_time\": {cpu:?},
     \"results\": {res:?}
 }}",
             algo = algo,
             conf = dimensions,
+            router = router,
+            order = order,
             paths = paths.len(),
             threads = thread_number,
+            scheduling = scheduling,
             runs = runs,
             mapped = mapped_paths,
+            ratio = mapped_ratio,
+            reroute = reroute_rounds,
+            rerouted = rerouted,
+            identical = identical,
+            its = iterations,
             collisions = retry_counts,
             comps = computations,
+            t_routed = thread_routed,
+            t_retries = thread_retries,
             cpu = cpu_results,
             res = results
         ))
@@ -156,12 +335,20 @@ fn main() {
         println!("[INFO] Successfully validated the maze.");
         println!("\nStatistics:");
         println!("    Maze configuration: {}", dimensions);
+        println!("    Router:             {}", router);
+        println!("    Order:              {}", order);
         println!("    Thread number:      {}", thread_number);
+        println!("    Scheduling:         {}", scheduling);
         println!("    Paths overall:      {}", paths.len());
         println!("    Runs:               {}", runs);
         println!("    Mapped:             {:?}", mapped_paths);
+        println!("    Mapped ratio:       {:?}", mapped_ratio);
+        println!("    Rerouted:           {:?}", rerouted);
+        println!("    Iterations:         {:?}", iterations);
         println!("    Collisions:         {:?}", retry_counts);
         println!("    Computations:       {:?}", computations);
+        println!("    Routed per thread:  {:?}", thread_routed);
+        println!("    Retries per thread: {:?}", thread_retries);
         println!("\nCPU time: {:?} ms", cpu_results);
         println!("Routing Time: {:?} ms", results);
     }
@@ -170,49 +357,70 @@ fn main() {
 fn route_paths(
     mut maze: Maze,
     mut to_map: Vec<(Point, Point)>,
+    router: Router,
     thread_number: usize,
-) -> (Maze, usize) {
-    let mut global_retries = 0;
-    // partition the vec
-    let l = to_map.len() / thread_number;
-    let mut rest = to_map.len() % thread_number;
-
-    let mut paths_to_map = vec![Vec::with_capacity(l); thread_number];
-
-    for t_num in 0..thread_number {
-        if rest > 0 {
-            paths_to_map[t_num] = to_map.split_off(to_map.len() - l - 1);
-            rest -= 1;
-        } else {
-            if to_map.len() <= l {
-                paths_to_map[t_num] = to_map.split_off(0);
-            } else {
-                paths_to_map[t_num] = to_map.split_off(to_map.len() - l);
+    scheduling: Scheduling,
+) -> (Maze, Vec<(usize, usize)>) {
+    let mut handles = Vec::new();
+
+    match scheduling {
+        Scheduling::Static => {
+            // partition the vec
+            let l = to_map.len() / thread_number;
+            let mut rest = to_map.len() % thread_number;
+
+            let mut paths_to_map = vec![Vec::with_capacity(l); thread_number];
+
+            for t_num in 0..thread_number {
+                if rest > 0 {
+                    paths_to_map[t_num] = to_map.split_off(to_map.len() - l - 1);
+                    rest -= 1;
+                } else {
+                    if to_map.len() <= l {
+                        paths_to_map[t_num] = to_map.split_off(0);
+                    } else {
+                        paths_to_map[t_num] = to_map.split_off(to_map.len() - l);
+                    }
+                }
             }
-        }
-    }
 
-    let mut handles = Vec::new();
+            for points in paths_to_map.drain(..) {
+                let g = maze.grid.clone();
+                handles.push(thread::spawn(move || route(&g, points, &router)));
+            }
+        }
+        Scheduling::Queue => {
+            // all threads share the input and claim the next pair by bumping the index
+            let queue = Arc::new(to_map);
+            let next = Arc::new(AtomicUsize::new(0));
 
-    for points in paths_to_map.drain(..) {
-        let g = maze.grid.clone();
-        handles.push(thread::spawn(move || route(&g, points)));
+            for _ in 0..thread_number {
+                let g = maze.grid.clone();
+                let q = queue.clone();
+                let n = next.clone();
+                handles.push(thread::spawn(move || route_from_queue(&g, &q, &n, &router)));
+            }
+        }
     }
 
+    // per thread: (routed pairs, retries)
+    let mut thread_stats = Vec::with_capacity(thread_number);
+
     for handle in handles {
         let (mut mapped, mut not_mapped, retries) = handle.join().unwrap();
+        thread_stats.push((mapped.len() + not_mapped.len(), retries));
         maze.paths.append(&mut mapped);
         maze.unmappable_paths.append(&mut not_mapped);
-        global_retries += retries;
     }
 
-    (maze, global_retries)
+    (maze, thread_stats)
 }
 
 /// Attempts to route the paths from `to_map` on he grid using STM.
 fn route(
     grid: &StmGrid,
     mut to_map: Vec<(Point, Point)>,
+    router: &Router,
 ) -> (Vec<Path>, Vec<(Point, Point)>, usize) {
     let mut mapped = Vec::new();
     let mut unmappable_paths = Vec::new();
@@ -220,37 +428,8 @@ fn route(
 
     // search for a path for all point pairs (sort out any pairs w/o path)
     for pair in to_map.drain(..) {
-        #[cfg(feature = "naive")]
-        let ta_result = atomically(|trans| {
-            if let Some(path) = pathfinder::find_path(pair.clone(), &grid, trans)? {
-                stm_grid::update_grid(&grid, &path, trans)?;
-                Ok(Some(path))
-            } else {
-                Ok(None)
-            }
-        });
-
-        #[cfg(not(feature = "naive"))]
-        let ta_result = atomically(|trans| {
-            let copy_grid = stm_grid::create_working_copy(&grid);
-            if let Some(path) = pathfinder::find_path(pair.clone(), &copy_grid) {
-                stm_grid::update_grid(&grid, &path, trans)?;
-                Ok(Some(path))
-            } else {
-                Ok(None)
-            }
-        });
-
-        //match ta_result {
-            //(Some(path), retries) => {
-                //mapped.push(path);
-                //overall_retries += retries;
-            //}
-            //(None, retries) => {
-                //unmappable_paths.push(pair);
-                //overall_retries += retries;
-            //}
-        //}
+        let (ta_result, retries) = route_pair(grid, pair, router);
+        overall_retries += retries;
         match ta_result {
             Some(path) => mapped.push(path),
             None => unmappable_paths.push(pair),
@@ -259,3 +438,63 @@ fn route(
 
     (mapped, unmappable_paths, overall_retries)
 }
+
+/// Routes pairs from the shared `queue` until all of them have been claimed.
+fn route_from_queue(
+    grid: &StmGrid,
+    queue: &[(Point, Point)],
+    next: &AtomicUsize,
+    router: &Router,
+) -> (Vec<Path>, Vec<(Point, Point)>, usize) {
+    let mut mapped = Vec::new();
+    let mut unmappable_paths = Vec::new();
+    let mut overall_retries = 0;
+
+    loop {
+        let idx = next.fetch_add(1, Ordering::Relaxed);
+        if idx >= queue.len() {
+            break;
+        }
+
+        let pair = queue[idx];
+        let (ta_result, retries) = route_pair(grid, pair, router);
+        overall_retries += retries;
+        match ta_result {
+            Some(path) => mapped.push(path),
+            None => unmappable_paths.push(pair),
+        }
+    }
+
+    (mapped, unmappable_paths, overall_retries)
+}
+
+/// Routes a single pair in a transaction. Returns the path, if any, and the number of retries.
+fn route_pair(grid: &StmGrid, pair: (Point, Point), router: &Router) -> (Option<Path>, usize) {
+    // every execution of the transaction body beyond the first one is a retry
+    let attempts = Cell::new(0);
+
+    #[cfg(feature = "naive")]
+    let ta_result = atomically(|trans| {
+        attempts.set(attempts.get() + 1);
+        if let Some(path) = pathfinder::find_path(pair.clone(), &grid, trans)? {
+            stm_grid::update_grid(&grid, &path, trans)?;
+            Ok(Some(path))
+        } else {
+            Ok(None)
+        }
+    });
+
+    #[cfg(not(feature = "naive"))]
+    let ta_result = atomically(|trans| {
+        attempts.set(attempts.get() + 1);
+        let copy_grid = stm_grid::create_working_copy(&grid);
+        if let Some(path) = pathfinder::find_path(pair.clone(), &copy_grid, router) {
+            stm_grid::update_grid(&grid, &path, trans)?;
+            Ok(Some(path))
+        } else {
+            Ok(None)
+        }
+    });
+
+    (ta_result, attempts.get() - 1)
+}
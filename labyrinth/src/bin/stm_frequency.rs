@@ -1,10 +1,17 @@
 use clap::{App, Arg};
+use labyrinth::compare::{self, RoutingResult};
+use labyrinth::export::ImageFormat;
 use labyrinth::parser;
+use labyrinth::router::Router;
+use labyrinth::schedule::PathOrder;
 use labyrinth::pathfinder;
 use labyrinth::stm_grid;
-use labyrinth::types::{Maze, Path, Point, StmGrid};
+use labyrinth::stmseq::types::{Maze, Path, StmGrid};
+use labyrinth::types::Point;
+use std::cell::Cell;
 use std::fs::{create_dir_all, File};
 use std::io::Write;
+use std::process;
 use std::str::FromStr;
 use std::thread;
 use stm::atomically;
@@ -58,6 +65,52 @@ fn main() {
                 .help("The update frequency for the maze data structure. Determines, after how many mapped paths all threads must synchronize.")
                 .default_value("4")
         )
+        .arg(
+            Arg::with_name("router")
+                .long("router")
+                .takes_value(true)
+                .help("The routing algorithm to use: bfs | astar | lee[:<x cost>,<y cost>,<via cost>]")
+                .default_value("bfs")
+        )
+        .arg(
+            Arg::with_name("order")
+                .long("order")
+                .takes_value(true)
+                .help("The order in which the pairs are routed: file | longest | shortest | clustered | random[:<seed>]")
+                .default_value("file")
+        )
+        .arg(
+            Arg::with_name("reroute")
+                .long("reroute")
+                .takes_value(true)
+                .help("Maximal number of rip-up-and-reroute rounds for the unmappable paths after routing (0 disables the phase).")
+                .default_value("0")
+        )
+        .arg(
+            Arg::with_name("render")
+                .long("render")
+                .takes_value(true)
+                .help("Writes an image of every layer of the routed maze (of the last run) into the given directory.")
+        )
+        .arg(
+            Arg::with_name("renderformat")
+                .long("render-format")
+                .takes_value(true)
+                .help("Image format for --render: ppm | svg")
+                .default_value("svg")
+        )
+        .arg(
+            Arg::with_name("dumppaths")
+                .long("dump-paths")
+                .takes_value(true)
+                .help("Writes the routed paths and unmappable pairs (of the last run) in canonical form into the given file.")
+        )
+        .arg(
+            Arg::with_name("comparewith")
+                .long("compare-with")
+                .takes_value(true)
+                .help("Compares the routed paths of every run against a file written with --dump-paths (e.g., by the sequential version).")
+        )
         .get_matches();
 
     // thread number
@@ -75,23 +128,71 @@ fn main() {
     // #runs
     let runs = usize::from_str(matches.value_of("runs").unwrap_or("1")).unwrap();
 
+    // rendering of the routed maze
+    let render_dir = matches.value_of("render");
+    let render_format = ImageFormat::from_str(matches.value_of("renderformat").unwrap())
+        .expect("Could not parse the image format");
+
+    // determinism checks
+    let dump_file = matches.value_of("dumppaths");
+    let reference = matches.value_of("comparewith").map(|f| match RoutingResult::read_file(f) {
+        Ok(res) => res,
+        Err(e) => {
+            eprintln!("[ERROR] Failed to read reference {}: {}", f, e);
+            process::exit(1);
+        }
+    });
+
+    // routing algorithm
+    let router = match Router::from_str(matches.value_of("router").unwrap()) {
+        Ok(r) => r,
+        Err(e) => {
+            eprintln!("[ERROR] {}", e);
+            process::exit(1);
+        }
+    };
+
+    // routing order of the pairs
+    let order = match PathOrder::from_str(matches.value_of("order").unwrap()) {
+        Ok(o) => o,
+        Err(e) => {
+            eprintln!("[ERROR] {}", e);
+            process::exit(1);
+        }
+    };
+
+    // rip-up-and-reroute phase
+    let reroute_rounds = usize::from_str(matches.value_of("reroute").unwrap())
+        .expect("The entered number of reroute rounds was not a valid uint");
+
     // input location & parsing
     let input_file = matches.value_of("INPUT").unwrap();
-    let (dimensions, paths) = parser::parse_file(input_file);
+    let (dimensions, mut paths, walls) = match parser::parse_file(input_file) {
+        Ok(input) => input,
+        Err(e) => {
+            eprintln!("[ERROR] Failed to parse {}: {}", input_file, e);
+            process::exit(1);
+        }
+    };
+    order.apply(&mut paths);
 
     let mut results = Vec::with_capacity(runs);
     let mut mapped_paths = Vec::with_capacity(runs);
+    let mut identical = Vec::with_capacity(runs);
+    let mut rerouted = Vec::with_capacity(runs);
+    let mut iterations = Vec::with_capacity(runs);
     let mut retry_counts = Vec::with_capacity(runs);
 
-    for _ in 0..runs {
-        let maze = Maze::new(dimensions.clone(), None);
+    for r in 0..runs {
+        let maze = Maze::new(dimensions.clone(), walls.clone());
 
         if !json_dump {
             println!("[INFO] Loaded maze data from file.");
         }
 
         let start = PreciseTime::now();
-        let (filled_maze, retries) = route_paths(maze, paths.clone(), thread_number, freq);
+        let (mut filled_maze, retries, rounds) = route_paths(maze, paths.clone(), router, thread_number, freq);
+        let reroute_stats = filled_maze.reroute(&router, reroute_rounds);
         let end = PreciseTime::now();
 
         if !json_dump {
@@ -103,13 +204,41 @@ fn main() {
         if filled_maze.is_valid() {
             results.push(runtime_ms);
             mapped_paths.push(filled_maze.paths.len());
+            rerouted.push(reroute_stats.rerouted);
+            iterations.push(rounds);
             retry_counts.push(retries);
+            if let Some(reference) = &reference {
+                let differences = filled_maze.routing_result().diff(reference);
+                if !json_dump {
+                    compare::print_differences(&differences);
+                }
+                identical.push(differences.is_empty());
+            }
+            if let Some(file) = dump_file {
+                if r + 1 == runs {
+                    if let Err(e) = filled_maze.routing_result().write_file(file) {
+                        eprintln!("[ERROR] Could not write the routed paths: {}", e);
+                    }
+                }
+            }
+            if let Some(dir) = render_dir {
+                if r + 1 == runs {
+                    if let Err(e) = filled_maze.render(dir, render_format) {
+                        eprintln!("[ERROR] Could not render the maze: {}", e);
+                    }
+                }
+            }
         } else {
             eprintln!("Incorrect path mappings found in maze: {:?}", filled_maze);
             return;
         }
     }
 
+    let mapped_ratio: Vec<f64> = mapped_paths
+        .iter()
+        .map(|&m| m as f64 / paths.len().max(1) as f64)
+        .collect();
+
     if json_dump {
         create_dir_all(out_dir).unwrap();
         let filename = format!(
@@ -124,20 +253,34 @@ fn main() {
         f.write_fmt(format_args!(
             "{{
     \"configuration\": \"{conf}\",
+    \"router\": \"{router}\",
+    \"order\": \"{order}\",
     \"paths\": {paths},
     \"threads\": {threads},
     \"frequency\": {freq},
     \"runs\": {runs},
     \"mapped\": {mapped:?},
+    \"mapped_ratio\": {ratio:?},
+    \"reroute_rounds\": {reroute},
+    \"rerouted\": {rerouted:?},
+    \"matches_reference\": {identical:?},
+    \"iterations\": {its:?},
     \"collisions\": {collisions:?},
     \"results\": {res:?}
 }}",
             conf = dimensions,
+            router = router,
+            order = order,
             paths = paths.len(),
             threads = thread_number,
             freq = freq,
             runs = runs,
             mapped = mapped_paths,
+            ratio = mapped_ratio,
+            reroute = reroute_rounds,
+            rerouted = rerouted,
+            identical = identical,
+            its = iterations,
             collisions = retry_counts,
             res = results
         ))
@@ -146,11 +289,16 @@ fn main() {
         println!("[INFO] Successfully validated the maze.");
         println!("\nStatistics:");
         println!("    Maze configuration: {}", dimensions);
+        println!("    Router:             {}", router);
+        println!("    Order:              {}", order);
         println!("    Thread number:      {}", thread_number);
         println!("    Update frequency:   {}", freq);
         println!("    Paths overall:      {}", paths.len());
         println!("    Runs:               {}", runs);
         println!("    Mapped:             {:?}", mapped_paths);
+        println!("    Mapped ratio:       {:?}", mapped_ratio);
+        println!("    Rerouted:           {:?}", rerouted);
+        println!("    Iterations:         {:?}", iterations);
         println!("    Collisions:         {:?}", retry_counts);
         println!("\nRouting Time: {:?} ms", results);
     }
@@ -159,17 +307,18 @@ fn main() {
 fn route_paths(
     mut maze: Maze,
     mut to_map: Vec<(Point, Point)>,
+    router: Router,
     thread_number: usize,
     freq: usize,
-) -> (Maze, usize) {
+) -> (Maze, usize, usize) {
     let mut global_retries = 0;
+    let mut rounds = 0;
 
     while !to_map.is_empty() {
-        let mut get_mapped = if to_map.len() <= freq {
-            to_map.split_off(0)
-        } else {
-            to_map.split_off(to_map.len() - freq)
-        };
+        rounds += 1;
+        // take the next batch from the front to keep the routing order of the input
+        let rest = to_map.split_off(freq.min(to_map.len()));
+        let mut get_mapped = std::mem::replace(&mut to_map, rest);
 
         // partition the vec
         let l = get_mapped.len() / thread_number;
@@ -194,7 +343,7 @@ fn route_paths(
 
         for points in paths_to_map.drain(..) {
             let g = maze.grid.clone();
-            handles.push(thread::spawn(move || route(&g, points)));
+            handles.push(thread::spawn(move || route(&g, points, &router)));
         }
 
         for handle in handles {
@@ -205,13 +354,14 @@ fn route_paths(
         }
     }
 
-    (maze, global_retries)
+    (maze, global_retries, rounds)
 }
 
 /// Attempts to route the paths from `to_map` on he grid using STM.
 fn route(
     grid: &StmGrid,
     mut to_map: Vec<(Point, Point)>,
+    router: &Router,
 ) -> (Vec<Path>, Vec<(Point, Point)>, usize) {
     let mut mapped = Vec::new();
     let mut unmappable_paths = Vec::new();
@@ -219,10 +369,13 @@ fn route(
 
     // search for a path for all point pairs (sort out any pairs w/o path)
     for pair in to_map.drain(..) {
+        // every execution of the transaction body beyond the first one is a retry
+        let attempts = Cell::new(0);
+
         let ta_result = atomically(|trans| {
+            attempts.set(attempts.get() + 1);
             let copy_grid = stm_grid::create_working_copy(&grid);
-            if let Some(path) = pathfinder::find_path(pair.clone(), &copy_grid) {
-                // if let Some(path) = pathfinder::find_path(pair.clone(), &grid, trans)? {
+            if let Some(path) = pathfinder::find_path(pair.clone(), &copy_grid, router) {
                 stm_grid::update_grid(&grid, &path, trans)?;
                 Ok(Some(path))
             } else {
@@ -230,15 +383,10 @@ fn route(
             }
         });
 
+        overall_retries += attempts.get() - 1;
         match ta_result {
-            (Some(path), retries) => {
-                mapped.push(path);
-                overall_retries += retries;
-            }
-            (None, retries) => {
-                unmappable_paths.push(pair);
-                overall_retries += retries;
-            }
+            Some(path) => mapped.push(path),
+            None => unmappable_paths.push(pair),
         }
     }
 
@@ -0,0 +1,317 @@
+use crate::types::Point;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+/// The outcome of routing a single pair: the routed points or `None` if it was unmappable.
+type Outcome = Option<Vec<Point>>;
+
+/// Canonical representation of a routing result that is independent of the order in which the
+/// paths have been mapped. Used to check whether different runtimes produce the same mazes.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct RoutingResult {
+    /// All outcomes for each pair of endpoints, sorted
+    pairs: BTreeMap<(Point, Point), Vec<Outcome>>,
+}
+
+/// A pair of endpoints whose outcome differs between two routing results.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Difference {
+    pub pair: (Point, Point),
+    /// Outcomes in the result being checked
+    pub found: Vec<Outcome>,
+    /// Outcomes in the reference result
+    pub expected: Vec<Outcome>,
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (start, end) = self.pair;
+        let same_shape = self.found.len() == self.expected.len()
+            && self
+                .found
+                .iter()
+                .zip(self.expected.iter())
+                .all(|(a, b)| a.is_some() == b.is_some());
+        if same_shape {
+            return write!(f, "{} -> {}: routed on a different path", start, end);
+        }
+
+        write!(
+            f,
+            "{} -> {}: {} (reference: {})",
+            start,
+            end,
+            describe(&self.found),
+            describe(&self.expected)
+        )
+    }
+}
+
+fn describe(outcomes: &[Outcome]) -> String {
+    if outcomes.is_empty() {
+        return "missing".to_string();
+    }
+
+    outcomes
+        .iter()
+        .map(|o| match o {
+            Some(path) => format!("routed over {} fields", path.len()),
+            None => "unmappable".to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+impl RoutingResult {
+    /// Collects the mapped `paths` (as start, end and points) and the `unmappable` pairs.
+    pub fn new<'a, I>(paths: I, unmappable: &[(Point, Point)]) -> Self
+    where
+        I: IntoIterator<Item = (Point, Point, &'a [Point])>,
+    {
+        let mut res = RoutingResult::default();
+
+        for (start, end, path) in paths {
+            res.insert((start, end), Some(path.to_vec()));
+        }
+        for pair in unmappable {
+            res.insert(*pair, None);
+        }
+
+        res
+    }
+
+    fn insert(&mut self, pair: (Point, Point), outcome: Outcome) {
+        let outcomes = self.pairs.entry(pair).or_insert_with(Vec::new);
+        outcomes.push(outcome);
+        outcomes.sort();
+    }
+
+    /// Returns all pairs whose outcome differs from the one in `reference`.
+    pub fn diff(&self, reference: &RoutingResult) -> Vec<Difference> {
+        let mut keys: Vec<&(Point, Point)> =
+            self.pairs.keys().chain(reference.pairs.keys()).collect();
+        keys.sort();
+        keys.dedup();
+
+        keys.into_iter()
+            .filter_map(|pair| {
+                let found = self.pairs.get(pair).cloned().unwrap_or_default();
+                let expected = reference.pairs.get(pair).cloned().unwrap_or_default();
+
+                if found != expected {
+                    Some(Difference {
+                        pair: *pair,
+                        found,
+                        expected,
+                    })
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// Writes the result in its canonical text form. Each line holds one outcome:
+    /// `m <start> <end> <points...>` for mapped and `u <start> <end>` for unmappable pairs.
+    pub fn write<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        for (&(start, end), outcomes) in &self.pairs {
+            for outcome in outcomes {
+                match outcome {
+                    Some(path) => {
+                        write!(out, "m {} {}", fmt_point(&start), fmt_point(&end))?;
+                        for pt in path {
+                            write!(out, " {}", fmt_point(pt))?;
+                        }
+                        writeln!(out)?;
+                    }
+                    None => writeln!(out, "u {} {}", fmt_point(&start), fmt_point(&end))?,
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes the canonical form into the file at `path`.
+    pub fn write_file<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        self.write(&mut out)?;
+        out.flush()
+    }
+
+    /// Reads a result in canonical form as written by `write_file`.
+    pub fn read_file<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let f = BufReader::new(File::open(path)?);
+        let mut res = RoutingResult::default();
+
+        for (idx, line) in f.lines().enumerate() {
+            let line = line?;
+            let mut tokens = line.split_whitespace();
+            let kind = match tokens.next() {
+                Some(k) => k,
+                None => continue,
+            };
+
+            let coords = tokens
+                .map(str::parse::<usize>)
+                .collect::<Result<Vec<usize>, _>>()
+                .map_err(|e| invalid_data(idx + 1, &e.to_string()))?;
+            if coords.len() < 6 || coords.len() % 3 != 0 {
+                return Err(invalid_data(idx + 1, "incomplete point"));
+            }
+            let mut points = coords.chunks(3).map(|c| Point {
+                x: c[0],
+                y: c[1],
+                z: c[2],
+            });
+            let pair = (points.next().unwrap(), points.next().unwrap());
+
+            match kind {
+                "m" => res.insert(pair, Some(points.collect())),
+                "u" => res.insert(pair, None),
+                other => return Err(invalid_data(idx + 1, &format!("unknown kind `{}`", other))),
+            }
+        }
+
+        Ok(res)
+    }
+}
+
+fn fmt_point(pt: &Point) -> String {
+    format!("{} {} {}", pt.x, pt.y, pt.z)
+}
+
+fn invalid_data(line: usize, msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("line {}: {}", line, msg))
+}
+
+/// Prints a summary of the `differences` to a reference result.
+pub fn print_differences(differences: &[Difference]) {
+    /// Maximal number of differing pairs to list individually
+    const MAX_LISTED: usize = 10;
+
+    if differences.is_empty() {
+        println!("[INFO] Routing result matches the reference.");
+        return;
+    }
+
+    println!(
+        "[WARN] Routing result differs from the reference in {} pairs:",
+        differences.len()
+    );
+    for d in differences.iter().take(MAX_LISTED) {
+        println!("    {}", d);
+    }
+    if differences.len() > MAX_LISTED {
+        println!("    ... and {} more", differences.len() - MAX_LISTED);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pt(x: usize, y: usize, z: usize) -> Point {
+        Point { x, y, z }
+    }
+
+    fn straight(start: Point, end: Point) -> Vec<Point> {
+        (start.x..=end.x).map(|x| pt(x, start.y, start.z)).collect()
+    }
+
+    /// Two mapped pairs and an unmappable one, the mapped paths are given in `order`.
+    fn result(order: &[usize], unmappable: &[(Point, Point)]) -> RoutingResult {
+        let pairs = [(pt(0, 0, 0), pt(3, 0, 0)), (pt(0, 1, 0), pt(2, 1, 0))];
+        let paths: Vec<Vec<Point>> = pairs.iter().map(|&(s, e)| straight(s, e)).collect();
+
+        RoutingResult::new(
+            order
+                .iter()
+                .map(|&i| (pairs[i].0, pairs[i].1, paths[i].as_slice())),
+            unmappable,
+        )
+    }
+
+    #[test]
+    fn identical_results() {
+        let unmappable = [(pt(0, 2, 0), pt(3, 2, 1))];
+        let res = result(&[0, 1], &unmappable);
+
+        // the order in which the paths have been mapped does not matter
+        assert_eq!(result(&[1, 0], &unmappable), res);
+        assert!(res.diff(&result(&[1, 0], &unmappable)).is_empty());
+
+        let file = std::env::temp_dir().join(format!("labyrinth-compare-{}", std::process::id()));
+        res.write_file(&file).unwrap();
+        let text = std::fs::read_to_string(&file).unwrap();
+        let read = RoutingResult::read_file(&file).unwrap();
+        std::fs::remove_file(&file).unwrap();
+
+        assert_eq!(
+            text,
+            "m 0 0 0 3 0 0 0 0 0 1 0 0 2 0 0 3 0 0\n\
+             m 0 1 0 2 1 0 0 1 0 1 1 0 2 1 0\n\
+             u 0 2 0 3 2 1\n"
+        );
+        assert_eq!(read, res);
+        assert!(read.diff(&res).is_empty());
+    }
+
+    #[test]
+    fn differently_routed_pair() {
+        let reference = result(&[0, 1], &[]);
+        let (start, end) = (pt(0, 1, 0), pt(2, 1, 0));
+        let first = straight(pt(0, 0, 0), pt(3, 0, 0));
+        let detour = vec![start, pt(0, 2, 0), pt(1, 2, 0), pt(2, 2, 0), end];
+        let res = RoutingResult::new(
+            vec![
+                (first[0], first[3], first.as_slice()),
+                (start, end, detour.as_slice()),
+            ],
+            &[],
+        );
+
+        let differences = res.diff(&reference);
+        assert_eq!(
+            differences,
+            vec![Difference {
+                pair: (start, end),
+                found: vec![Some(detour)],
+                expected: vec![Some(straight(start, end))],
+            }]
+        );
+        assert_eq!(
+            differences[0].to_string(),
+            "0x1x0 -> 2x1x0: routed on a different path"
+        );
+    }
+
+    #[test]
+    fn unmappable_on_one_side() {
+        let reference = result(&[0, 1], &[]);
+        let (start, end) = (pt(0, 1, 0), pt(2, 1, 0));
+        let first = straight(pt(0, 0, 0), pt(3, 0, 0));
+        let res = RoutingResult::new(
+            vec![(first[0], first[3], first.as_slice())],
+            &[(start, end)],
+        );
+
+        let differences = res.diff(&reference);
+        assert_eq!(differences.len(), 1);
+        assert_eq!(differences[0].pair, (start, end));
+        assert_eq!(
+            differences[0].to_string(),
+            "0x1x0 -> 2x1x0: unmappable (reference: routed over 3 fields)"
+        );
+
+        // the other way round, the reference could not map the pair
+        let reversed = reference.diff(&res);
+        assert_eq!(
+            reversed[0].to_string(),
+            "0x1x0 -> 2x1x0: routed over 3 fields (reference: unmappable)"
+        );
+    }
+}
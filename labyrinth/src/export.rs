@@ -0,0 +1,332 @@
+use crate::types::*;
+use std::fs::{create_dir_all, File};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use strum_macros::{Display, EnumString};
+
+/// Edge length of a single field in pixels (PPM) or user units (SVG).
+const CELL_SIZE: usize = 8;
+
+const FREE_COLOR: (u8, u8, u8) = (255, 255, 255);
+const WALL_COLOR: (u8, u8, u8) = (40, 40, 40);
+/// Color for fields that are marked as used but do not belong to any known path
+const USED_COLOR: (u8, u8, u8) = (160, 160, 160);
+const ENDPOINT_COLOR: (u8, u8, u8) = (0, 0, 0);
+const UNMAPPED_COLOR: (u8, u8, u8) = (220, 0, 0);
+
+#[derive(Clone, Copy, Debug, Display, EnumString, PartialEq)]
+#[strum(serialize_all = "lowercase")]
+pub enum ImageFormat {
+    Ppm,
+    Svg,
+}
+
+/// Writes one image per z-layer of the `grid` into `dir` (named `layer-<z>.<format>`).
+///
+/// Walls, free fields and each of the `paths` are colored distinctly, path endpoints are marked
+/// in black and the endpoints of `unmappable` pairs in red.
+pub fn render_layers<P: AsRef<Path>, G: FieldGrid>(
+    dir: P,
+    format: ImageFormat,
+    grid: &G,
+    paths: &[Vec<Point>],
+    unmappable: &[(Point, Point)],
+) -> io::Result<()> {
+    create_dir_all(&dir)?;
+    for z in 0..grid.dimensions().z {
+        let filename = dir.as_ref().join(format!("layer-{}.{}", z, format));
+        let mut out = BufWriter::new(File::create(filename)?);
+        match format {
+            ImageFormat::Ppm => write_ppm(&mut out, grid, paths, unmappable, z)?,
+            ImageFormat::Svg => write_svg(&mut out, grid, paths, unmappable, z)?,
+        }
+        out.flush()?;
+    }
+
+    Ok(())
+}
+
+/// Writes layer `z` as binary PPM image.
+pub fn write_ppm<W: Write, G: FieldGrid>(
+    out: &mut W,
+    grid: &G,
+    paths: &[Vec<Point>],
+    unmappable: &[(Point, Point)],
+    z: usize,
+) -> io::Result<()> {
+    let colors = layer_colors(grid, paths, z);
+    let (width, height) = (grid.dimensions().x, grid.dimensions().y);
+    let mut marks = vec![vec![None; height]; width];
+    for path in paths {
+        for pt in path.first().into_iter().chain(path.last()) {
+            if pt.z == z {
+                marks[pt.x][pt.y] = Some(ENDPOINT_COLOR);
+            }
+        }
+    }
+    for (start, end) in unmappable {
+        for pt in &[start, end] {
+            if pt.z == z {
+                marks[pt.x][pt.y] = Some(UNMAPPED_COLOR);
+            }
+        }
+    }
+
+    write!(out, "P6\n{} {}\n255\n", width * CELL_SIZE, height * CELL_SIZE)?;
+
+    let mut row = Vec::with_capacity(width * CELL_SIZE * 3);
+    for y in 0..height {
+        for py in 0..CELL_SIZE {
+            row.clear();
+            for x in 0..width {
+                for px in 0..CELL_SIZE {
+                    // endpoints are drawn as a square in the center of the field
+                    let inner = (CELL_SIZE / 4..CELL_SIZE - CELL_SIZE / 4).contains(&px)
+                        && (CELL_SIZE / 4..CELL_SIZE - CELL_SIZE / 4).contains(&py);
+                    let (r, g, b) = match marks[x][y] {
+                        Some(c) if inner => c,
+                        _ => colors[x][y],
+                    };
+                    row.extend_from_slice(&[r, g, b]);
+                }
+            }
+            out.write_all(&row)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Writes layer `z` as SVG image.
+pub fn write_svg<W: Write, G: FieldGrid>(
+    out: &mut W,
+    grid: &G,
+    paths: &[Vec<Point>],
+    unmappable: &[(Point, Point)],
+    z: usize,
+) -> io::Result<()> {
+    let colors = layer_colors(grid, paths, z);
+    let (width, height) = (grid.dimensions().x, grid.dimensions().y);
+    let radius = CELL_SIZE as f64 / 4.0;
+
+    writeln!(
+        out,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\" shape-rendering=\"crispEdges\">",
+        w = width * CELL_SIZE,
+        h = height * CELL_SIZE
+    )?;
+    writeln!(out, "<title>Layer {}</title>", z)?;
+
+    for x in 0..width {
+        for y in 0..height {
+            let (r, g, b) = colors[x][y];
+            writeln!(
+                out,
+                "<rect x=\"{}\" y=\"{}\" width=\"{s}\" height=\"{s}\" fill=\"#{:02x}{:02x}{:02x}\"/>",
+                x * CELL_SIZE,
+                y * CELL_SIZE,
+                r,
+                g,
+                b,
+                s = CELL_SIZE
+            )?;
+        }
+    }
+
+    let endpoints = paths
+        .iter()
+        .flat_map(|p| p.first().into_iter().chain(p.last()))
+        .map(|pt| (pt, ENDPOINT_COLOR));
+    let unmapped = unmappable
+        .iter()
+        .flat_map(|(start, end)| vec![start, end])
+        .map(|pt| (pt, UNMAPPED_COLOR));
+    for (pt, (r, g, b)) in endpoints.chain(unmapped).filter(|(pt, _)| pt.z == z) {
+        writeln!(
+            out,
+            "<circle cx=\"{}\" cy=\"{}\" r=\"{}\" fill=\"#{:02x}{:02x}{:02x}\"><title>{}</title></circle>",
+            (pt.x as f64 + 0.5) * CELL_SIZE as f64,
+            (pt.y as f64 + 0.5) * CELL_SIZE as f64,
+            radius,
+            r,
+            g,
+            b,
+            pt
+        )?;
+    }
+
+    writeln!(out, "</svg>")
+}
+
+/// Computes the fill color of every field in layer `z`.
+fn layer_colors<G: FieldGrid>(
+    grid: &G,
+    paths: &[Vec<Point>],
+    z: usize,
+) -> Vec<Vec<(u8, u8, u8)>> {
+    let dims = grid.dimensions();
+    let mut colors: Vec<Vec<(u8, u8, u8)>> = (0..dims.x)
+        .map(|x| {
+            (0..dims.y)
+                .map(|y| match grid.field(&Point { x, y, z }) {
+                    Field::Free => FREE_COLOR,
+                    Field::Wall => WALL_COLOR,
+                    Field::Used => USED_COLOR,
+                })
+                .collect()
+        })
+        .collect();
+
+    for (idx, path) in paths.iter().enumerate() {
+        let color = path_color(idx);
+        for pt in path.iter().filter(|pt| pt.z == z) {
+            colors[pt.x][pt.y] = color;
+        }
+    }
+
+    colors
+}
+
+/// Picks a distinct color for the path with index `idx` by rotating the hue by the golden angle.
+fn path_color(idx: usize) -> (u8, u8, u8) {
+    let hue = (idx as f64 * 137.508) % 360.0;
+    let (s, v) = (0.65, 0.9);
+
+    let c = v * s;
+    let h = hue / 60.0;
+    let x = c * (1.0 - (h % 2.0 - 1.0).abs());
+    let (r, g, b) = match h as usize {
+        0 => (c, x, 0.0),
+        1 => (x, c, 0.0),
+        2 => (0.0, c, x),
+        3 => (0.0, x, c),
+        4 => (x, 0.0, c),
+        _ => (c, 0.0, x),
+    };
+    let m = v - c;
+
+    (
+        ((r + m) * 255.0) as u8,
+        ((g + m) * 255.0) as u8,
+        ((b + m) * 255.0) as u8,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 3x3x2 grid with a wall in the center of layer 0 and a path from the upper left corner of
+    /// layer 0 to the upper right corner of layer 1.
+    fn maze() -> (FlatGrid<Field>, Vec<Vec<Point>>) {
+        let mut grid = FlatGrid::new(Point { x: 3, y: 3, z: 2 }, Field::Free);
+        grid[Point { x: 1, y: 1, z: 0 }] = Field::Wall;
+        let path = vec![
+            Point { x: 0, y: 0, z: 0 },
+            Point { x: 1, y: 0, z: 0 },
+            Point { x: 2, y: 0, z: 0 },
+            Point { x: 2, y: 0, z: 1 },
+        ];
+        for pt in &path {
+            grid[*pt] = Field::Used;
+        }
+
+        (grid, vec![path])
+    }
+
+    fn ppm(z: usize) -> Vec<u8> {
+        let (grid, paths) = maze();
+        let mut out = Vec::new();
+        write_ppm(&mut out, &grid, &paths, &[], z).unwrap();
+        out
+    }
+
+    fn svg(z: usize) -> String {
+        let (grid, paths) = maze();
+        let mut out = Vec::new();
+        write_svg(&mut out, &grid, &paths, &[], z).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    fn hex((r, g, b): (u8, u8, u8)) -> String {
+        format!("#{:02x}{:02x}{:02x}", r, g, b)
+    }
+
+    #[test]
+    fn ppm_layers() {
+        let header = b"P6\n24 24\n255\n";
+        let pixel = |image: &[u8], x: usize, y: usize| {
+            let idx = header.len() + (y * 3 * CELL_SIZE + x) * 3;
+            (image[idx], image[idx + 1], image[idx + 2])
+        };
+
+        let layer0 = ppm(0);
+        assert!(layer0.starts_with(header));
+        assert_eq!(layer0.len(), header.len() + 24 * 24 * 3);
+        // endpoints are marked in the center of their field only
+        assert_eq!(pixel(&layer0, 4, 4), ENDPOINT_COLOR);
+        assert_eq!(pixel(&layer0, 0, 0), path_color(0));
+        assert_eq!(pixel(&layer0, 8, 0), path_color(0));
+        assert_eq!(pixel(&layer0, 12, 12), WALL_COLOR);
+        assert_eq!(pixel(&layer0, 16, 16), FREE_COLOR);
+        assert_eq!(pixel(&layer0, 20, 4), path_color(0));
+
+        let layer1 = ppm(1);
+        assert!(layer1.starts_with(header));
+        assert_eq!(pixel(&layer1, 4, 4), FREE_COLOR);
+        assert_eq!(pixel(&layer1, 12, 12), FREE_COLOR);
+        assert_eq!(pixel(&layer1, 20, 4), ENDPOINT_COLOR);
+        assert_eq!(pixel(&layer1, 16, 0), path_color(0));
+    }
+
+    #[test]
+    fn svg_layers() {
+        let rect = |x: usize, y: usize, color| {
+            format!(
+                "<rect x=\"{}\" y=\"{}\" width=\"8\" height=\"8\" fill=\"{}\"/>",
+                x * CELL_SIZE,
+                y * CELL_SIZE,
+                hex(color)
+            )
+        };
+        let endpoint = |x: usize, y: usize| {
+            format!(
+                "<circle cx=\"{}\" cy=\"{}\" r=\"2\" fill=\"{}\">",
+                x * CELL_SIZE + 4,
+                y * CELL_SIZE + 4,
+                hex(ENDPOINT_COLOR)
+            )
+        };
+
+        let layer0 = svg(0);
+        let header = "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"24\" height=\"24\"";
+        assert!(layer0.starts_with(header));
+        assert!(layer0.contains("<title>Layer 0</title>"));
+        assert!(layer0.ends_with("</svg>\n"));
+        assert_eq!(layer0.matches("<rect ").count(), 9);
+        assert!(layer0.contains(&rect(1, 1, WALL_COLOR)));
+        assert!(layer0.contains(&rect(1, 0, path_color(0))));
+        assert!(layer0.contains(&rect(2, 2, FREE_COLOR)));
+        assert!(layer0.contains(&endpoint(0, 0)));
+        assert!(!layer0.contains(&endpoint(2, 0)));
+
+        let layer1 = svg(1);
+        assert!(layer1.contains("<title>Layer 1</title>"));
+        assert!(layer1.contains(&rect(1, 1, FREE_COLOR)));
+        assert!(layer1.contains(&rect(2, 0, path_color(0))));
+        assert!(layer1.contains(&endpoint(2, 0)));
+        assert_eq!(layer1.matches("<circle ").count(), 1);
+    }
+
+    #[test]
+    fn render_all_layers() {
+        let dir = std::env::temp_dir().join(format!("labyrinth-export-{}", std::process::id()));
+        let (grid, paths) = maze();
+        render_layers(&dir, ImageFormat::Ppm, &grid, &paths, &[]).unwrap();
+
+        assert_eq!(std::fs::read(dir.join("layer-0.ppm")).unwrap(), ppm(0));
+        assert_eq!(std::fs::read(dir.join("layer-1.ppm")).unwrap(), ppm(1));
+        assert!(!dir.join("layer-2.ppm").exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
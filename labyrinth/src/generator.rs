@@ -0,0 +1,276 @@
+use crate::parser::Maze;
+use crate::types::Point;
+use rand::Rng;
+use rand_chacha::rand_core::SeedableRng;
+use rand_chacha::ChaCha12Rng;
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Write};
+
+/// Number of attempts to find a matching destination for a source point before a new source
+/// point is drawn.
+const DESTINATION_ATTEMPTS: usize = 1000;
+
+/// Number of source points in a row without a matching destination after which the generator
+/// gives up.
+const SOURCE_ATTEMPTS: usize = 1000;
+
+/// Parameters for generating a random maze.
+#[derive(Clone, Debug)]
+pub struct GeneratorConfig {
+    /// Seed for the random number generator
+    pub seed: u64,
+    /// Size of the maze
+    pub dimensions: Point,
+    /// Number of paths to generate
+    pub paths: usize,
+    /// Upper bound for the Manhattan distance between the endpoints of a path, if any
+    pub max_path_length: Option<usize>,
+    /// Fraction of the remaining fields (0.0 to 1.0) that are turned into walls
+    pub obstacle_density: f64,
+}
+
+/// Reasons why no maze could be generated for a `GeneratorConfig`.
+#[derive(Debug, PartialEq)]
+pub enum GenerateError {
+    /// The maximal path length is 0
+    ZeroPathLength,
+    /// The maze has fewer fields than the paths have endpoints
+    TooManyPaths { dimensions: Point, paths: usize },
+    /// No free pair of endpoints within the maximal path length was found for the remaining paths
+    Exhausted { placed: usize, paths: usize },
+}
+
+impl fmt::Display for GenerateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GenerateError::ZeroPathLength => {
+                write!(f, "the maximal path length must be at least 1")
+            }
+            GenerateError::TooManyPaths { dimensions, paths } => write!(
+                f,
+                "a maze of size {} cannot hold {} paths",
+                dimensions, paths
+            ),
+            GenerateError::Exhausted { placed, paths } => write!(
+                f,
+                "could only place {} of {} paths, no free endpoints are left for the others",
+                placed, paths
+            ),
+        }
+    }
+}
+
+impl Error for GenerateError {}
+
+/// Generates a random maze according to the `config`. The result has the same layout as the
+/// return value of `parser::parse_file` and the same seed always yields the same maze.
+///
+/// Endpoints are never shared between paths and never covered by walls. Fails if the maze is
+/// too small to hold all path endpoints, or if no free endpoints within the maximal path length
+/// are found for a path after `SOURCE_ATTEMPTS` source points in a row.
+pub fn generate(config: &GeneratorConfig) -> Result<Maze, GenerateError> {
+    let dims = config.dimensions;
+    let field_count = dims.x * dims.y * dims.z;
+    if config.max_path_length == Some(0) {
+        return Err(GenerateError::ZeroPathLength);
+    }
+    if config.paths * 2 > field_count {
+        return Err(GenerateError::TooManyPaths {
+            dimensions: dims,
+            paths: config.paths,
+        });
+    }
+
+    let mut rng = ChaCha12Rng::seed_from_u64(config.seed);
+    let mut used = HashSet::with_capacity(config.paths * 2);
+    let mut paths = Vec::with_capacity(config.paths);
+    let mut failed_sources = 0;
+
+    while paths.len() < config.paths {
+        let src = random_point(&mut rng, &dims);
+        if used.contains(&src) {
+            continue;
+        }
+
+        let placed = paths.len();
+        for _ in 0..DESTINATION_ATTEMPTS {
+            let dst = match config.max_path_length {
+                Some(len) => random_point_near(&mut rng, &dims, &src, len),
+                None => random_point(&mut rng, &dims),
+            };
+
+            if dst != src && !used.contains(&dst) {
+                used.insert(src);
+                used.insert(dst);
+                paths.push((src, dst));
+                break;
+            }
+        }
+
+        if paths.len() > placed {
+            failed_sources = 0;
+        } else {
+            failed_sources += 1;
+            if failed_sources == SOURCE_ATTEMPTS {
+                return Err(GenerateError::Exhausted {
+                    placed,
+                    paths: config.paths,
+                });
+            }
+        }
+    }
+
+    // place walls on all remaining fields with the given probability
+    let mut walls = Vec::new();
+    if config.obstacle_density > 0.0 {
+        for x in 0..dims.x {
+            for y in 0..dims.y {
+                for z in 0..dims.z {
+                    let pt = Point { x, y, z };
+                    if !used.contains(&pt) && rng.gen_bool(config.obstacle_density.min(1.0)) {
+                        walls.push(pt);
+                    }
+                }
+            }
+        }
+    }
+
+    let walls = if walls.is_empty() { None } else { Some(walls) };
+
+    Ok((dims, paths, walls))
+}
+
+/// Writes a maze in the input format understood by `parser::parse_file`.
+pub fn write_input<W: Write>(
+    out: &mut W,
+    dimensions: &Point,
+    paths: &[(Point, Point)],
+    walls: &Option<Vec<Point>>,
+) -> io::Result<()> {
+    writeln!(out, "# Dimensions (x, y, z)")?;
+    writeln!(out, "d  {} {} {}", dimensions.x, dimensions.y, dimensions.z)?;
+
+    if let Some(walls) = walls {
+        writeln!(out)?;
+        writeln!(out, "# Walls (x, y, z)")?;
+        for w in walls {
+            writeln!(out, "w   {:3} {:3} {:1}", w.x, w.y, w.z)?;
+        }
+    }
+
+    writeln!(out)?;
+    writeln!(out, "# Paths: Sources (x, y, z) -> Destinations (x, y, z)")?;
+    for (src, dst) in paths {
+        writeln!(
+            out,
+            "p   {:3} {:3} {:1}   {:3} {:3} {:1}",
+            src.x, src.y, src.z, dst.x, dst.y, dst.z
+        )?;
+    }
+
+    Ok(())
+}
+
+fn random_point(rng: &mut ChaCha12Rng, dims: &Point) -> Point {
+    Point {
+        x: rng.gen_range(0, dims.x),
+        y: rng.gen_range(0, dims.y),
+        z: rng.gen_range(0, dims.z),
+    }
+}
+
+/// Draws a point with a Manhattan distance of at most `max_dist` to `origin`.
+fn random_point_near(rng: &mut ChaCha12Rng, dims: &Point, origin: &Point, max_dist: usize) -> Point {
+    let mut remaining = max_dist;
+    let x = random_coordinate(rng, origin.x, dims.x, &mut remaining);
+    let y = random_coordinate(rng, origin.y, dims.y, &mut remaining);
+    let z = random_coordinate(rng, origin.z, dims.z, &mut remaining);
+
+    Point { x, y, z }
+}
+
+/// Picks a coordinate within `remaining` steps of `origin` and deducts the distance taken.
+fn random_coordinate(rng: &mut ChaCha12Rng, origin: usize, bound: usize, remaining: &mut usize) -> usize {
+    let low = origin.saturating_sub(*remaining);
+    let high = std::cmp::min(origin + *remaining, bound - 1);
+    let c = rng.gen_range(low, high + 1);
+
+    *remaining -= if c > origin { c - origin } else { origin - c };
+    c
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(
+        seed: u64,
+        dimensions: Point,
+        paths: usize,
+        max_path_length: Option<usize>,
+    ) -> GeneratorConfig {
+        GeneratorConfig {
+            seed,
+            dimensions,
+            paths,
+            max_path_length,
+            obstacle_density: 0.1,
+        }
+    }
+
+    #[test]
+    fn same_seed_same_maze() {
+        let dims = Point { x: 16, y: 16, z: 3 };
+        let maze = generate(&config(7, dims, 20, None)).unwrap();
+
+        assert_eq!(generate(&config(7, dims, 20, None)).unwrap(), maze);
+        assert_ne!(generate(&config(8, dims, 20, None)).unwrap(), maze);
+
+        let (dimensions, paths, walls) = maze;
+        assert_eq!(dimensions, dims);
+        assert_eq!(paths.len(), 20);
+        let endpoints: HashSet<Point> = paths.iter().flat_map(|&(s, d)| vec![s, d]).collect();
+        assert_eq!(endpoints.len(), 40);
+        assert!(walls.unwrap().iter().all(|w| !endpoints.contains(w)));
+    }
+
+    #[test]
+    fn paths_within_max_length() {
+        let dims = Point { x: 32, y: 32, z: 4 };
+        let (_, paths, _) = generate(&config(3, dims, 50, Some(3))).unwrap();
+
+        assert_eq!(paths.len(), 50);
+        for (src, dst) in paths {
+            let dist = (src.x as isize - dst.x as isize).abs()
+                + (src.y as isize - dst.y as isize).abs()
+                + (src.z as isize - dst.z as isize).abs();
+            assert!((1..=3).contains(&dist), "{} -> {} too far apart", src, dst);
+        }
+    }
+
+    #[test]
+    fn infeasible_mazes() {
+        let line = Point { x: 4, y: 1, z: 1 };
+        // the first path takes the two middle fields, the outer ones are too far apart
+        assert_eq!(
+            generate(&config(2, line, 2, Some(1))),
+            Err(GenerateError::Exhausted {
+                placed: 1,
+                paths: 2
+            })
+        );
+        assert_eq!(
+            generate(&config(0, line, 3, None)),
+            Err(GenerateError::TooManyPaths {
+                dimensions: line,
+                paths: 3
+            })
+        );
+        assert_eq!(
+            generate(&config(0, line, 1, Some(0))),
+            Err(GenerateError::ZeroPathLength)
+        );
+    }
+}
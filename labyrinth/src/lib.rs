@@ -1,4 +1,10 @@
+pub mod compare;
+pub mod export;
+pub mod generator;
 pub mod parser;
+pub mod reroute;
+pub mod router;
+pub mod schedule;
 pub mod types;
 pub mod stmseq;
 pub mod ohua;
@@ -1,26 +1,13 @@
+use crate::compare::RoutingResult;
+use crate::export::{self, ImageFormat};
+use crate::reroute::{self, RerouteStats};
 use crate::ohua::grid::*;
+use crate::router::Router;
 use crate::types::*;
-use std::collections::LinkedList;
+use std::collections::HashMap;
+use std::io;
 use std::sync::Arc;
 
-/// This data structure contains information on whether a point has been visited before and
-/// information on how to get back from the end point to the start.
-/// Each point is assigned the previous point in the path to allow easy backtracking.
-#[derive(Clone, Copy, Debug, Default, PartialEq)]
-enum PointStatus {
-    #[default]
-    Unvisited,
-    BacktrackInfo(Option<Point>)
-}
-
-impl PointStatus {
-    #[inline]
-    pub fn unvisited(&self) -> bool {
-        *self == Self::Unvisited
-    }
-}
-
-
 // dummy data structures for now
 #[derive(Clone, Debug)]
 pub struct Maze {
@@ -32,40 +19,79 @@ pub struct Maze {
     pub paths: Vec<Path>,
     /// Paths that could not be mapped
     pub unmappable_paths: Vec<(Point, Point)>,
+    /// The algorithm used to search for paths
+    pub router: Router,
+    /// Number of paths that collided with previously mapped paths and had to be recomputed
+    pub retries: usize,
+    /// Number of `fill` iterations done so far
+    pub iterations: usize,
 }
 
 impl Maze {
-    /// Initialize the maze, for now w/o obstacles
-    pub fn init(dimensions: Point) -> Self {
+    /// Initialize the maze, optionally with obstacles
+    pub fn init(dimensions: Point, obstacles: Option<Vec<Point>>, router: Router) -> Self {
         Maze {
-            grid: initialize_grid(dimensions.x, dimensions.y, dimensions.z, &None),
-            obstacles: None,
+            grid: initialize_grid(dimensions.x, dimensions.y, dimensions.z, &obstacles),
+            obstacles,
             paths: Vec::new(),
             unmappable_paths: Vec::new(),
+            router,
+            retries: 0,
+            iterations: 0,
         }
     }
 
     /// Updates the labyrinth structure, returns the start and end point if the update was
     /// unsuccessful
-    pub fn update(&mut self, path: Option<Path>
-    //              , retry_sender: &std::sync::mpsc::Sender<usize>
-    ) -> Option<(Point, Point)> {
+    pub fn update(&mut self, path: Option<Path>) -> Option<(Point, Point)> {
         let path = path?;
 
         if path_available(&self.grid, &path) {
             for pt in &path.path {
-                self.grid[pt.x][pt.y][pt.z] = Field::Used;
+                self.grid[*pt] = Field::Used;
             }
             self.paths.push(path);
             None
         } else {
-//            retry_sender.send(1).unwrap();
+            self.retries += 1;
             Some((path.start, path.end))
         }
     }
 
-    pub fn update_paths(&mut self, paths: Vec<Option<Path>>) -> Vec<OPoint> {
-        paths.into_iter().map(|p| self.update(p)).collect()
+    /// Returns a read-only view of the current grid for the path searches of one `fill`
+    /// iteration. Only the grid chunks updated while the snapshot is alive get copied.
+    pub fn snapshot(&self) -> Arc<Snapshot> {
+        Arc::new(Snapshot {
+            grid: self.grid.clone(),
+            router: self.router,
+        })
+    }
+
+    /// Marks the end of a `fill` iteration, passes the update results through unchanged.
+    pub fn finish_iteration(&mut self, results: Vec<OPoint>) -> Vec<OPoint> {
+        self.iterations += 1;
+        results
+    }
+
+    /// Stores all pairs from `requested` that have not been mapped as unmappable. `fill` drops
+    /// pairs without a path silently, so this has to be done once routing is complete.
+    pub fn record_unmappable(&mut self, requested: &[(Point, Point)]) {
+        let mut mapped: HashMap<(Point, Point), usize> = HashMap::new();
+        for p in &self.paths {
+            *mapped.entry((p.start, p.end)).or_insert(0) += 1;
+        }
+
+        self.unmappable_paths = requested
+            .iter()
+            .filter(|pair| match mapped.get_mut(pair) {
+                Some(count) if *count > 0 => {
+                    *count -= 1;
+                    false
+                }
+                _ => true,
+            })
+            .cloned()
+            .collect();
     }
 
     pub fn is_valid(&self) -> bool {
@@ -74,7 +100,7 @@ impl Maze {
         for path in &self.paths {
             for pt in &path.path {
                 if at_grid_coordinates(&ctrl_grid, &pt) == &Field::Used {
-                    ctrl_grid[pt.x][pt.y][pt.z] = Field::Free;
+                    ctrl_grid[*pt] = Field::Free;
                 } else {
                     return false;
                 }
@@ -83,6 +109,48 @@ impl Maze {
 
         true
     }
+
+    /// Writes an image of every layer of the maze into `dir`, see `export::render_layers`.
+    pub fn render<P: AsRef<std::path::Path>>(&self, dir: P, format: ImageFormat) -> io::Result<()> {
+        let paths: Vec<Vec<Point>> = self.paths.iter().map(|p| p.path.clone()).collect();
+        export::render_layers(dir, format, &self.grid, &paths, &self.unmappable_paths)
+    }
+
+    /// Tries to map the unmappable paths by ripping up the paths blocking them, see
+    /// `reroute::rip_up_and_reroute`. Requires `record_unmappable` to be called before.
+    pub fn reroute(&mut self, max_rounds: usize) -> RerouteStats {
+        if max_rounds == 0 || self.unmappable_paths.is_empty() {
+            return RerouteStats::default();
+        }
+
+        let mut paths: Vec<Vec<Point>> = self.paths.drain(..).map(|p| p.path).collect();
+        let stats = reroute::rip_up_and_reroute(
+            &mut self.grid,
+            &mut paths,
+            &mut self.unmappable_paths,
+            &self.router,
+            max_rounds,
+        );
+
+        self.paths = paths
+            .into_iter()
+            .map(|path| Path {
+                start: path[0],
+                end: path[path.len() - 1],
+                path,
+            })
+            .collect();
+
+        stats
+    }
+
+    /// Returns the canonical routing result of this maze for comparisons between runtimes.
+    pub fn routing_result(&self) -> RoutingResult {
+        RoutingResult::new(
+            self.paths.iter().map(|p| (p.start, p.end, &p.path[..])),
+            &self.unmappable_paths,
+        )
+    }
 }
 
 pub fn path_available(grid: &Grid, path: &Path) -> bool {
@@ -95,6 +163,13 @@ pub fn path_available(grid: &Grid, path: &Path) -> bool {
     true
 }
 
+/// The state of the grid at the beginning of a `fill` iteration, see `Maze::snapshot`.
+#[derive(Debug)]
+pub struct Snapshot {
+    pub grid: Grid,
+    pub router: Router,
+}
+
 /// A single path in the maze.
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct Path {
@@ -106,116 +181,13 @@ pub struct Path {
     pub path: Vec<Point>,
 }
 
-pub fn find_path(maze: Arc<Maze>, pair: Option<(Point, Point)>) -> Option<Path> {
-    // TODO: Add costs?
+pub fn find_path(snapshot: Arc<Snapshot>, pair: Option<(Point, Point)>) -> Option<Path> {
     let (start, end) = pair.unwrap();
 
-    // check if the route is still available
-    if at_grid_coordinates(&maze.grid, &start) != &Field::Free {
-        return None;
-    }
-
-    let mut point_status = vec![vec![vec![PointStatus::Unvisited; maze.grid[0][0].len()]; maze.grid[0].len()]; maze.grid.len()];
-    let mut unseen_points = LinkedList::new();
-
-    // set the start point
-    point_status[start.x][start.y][start.z] = PointStatus::BacktrackInfo(None);
-    unseen_points.push_back(start);
-
-    while !unseen_points.is_empty() {
-        let current = unseen_points.pop_front().unwrap();
-
-        // stop when reacing the end node
-        if current == end {
-            return Some(generate_path(current, &point_status));
-        }
-
-        // get a list of all possible successors
-        for child in get_successors(&current, &maze.grid) {
-            // sort out anything that has been seen or is blocked
-            match at_grid_coordinates(&maze.grid, &child) {
-                &Field::Used => continue,
-                &Field::Wall => continue,
-                &Field::Free => (),
-            }
-
-            if point_status[child.x][child.y][child.z].unvisited() {
-                point_status[child.x][child.y][child.z] = PointStatus::BacktrackInfo(Some(current));
-                unseen_points.push_back(child);
-            }
-        }
-    }
-
-    // All points have been processed and no path was found
-    None
-}
-
-fn get_successors(cur: &Point, grid: &Grid) -> Vec<Point> {
-    let mut res = Vec::with_capacity(6);
-
-    if cur.x > 0 {
-        res.push(Point {
-            x: cur.x - 1,
-            y: cur.y,
-            z: cur.z,
-        });
-    }
-    if cur.x < grid.len() - 1 {
-        res.push(Point {
-            x: cur.x + 1,
-            y: cur.y,
-            z: cur.z,
-        });
-    }
-    if cur.y > 0 {
-        res.push(Point {
-            x: cur.x,
-            y: cur.y - 1,
-            z: cur.z,
-        });
-    }
-    if cur.y < grid[0].len() - 1 {
-        res.push(Point {
-            x: cur.x,
-            y: cur.y + 1,
-            z: cur.z,
-        });
-    }
-    if cur.z > 0 {
-        res.push(Point {
-            x: cur.x,
-            y: cur.y,
-            z: cur.z - 1,
-        });
-    }
-    if cur.z < grid[0][0].len() - 1 {
-        res.push(Point {
-            x: cur.x,
-            y: cur.y,
-            z: cur.z + 1,
-        });
-    }
-
-    res
-}
-
-fn generate_path(end_node: Point, meta_info: &Vec<Vec<Vec<PointStatus>>>) -> Path {
-    let mut path = vec![end_node];
-    let mut current = end_node;
-
-    while let PointStatus::BacktrackInfo(Some(next)) = meta_info[current.x][current.y][current.z] {
-        path.push(next);
-        current = next;
-    }
-
-    // important, we built the vec from end -> start
-    path.reverse();
-
-    Path {
-        start: current,
-        end: end_node,
-        path,
-    }
+    snapshot
+        .router
+        .route(start, end, &snapshot.grid)
+        .map(|path| Path { start, end, path })
 }
 
 pub type OPoint = Option<(Point, Point)>;
@@ -261,17 +233,6 @@ impl UnmappedPaths for Vec<OPoint> {
     }
 }
 
-pub trait Unarc {
-    fn unarc(&self, item: Arc<Maze>) -> Maze {
-        match Arc::try_unwrap(item) {
-            Ok(ap) => ap,
-            _ => panic!("Failed to unwrap the Arc. Please make sure that the construction of `x` has destructed all previous Arcs.")
-        }
-    }
-}
-
-impl Unarc for Vec<Option<Path>> {}
-
 pub fn filter_paths(mut unm: Unmapped) -> Unmapped {
     unm.filter_mapped();
     unm
@@ -1,7 +1,73 @@
 use crate::types::*;
+use std::ops::{Index, IndexMut};
+use std::sync::Arc;
+
+/// Number of fields stored in a single chunk of the `Grid`.
+const CHUNK_SIZE: usize = 1024;
 
 /// The grid the maze is set up in. Contains for every field the information about its state.
-pub type Grid = Vec<Vec<Vec<Field>>>;
+///
+/// The fields are stored in the layout of a `FlatGrid`, split into chunks that are shared
+/// between clones of the grid (copy-on-write). Cloning only copies the chunk pointers and a
+/// write copies the affected chunk only if another clone still refers to it. A snapshot taken
+/// at the beginning of a `fill` iteration thus only costs the chunks written during that
+/// iteration.
+#[derive(Clone, Debug)]
+pub struct Grid {
+    dimensions: Point,
+    chunks: Vec<Arc<[Field; CHUNK_SIZE]>>,
+}
+
+impl Grid {
+    fn new(dimensions: Point) -> Self {
+        // the last chunk may extend beyond the grid, those fields are never accessed
+        let len = dimensions.x * dimensions.y * dimensions.z;
+        let chunks = (0..len)
+            .step_by(CHUNK_SIZE)
+            .map(|_| Arc::new([Field::Free; CHUNK_SIZE]))
+            .collect();
+
+        Grid { dimensions, chunks }
+    }
+
+    /// Chunk and offset within the chunk of the field at `pt`.
+    #[inline]
+    fn position(&self, pt: &Point) -> (usize, usize) {
+        let idx = (pt.x * self.dimensions.y + pt.y) * self.dimensions.z + pt.z;
+        (idx / CHUNK_SIZE, idx % CHUNK_SIZE)
+    }
+}
+
+impl FieldGrid for Grid {
+    #[inline]
+    fn dimensions(&self) -> Point {
+        self.dimensions
+    }
+
+    #[inline]
+    fn field(&self, pt: &Point) -> Field {
+        self[*pt]
+    }
+}
+
+impl Index<Point> for Grid {
+    type Output = Field;
+
+    #[inline]
+    fn index(&self, pt: Point) -> &Field {
+        let (chunk, offset) = self.position(&pt);
+        &self.chunks[chunk][offset]
+    }
+}
+
+impl IndexMut<Point> for Grid {
+    /// Copies the chunk containing `pt` first if it is shared with another grid.
+    #[inline]
+    fn index_mut(&mut self, pt: Point) -> &mut Field {
+        let (chunk, offset) = self.position(&pt);
+        &mut Arc::make_mut(&mut self.chunks[chunk])[offset]
+    }
+}
 
 /// Initializes a new, empty grid, optionally with pre-defined walls which can be provided using the `walls` parameter.
 ///
@@ -12,12 +78,17 @@ pub fn initialize_grid(
     depth: usize,
     walls: &Option<Vec<Point>>,
 ) -> Grid {
-    let mut grid = vec![vec![vec![Field::Free; depth]; height]; width];
+    let dimensions = Point {
+        x: width,
+        y: height,
+        z: depth,
+    };
+    let mut grid = Grid::new(dimensions);
 
     // place walls if any
     if let Some(wall_vec) = walls {
         for wall in wall_vec {
-            grid[wall.x][wall.y][wall.z] = Field::Wall;
+            grid[*wall] = Field::Wall;
         }
     }
 
@@ -25,5 +96,27 @@ pub fn initialize_grid(
 }
 
 pub fn at_grid_coordinates<'a>(grid: &'a Grid, pt: &Point) -> &'a Field {
-    &grid[pt.x][pt.y][pt.z]
+    &grid[*pt]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clones_are_copy_on_write() {
+        let mut grid = initialize_grid(40, 40, 2, &None);
+        let snapshot = grid.clone();
+        let pt = Point { x: 3, y: 5, z: 1 };
+
+        grid[pt] = Field::Used;
+
+        assert_eq!(snapshot[pt], Field::Free);
+        assert_eq!(grid[pt], Field::Used);
+        // only the written chunk has been copied
+        let (written, _) = grid.position(&pt);
+        for (idx, (a, b)) in grid.chunks.iter().zip(&snapshot.chunks).enumerate() {
+            assert_eq!(Arc::ptr_eq(a, b), idx != written);
+        }
+    }
 }
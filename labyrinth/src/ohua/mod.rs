@@ -2,4 +2,3 @@ mod benchs;
 pub mod generated;
 mod grid;
 pub mod original;
-pub mod less_arc;
@@ -1,45 +1,307 @@
 use crate::types::Point;
+use std::error::Error;
+use std::fmt;
 use std::fs::File;
-use std::io::{BufRead, BufReader};
+use std::io::{self, BufRead, BufReader};
 use std::path::Path;
 use std::str::SplitWhitespace;
 
-pub fn parse_file<P: AsRef<Path>>(path: P) -> (Point, Vec<(Point, Point)>) {
-    // FIXME: Error handling for IO, dimension/path check
-    let f = File::open(path).unwrap();
-    let f = BufReader::new(f);
+/// Dimensions, paths to map and walls (if any have been declared) of a maze.
+pub type Maze = (Point, Vec<(Point, Point)>, Option<Vec<Point>>);
 
-    let mut dimensions = Point { x: 0, y: 0, z: 0 };
+/// Errors that may occur while reading an input file. All variants except `Io` and
+/// `MissingDimensions` carry the (1-based) line number the problem was found at.
+#[derive(Debug)]
+pub enum ParseError {
+    /// The file could not be opened or read
+    Io(io::Error),
+    /// A line started with a token that is not part of the input format
+    UnknownToken { line: usize, token: String },
+    /// A line ended before all coordinates of a point had been read
+    MissingCoordinate { line: usize },
+    /// A coordinate could not be parsed as unsigned integer
+    NonNumeric { line: usize, field: String },
+    /// A point lies outside of the dimensions declared in the `d` line
+    OutOfBounds { line: usize, point: Point, dimensions: Point },
+    /// A path starts and ends at the same point
+    DuplicateEndpoint { line: usize, point: Point },
+    /// The file does not contain a `d` line
+    MissingDimensions,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::Io(e) => write!(f, "could not read input file: {}", e),
+            ParseError::UnknownToken { line, token } => {
+                write!(f, "line {}: invalid token `{}` at line start", line, token)
+            }
+            ParseError::MissingCoordinate { line } => {
+                write!(f, "line {}: missing coordinate", line)
+            }
+            ParseError::NonNumeric { line, field } => {
+                write!(f, "line {}: `{}` is not a valid coordinate", line, field)
+            }
+            ParseError::OutOfBounds {
+                line,
+                point,
+                dimensions,
+            } => write!(
+                f,
+                "line {}: point {} lies outside of the maze dimensions {}",
+                line, point, dimensions
+            ),
+            ParseError::DuplicateEndpoint { line, point } => write!(
+                f,
+                "line {}: path starts and ends at the same point {}",
+                line, point
+            ),
+            ParseError::MissingDimensions => write!(f, "no dimensions (`d` line) declared"),
+        }
+    }
+}
+
+impl Error for ParseError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ParseError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ParseError {
+    fn from(e: io::Error) -> Self {
+        ParseError::Io(e)
+    }
+}
+
+/// Parses an input file describing the maze.
+///
+/// Lines starting with `#` are comments. Supported line types are:
+/// - `d x y z`: dimensions of the maze
+/// - `p x1 y1 z1 x2 y2 z2`: a path to be routed between the two points
+/// - `w x y z`: a single wall (obstacle) field
+/// - `b x1 y1 z1 x2 y2 z2`: a box of walls spanning both corner points (inclusive)
+///
+/// Returns the dimensions, the paths to map and the walls, if any have been declared.
+pub fn parse_file<P: AsRef<Path>>(path: P) -> Result<Maze, ParseError> {
+    let f = File::open(path)?;
+    parse(BufReader::new(f))
+}
+
+/// Parses a maze description in the format of `parse_file` from `reader`.
+pub fn parse<R: BufRead>(reader: R) -> Result<Maze, ParseError> {
+    let mut dimensions = None;
+    // every point is stored along with the line it was declared in for later bounds checks
     let mut paths = Vec::new();
+    let mut walls = Vec::new();
+    // boxes are only expanded once their corners are known to lie within the maze
+    let mut boxes = Vec::new();
 
-    for line in f.lines() {
-        let l = line.unwrap().to_lowercase();
+    for (idx, line) in reader.lines().enumerate() {
+        let line_no = idx + 1;
+        let l = line?.to_lowercase();
         let mut it = l.split_whitespace();
 
         match it.next() {
-            Some("#") => continue,
-            Some("d") => dimensions = take_point(&mut it),
+            Some(x) if x.starts_with('#') => continue,
+            Some("d") => dimensions = Some(take_point(&mut it, line_no)?),
             Some("p") => {
-                let point1 = take_point(&mut it);
-                let point2 = take_point(&mut it);
-                paths.push((point1, point2));
+                let point1 = take_point(&mut it, line_no)?;
+                let point2 = take_point(&mut it, line_no)?;
+                paths.push((line_no, (point1, point2)));
+            }
+            Some("w") => walls.push((line_no, take_point(&mut it, line_no)?)),
+            Some("b") => {
+                let corner1 = take_point(&mut it, line_no)?;
+                let corner2 = take_point(&mut it, line_no)?;
+                boxes.push((line_no, (corner1, corner2)));
+            }
+            Some(x) => {
+                return Err(ParseError::UnknownToken {
+                    line: line_no,
+                    token: x.to_string(),
+                })
             }
-            Some(x) => panic!(
-                "Encountered malformed input file: Invalid token {} at line start.",
-                x
-            ),
             None => continue,
         }
     }
 
-    (dimensions, paths)
+    let dimensions = dimensions.ok_or(ParseError::MissingDimensions)?;
+
+    // validate all points against the declared dimensions.
+    // NOTE: Different paths may share an endpoint (the original STAMP inputs contain such
+    // pairs), only the later one will be unmappable then.
+    for &(line, (start, end)) in &paths {
+        check_bounds(&start, &dimensions, line)?;
+        check_bounds(&end, &dimensions, line)?;
+        if start == end {
+            return Err(ParseError::DuplicateEndpoint { line, point: start });
+        }
+    }
+    for (line, pt) in &walls {
+        check_bounds(pt, &dimensions, *line)?;
+    }
+    for &(line, (corner1, corner2)) in &boxes {
+        check_bounds(&corner1, &dimensions, line)?;
+        check_bounds(&corner2, &dimensions, line)?;
+        walls.extend(
+            expand_box(corner1, corner2)
+                .into_iter()
+                .map(|pt| (line, pt)),
+        );
+    }
+
+    let paths = paths.into_iter().map(|(_, pair)| pair).collect();
+    let walls = if walls.is_empty() {
+        None
+    } else {
+        Some(walls.into_iter().map(|(_, pt)| pt).collect())
+    };
+
+    Ok((dimensions, paths, walls))
+}
+
+fn take_point(it: &mut SplitWhitespace, line: usize) -> Result<Point, ParseError> {
+    Ok(Point {
+        x: take_coordinate(it, line)?,
+        y: take_coordinate(it, line)?,
+        z: take_coordinate(it, line)?,
+    })
+}
+
+fn take_coordinate(it: &mut SplitWhitespace, line: usize) -> Result<usize, ParseError> {
+    let field = it.next().ok_or(ParseError::MissingCoordinate { line })?;
+
+    field.parse::<usize>().map_err(|_| ParseError::NonNumeric {
+        line,
+        field: field.to_string(),
+    })
+}
+
+fn check_bounds(pt: &Point, dimensions: &Point, line: usize) -> Result<(), ParseError> {
+    if pt.x < dimensions.x && pt.y < dimensions.y && pt.z < dimensions.z {
+        Ok(())
+    } else {
+        Err(ParseError::OutOfBounds {
+            line,
+            point: *pt,
+            dimensions: *dimensions,
+        })
+    }
+}
+
+/// Returns all points contained in the box spanned by the two corners.
+fn expand_box(corner1: Point, corner2: Point) -> Vec<Point> {
+    let (x_min, x_max) = (corner1.x.min(corner2.x), corner1.x.max(corner2.x));
+    let (y_min, y_max) = (corner1.y.min(corner2.y), corner1.y.max(corner2.y));
+    let (z_min, z_max) = (corner1.z.min(corner2.z), corner1.z.max(corner2.z));
+
+    let mut points = Vec::new();
+    for x in x_min..=x_max {
+        for y in y_min..=y_max {
+            for z in z_min..=z_max {
+                points.push(Point { x, y, z });
+            }
+        }
+    }
+
+    points
 }
 
-fn take_point(it: &mut SplitWhitespace) -> Point {
-    // here comes the good code
-    Point {
-        x: it.next().unwrap().parse::<usize>().unwrap(),
-        y: it.next().unwrap().parse::<usize>().unwrap(),
-        z: it.next().unwrap().parse::<usize>().unwrap(),
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_str(input: &str) -> Result<Maze, ParseError> {
+        parse(input.as_bytes())
+    }
+
+    #[test]
+    fn parse_valid_input() {
+        let input = "# maze\nd 4 4 2\n#no space needed\nw 0 0 0\nb 1 1 0 2 2 1\np 0 3 0 3 0 1\n";
+        let (dimensions, paths, walls) = parse_str(input).unwrap();
+
+        assert_eq!(dimensions, Point { x: 4, y: 4, z: 2 });
+        assert_eq!(
+            paths,
+            vec![(Point { x: 0, y: 3, z: 0 }, Point { x: 3, y: 0, z: 1 })]
+        );
+        assert_eq!(walls.map(|w| w.len()), Some(1 + 8));
+    }
+
+    #[test]
+    fn io_error() {
+        match parse_file("/nonexistent/maze.txt") {
+            Err(ParseError::Io(_)) => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn unknown_token() {
+        match parse_str("d 2 2 1\n\nx 0 0 0\n") {
+            Err(ParseError::UnknownToken { line, token }) => {
+                assert_eq!(line, 3);
+                assert_eq!(token, "x");
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn missing_coordinate() {
+        match parse_str("d 2 2 1\np 0 0 0 1 1\n") {
+            Err(ParseError::MissingCoordinate { line }) => assert_eq!(line, 2),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn non_numeric() {
+        match parse_str("# walls\nd 2 2 1\nw 0 -1 0\n") {
+            Err(ParseError::NonNumeric { line, field }) => {
+                assert_eq!(line, 3);
+                assert_eq!(field, "-1");
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn out_of_bounds() {
+        match parse_str("d 2 2 1\nw 1 1 0\nw 1 2 0\n") {
+            Err(ParseError::OutOfBounds { line, point, .. }) => {
+                assert_eq!(line, 3);
+                assert_eq!(point, Point { x: 1, y: 2, z: 0 });
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        // the box is rejected before it is expanded, the dimensions may come last
+        match parse_str("b 0 0 0 100000 100000 100000\nd 2 2 1\n") {
+            Err(ParseError::OutOfBounds { line, .. }) => assert_eq!(line, 1),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn duplicate_endpoint() {
+        match parse_str("d 2 2 1\np 0 0 0 1 1 0\np 1 0 0 1 0 0\n") {
+            Err(ParseError::DuplicateEndpoint { line, point }) => {
+                assert_eq!(line, 3);
+                assert_eq!(point, Point { x: 1, y: 0, z: 0 });
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn missing_dimensions() {
+        match parse_str("w 0 0 0\n") {
+            Err(ParseError::MissingDimensions) => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
     }
 }
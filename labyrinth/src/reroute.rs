@@ -0,0 +1,261 @@
+use crate::router::Router;
+use crate::types::*;
+use std::collections::BTreeSet;
+use std::ops::IndexMut;
+
+/// Marks fields that do not belong to any path in the owner grid.
+const NO_OWNER: usize = usize::MAX;
+
+/// Outcome of the rip-up-and-reroute phase.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RerouteStats {
+    /// Number of rounds over the unmappable pairs that have been done
+    pub rounds: usize,
+    /// Number of previously unmappable pairs that have been mapped
+    pub rerouted: usize,
+    /// Number of mapped paths that have been ripped up and routed again
+    pub ripped_up: usize,
+}
+
+/// Presents fields used by other paths as free, so that a search finds the paths that block a
+/// pair.
+struct IgnoreUsed<'a, G>(&'a G);
+
+impl<'a, G: FieldGrid> FieldGrid for IgnoreUsed<'a, G> {
+    fn dimensions(&self) -> Point {
+        self.0.dimensions()
+    }
+
+    fn field(&self, pt: &Point) -> Field {
+        match self.0.field(pt) {
+            Field::Used => Field::Free,
+            f => f,
+        }
+    }
+}
+
+/// Tries to map the `unmappable` pairs by ripping up the `paths` blocking them.
+///
+/// For each unmappable pair, a path is searched ignoring all used fields. The paths covering
+/// that route are removed from the `grid`, the pair is routed and the removed paths are routed
+/// again. If any of them cannot be mapped anymore, the previous state is restored. This is
+/// repeated over the remaining unmappable pairs until a round maps no further pair or
+/// `max_rounds` is reached, so the number of mapped paths never decreases.
+///
+/// The `paths` have to be mapped in `grid` and go from their first to their last point.
+pub fn rip_up_and_reroute<G>(
+    grid: &mut G,
+    paths: &mut Vec<Vec<Point>>,
+    unmappable: &mut Vec<(Point, Point)>,
+    router: &Router,
+    max_rounds: usize,
+) -> RerouteStats
+where
+    G: FieldGrid + IndexMut<Point, Output = Field>,
+{
+    let mut stats = RerouteStats::default();
+
+    // paths are kept in fixed slots, so that the owner grid stays valid when paths are removed
+    let mut slots: Vec<Option<Vec<Point>>> = paths.drain(..).map(Some).collect();
+    let mut owners = FlatGrid::new(grid.dimensions(), NO_OWNER);
+    for (id, path) in slots.iter().enumerate() {
+        for pt in path.iter().flatten() {
+            owners[*pt] = id;
+        }
+    }
+
+    while stats.rounds < max_rounds && !unmappable.is_empty() {
+        stats.rounds += 1;
+        let before = unmappable.len();
+        let mut still_unmappable = Vec::new();
+
+        for pair in unmappable.drain(..) {
+            match reroute_pair(grid, &mut slots, &mut owners, pair, router) {
+                Some(ripped_up) => {
+                    stats.rerouted += 1;
+                    stats.ripped_up += ripped_up;
+                }
+                None => still_unmappable.push(pair),
+            }
+        }
+
+        *unmappable = still_unmappable;
+        // another round can only succeed if this one changed the grid
+        if unmappable.len() == before {
+            break;
+        }
+    }
+
+    paths.extend(slots.into_iter().flatten());
+    stats
+}
+
+/// Maps the `pair`, ripping up and rerouting blocking paths if necessary. Returns the number of
+/// ripped up paths or `None` if the grid has been left unchanged.
+fn reroute_pair<G>(
+    grid: &mut G,
+    slots: &mut Vec<Option<Vec<Point>>>,
+    owners: &mut FlatGrid<usize>,
+    pair: (Point, Point),
+    router: &Router,
+) -> Option<usize>
+where
+    G: FieldGrid + IndexMut<Point, Output = Field>,
+{
+    let (start, end) = pair;
+
+    // the grid may have changed since the pair failed
+    if let Some(path) = router.route(start, end, grid) {
+        add_path(grid, slots, owners, path);
+        return Some(0);
+    }
+
+    // only walls block the pair
+    let route = router.route(start, end, &IgnoreUsed(&*grid))?;
+    let blockers: BTreeSet<usize> = route
+        .iter()
+        .map(|pt| owners[*pt])
+        .filter(|&id| id != NO_OWNER)
+        .collect();
+
+    let ripped: Vec<(usize, Vec<Point>)> = blockers
+        .iter()
+        .map(|&id| (id, remove_path(grid, slots, owners, id)))
+        .collect();
+
+    let mut added = Vec::with_capacity(ripped.len() + 1);
+    if let Some(path) = router.route(start, end, grid) {
+        added.push(add_path(grid, slots, owners, path));
+
+        for (_, old) in &ripped {
+            match router.route(old[0], old[old.len() - 1], grid) {
+                Some(path) => added.push(add_path(grid, slots, owners, path)),
+                None => break,
+            }
+        }
+    }
+
+    if added.len() == ripped.len() + 1 {
+        return Some(ripped.len());
+    }
+
+    // restore the previous state
+    for id in added {
+        remove_path(grid, slots, owners, id);
+    }
+    for (id, path) in ripped {
+        for pt in &path {
+            grid[*pt] = Field::Used;
+            owners[*pt] = id;
+        }
+        slots[id] = Some(path);
+    }
+
+    None
+}
+
+fn add_path<G: IndexMut<Point, Output = Field>>(
+    grid: &mut G,
+    slots: &mut Vec<Option<Vec<Point>>>,
+    owners: &mut FlatGrid<usize>,
+    path: Vec<Point>,
+) -> usize {
+    let id = slots.len();
+    for pt in &path {
+        grid[*pt] = Field::Used;
+        owners[*pt] = id;
+    }
+    slots.push(Some(path));
+
+    id
+}
+
+fn remove_path<G: IndexMut<Point, Output = Field>>(
+    grid: &mut G,
+    slots: &mut [Option<Vec<Point>>],
+    owners: &mut FlatGrid<usize>,
+    id: usize,
+) -> Vec<Point> {
+    let path = slots[id].take().expect("path has already been removed");
+    for pt in &path {
+        grid[*pt] = Field::Free;
+        owners[*pt] = NO_OWNER;
+    }
+
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pt(x: usize, y: usize, z: usize) -> Point {
+        Point { x, y, z }
+    }
+
+    /// Creates a single layer grid with the given mapped path.
+    fn grid_with(x: usize, y: usize, path: &[Point]) -> FlatGrid<Field> {
+        let mut grid = FlatGrid::new(pt(x, y, 1), Field::Free);
+        for p in path {
+            grid[*p] = Field::Used;
+        }
+
+        grid
+    }
+
+    fn is_consistent(grid: &FlatGrid<Field>, paths: &[Vec<Point>]) -> bool {
+        let mut ctrl = grid.clone();
+        for p in paths.iter().flatten() {
+            if ctrl[*p] != Field::Used {
+                return false;
+            }
+            ctrl[*p] = Field::Free;
+        }
+        ctrl.iter().all(|f| *f != Field::Used)
+    }
+
+    #[test]
+    fn reroutes_blocking_path() {
+        // the path from (0,0) to (4,0) takes a detour over the whole row 2 and blocks the pair
+        let detour = vec![
+            pt(0, 0, 0),
+            pt(0, 1, 0),
+            pt(0, 2, 0),
+            pt(1, 2, 0),
+            pt(2, 2, 0),
+            pt(3, 2, 0),
+            pt(4, 2, 0),
+            pt(4, 1, 0),
+            pt(4, 0, 0),
+        ];
+        let mut grid = grid_with(5, 4, &detour);
+        let mut paths = vec![detour];
+        let mut unmappable = vec![(pt(2, 1, 0), pt(2, 3, 0))];
+
+        let stats = rip_up_and_reroute(&mut grid, &mut paths, &mut unmappable, &Router::Bfs, 3);
+
+        assert!(unmappable.is_empty());
+        assert_eq!(paths.len(), 2);
+        assert_eq!(stats.rerouted, 1);
+        assert_eq!(stats.ripped_up, 1);
+        assert!(is_consistent(&grid, &paths));
+    }
+
+    #[test]
+    fn restores_paths_that_cannot_be_rerouted() {
+        // the straight path from (0,1) to (2,1) has no way around the pair
+        let blocking = vec![pt(0, 1, 0), pt(1, 1, 0), pt(2, 1, 0)];
+        let mut grid = grid_with(3, 3, &blocking);
+        let mut paths = vec![blocking];
+        let mut unmappable = vec![(pt(1, 0, 0), pt(1, 2, 0))];
+        let (orig_grid, orig_paths) = (grid.clone(), paths.clone());
+
+        let stats = rip_up_and_reroute(&mut grid, &mut paths, &mut unmappable, &Router::Bfs, 3);
+
+        assert_eq!(unmappable.len(), 1);
+        assert_eq!(paths, orig_paths);
+        assert_eq!(grid, orig_grid);
+        assert_eq!(stats.rerouted, 0);
+        assert_eq!(stats.rounds, 1);
+    }
+}
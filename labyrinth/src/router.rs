@@ -0,0 +1,519 @@
+use crate::types::*;
+use std::cmp::Reverse;
+use std::cell::RefCell;
+use std::collections::{BinaryHeap, VecDeque};
+use std::fmt;
+use std::str::FromStr;
+
+/// Costs used by the Lee expansion router for a single step along each axis. Moves along the
+/// z-axis are vias between layers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ExpansionCosts {
+    pub x: usize,
+    pub y: usize,
+    pub via: usize,
+}
+
+impl Default for ExpansionCosts {
+    /// The cost configuration of the original STAMP benchmark.
+    fn default() -> Self {
+        ExpansionCosts { x: 1, y: 1, via: 2 }
+    }
+}
+
+/// The algorithm used to search for a path between two points.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Router {
+    /// Plain breadth-first search
+    #[default]
+    Bfs,
+    /// A* search guided by the Manhattan distance to the end point
+    AStar,
+    /// Lee expansion on a cost grid followed by a traceback that avoids bends, as done by STAMP
+    Lee(ExpansionCosts),
+}
+
+impl fmt::Display for Router {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Router::Bfs => write!(f, "bfs"),
+            Router::AStar => write!(f, "astar"),
+            Router::Lee(c) => write!(f, "lee:{},{},{}", c.x, c.y, c.via),
+        }
+    }
+}
+
+impl FromStr for Router {
+    type Err = String;
+
+    /// Parses `bfs`, `astar` or `lee`. The costs of the Lee router may be given as
+    /// `lee:<x>,<y>,<via>`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.splitn(2, ':');
+        match (parts.next().map(str::to_lowercase).as_deref(), parts.next()) {
+            (Some("bfs"), None) => Ok(Router::Bfs),
+            (Some("astar"), None) => Ok(Router::AStar),
+            (Some("lee"), None) => Ok(Router::Lee(ExpansionCosts::default())),
+            (Some("lee"), Some(costs)) => {
+                let costs: Vec<usize> = costs
+                    .split(',')
+                    .map(|c| c.trim().parse::<usize>())
+                    .collect::<Result<_, _>>()
+                    .map_err(|_| format!("Invalid expansion costs `{}`", costs))?;
+                match costs[..] {
+                    [x, y, via] if x > 0 && y > 0 && via > 0 => {
+                        Ok(Router::Lee(ExpansionCosts { x, y, via }))
+                    }
+                    _ => Err(format!(
+                        "Expected three positive costs `<x>,<y>,<via>`, got `{:?}`",
+                        costs
+                    )),
+                }
+            }
+            _ => Err(format!("Unknown router `{}`, expected bfs | astar | lee[:x,y,via]", s)),
+        }
+    }
+}
+
+impl Router {
+    /// Searches a path from `start` to `end` through all free fields of `grid`. Returns the
+    /// visited points in order from start to end or `None` if no path exists.
+    pub fn route<G: FieldGrid>(&self, start: Point, end: Point, grid: &G) -> Option<Vec<Point>> {
+        // check if the route is still available
+        if grid.field(&start) != Field::Free {
+            return None;
+        }
+
+        SEARCH_BUFFER.with(|buffer| {
+            let mut buffer = buffer.borrow_mut();
+            buffer.reset(grid.dimensions());
+
+            match self {
+                Router::Bfs => bfs(start, end, grid, &mut buffer),
+                Router::AStar => a_star(start, end, grid, &mut buffer),
+                Router::Lee(costs) => lee(start, end, grid, costs, &mut buffer),
+            }
+        })
+    }
+}
+
+thread_local! {
+    /// Search state of the current thread, reused by every search to avoid allocating a buffer
+    /// of the size of the grid per path.
+    static SEARCH_BUFFER: RefCell<SearchBuffer> = RefCell::new(SearchBuffer::default());
+}
+
+/// Per-field information of a single search: whether a field has been visited, at which cost
+/// it has been reached and how to get back to the start point from it.
+///
+/// Instead of clearing the buffer before each search, every entry is tagged with the epoch it
+/// was written in. Entries of older epochs count as unvisited.
+#[derive(Debug, Default)]
+struct SearchBuffer {
+    dimensions: Point,
+    epoch: u32,
+    epochs: Vec<u32>,
+    costs: Vec<usize>,
+    predecessors: Vec<Option<Point>>,
+}
+
+impl SearchBuffer {
+    /// Prepares the buffer for a new search on a grid of the given size.
+    fn reset(&mut self, dimensions: Point) {
+        if self.dimensions != dimensions || self.epoch == u32::MAX {
+            let len = dimensions.x * dimensions.y * dimensions.z;
+            self.dimensions = dimensions;
+            self.epochs = vec![0; len];
+            self.costs = vec![usize::MAX; len];
+            self.predecessors = vec![None; len];
+            self.epoch = 0;
+        }
+        self.epoch += 1;
+    }
+
+    /// Position of `pt` in the buffer, uses the same layout as `FlatGrid`.
+    #[inline]
+    fn index_of(&self, pt: &Point) -> usize {
+        (pt.x * self.dimensions.y + pt.y) * self.dimensions.z + pt.z
+    }
+
+    #[inline]
+    fn unvisited(&self, pt: &Point) -> bool {
+        self.epochs[self.index_of(pt)] != self.epoch
+    }
+
+    /// Cost to reach the field, `usize::MAX` if it has not been reached yet.
+    #[inline]
+    fn cost(&self, pt: &Point) -> usize {
+        if self.unvisited(pt) {
+            usize::MAX
+        } else {
+            self.costs[self.index_of(pt)]
+        }
+    }
+
+    /// The point the field has been reached from, `None` for the start point.
+    #[inline]
+    fn predecessor(&self, pt: &Point) -> Option<Point> {
+        self.predecessors[self.index_of(pt)]
+    }
+
+    #[inline]
+    fn visit(&mut self, pt: &Point, predecessor: Option<Point>, cost: usize) {
+        let idx = self.index_of(pt);
+        self.epochs[idx] = self.epoch;
+        self.predecessors[idx] = predecessor;
+        self.costs[idx] = cost;
+    }
+}
+
+fn bfs<G: FieldGrid>(
+    start: Point,
+    end: Point,
+    grid: &G,
+    buffer: &mut SearchBuffer,
+) -> Option<Vec<Point>> {
+    let mut unseen_points = VecDeque::new();
+
+    // set the start point
+    buffer.visit(&start, None, 0);
+    unseen_points.push_back(start);
+
+    while let Some(current) = unseen_points.pop_front() {
+        // stop when reacing the end node
+        if current == end {
+            return Some(generate_path(current, buffer));
+        }
+
+        // get a list of all possible successors
+        for child in get_successors(&current, grid) {
+            // sort out anything that has been seen or is blocked
+            if grid.field(&child) != Field::Free {
+                continue;
+            }
+
+            if buffer.unvisited(&child) {
+                buffer.visit(&child, Some(current), 0);
+                unseen_points.push_back(child);
+            }
+        }
+    }
+
+    // All points have been processed and no path was found
+    None
+}
+
+fn a_star<G: FieldGrid>(
+    start: Point,
+    end: Point,
+    grid: &G,
+    buffer: &mut SearchBuffer,
+) -> Option<Vec<Point>> {
+    // the insertion counter breaks ties between equally good points deterministically (FIFO)
+    let mut counter = 0usize;
+    let mut open = BinaryHeap::new();
+
+    buffer.visit(&start, None, 0);
+    open.push(Reverse((manhattan(&start, &end), counter, start.x, start.y, start.z)));
+
+    while let Some(Reverse((_, _, x, y, z))) = open.pop() {
+        let current = Point { x, y, z };
+        if current == end {
+            return Some(generate_path(current, buffer));
+        }
+
+        let next_dist = buffer.cost(&current) + 1;
+        for child in get_successors(&current, grid) {
+            if grid.field(&child) != Field::Free {
+                continue;
+            }
+
+            if next_dist < buffer.cost(&child) {
+                buffer.visit(&child, Some(current), next_dist);
+                counter += 1;
+                open.push(Reverse((
+                    next_dist + manhattan(&child, &end),
+                    counter,
+                    child.x,
+                    child.y,
+                    child.z,
+                )));
+            }
+        }
+    }
+
+    None
+}
+
+fn lee<G: FieldGrid>(
+    start: Point,
+    end: Point,
+    grid: &G,
+    costs: &ExpansionCosts,
+    buffer: &mut SearchBuffer,
+) -> Option<Vec<Point>> {
+    // expansion: compute the cheapest cost to reach every field from `start`
+    let mut wavefront = BinaryHeap::new();
+
+    buffer.visit(&start, None, 0);
+    wavefront.push(Reverse((0, start.x, start.y, start.z)));
+
+    while let Some(Reverse((cost, x, y, z))) = wavefront.pop() {
+        let current = Point { x, y, z };
+        if current == end {
+            break;
+        }
+        if cost > buffer.cost(&current) {
+            // outdated entry
+            continue;
+        }
+
+        for child in get_successors(&current, grid) {
+            if grid.field(&child) != Field::Free {
+                continue;
+            }
+
+            let child_cost = cost + step_cost(&current, &child, costs);
+            if child_cost < buffer.cost(&child) {
+                buffer.visit(&child, None, child_cost);
+                wavefront.push(Reverse((child_cost, child.x, child.y, child.z)));
+            }
+        }
+    }
+
+    if buffer.cost(&end) == usize::MAX {
+        return None;
+    }
+
+    // traceback: walk from the end point down the cost gradient, preferring to keep the direction
+    let mut path = vec![end];
+    let mut current = end;
+    let mut last_step: Option<(isize, isize, isize)> = None;
+
+    while current != start {
+        let current_cost = buffer.cost(&current);
+        let candidates: Vec<Point> = get_successors(&current, grid)
+            .into_iter()
+            .filter(|n| {
+                let c = buffer.cost(n);
+                c != usize::MAX && c + step_cost(n, &current, costs) == current_cost
+            })
+            .collect();
+
+        let next = candidates
+            .iter()
+            .find(|n| Some(direction(&current, n)) == last_step)
+            .or_else(|| candidates.first())
+            .copied()
+            .expect("Lee traceback lost the cost gradient");
+
+        last_step = Some(direction(&current, &next));
+        path.push(next);
+        current = next;
+    }
+
+    // important, we built the vec from end -> start
+    path.reverse();
+
+    Some(path)
+}
+
+#[inline]
+fn manhattan(a: &Point, b: &Point) -> usize {
+    dist(a.x, b.x) + dist(a.y, b.y) + dist(a.z, b.z)
+}
+
+#[inline]
+fn dist(a: usize, b: usize) -> usize {
+    if a > b {
+        a - b
+    } else {
+        b - a
+    }
+}
+
+/// Cost of moving between two adjacent points.
+#[inline]
+fn step_cost(from: &Point, to: &Point, costs: &ExpansionCosts) -> usize {
+    if from.x != to.x {
+        costs.x
+    } else if from.y != to.y {
+        costs.y
+    } else {
+        costs.via
+    }
+}
+
+#[inline]
+fn direction(from: &Point, to: &Point) -> (isize, isize, isize) {
+    (
+        to.x as isize - from.x as isize,
+        to.y as isize - from.y as isize,
+        to.z as isize - from.z as isize,
+    )
+}
+
+fn get_successors<G: FieldGrid>(cur: &Point, grid: &G) -> Vec<Point> {
+    let dims = grid.dimensions();
+    let mut res = Vec::with_capacity(6);
+
+    if cur.x > 0 {
+        res.push(Point {
+            x: cur.x - 1,
+            y: cur.y,
+            z: cur.z,
+        });
+    }
+    if cur.x < dims.x - 1 {
+        res.push(Point {
+            x: cur.x + 1,
+            y: cur.y,
+            z: cur.z,
+        });
+    }
+    if cur.y > 0 {
+        res.push(Point {
+            x: cur.x,
+            y: cur.y - 1,
+            z: cur.z,
+        });
+    }
+    if cur.y < dims.y - 1 {
+        res.push(Point {
+            x: cur.x,
+            y: cur.y + 1,
+            z: cur.z,
+        });
+    }
+    if cur.z > 0 {
+        res.push(Point {
+            x: cur.x,
+            y: cur.y,
+            z: cur.z - 1,
+        });
+    }
+    if cur.z < dims.z - 1 {
+        res.push(Point {
+            x: cur.x,
+            y: cur.y,
+            z: cur.z + 1,
+        });
+    }
+
+    res
+}
+
+fn generate_path(end_node: Point, buffer: &SearchBuffer) -> Vec<Point> {
+    let mut path = vec![end_node];
+    let mut current = end_node;
+
+    while let Some(next) = buffer.predecessor(&current) {
+        path.push(next);
+        current = next;
+    }
+
+    // important, we built the vec from end -> start
+    path.reverse();
+
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn empty_grid(x: usize, y: usize, z: usize) -> FlatGrid<Field> {
+        FlatGrid::new(Point { x, y, z }, Field::Free)
+    }
+
+    fn is_connected(path: &[Point]) -> bool {
+        path.windows(2).all(|w| manhattan(&w[0], &w[1]) == 1)
+    }
+
+    #[test]
+    fn parse_routers() {
+        assert_eq!(Router::from_str("bfs"), Ok(Router::Bfs));
+        assert_eq!(Router::from_str("AStar"), Ok(Router::AStar));
+        assert_eq!(
+            Router::from_str("lee"),
+            Ok(Router::Lee(ExpansionCosts::default()))
+        );
+        assert_eq!(
+            Router::from_str("lee:1,2,3"),
+            Ok(Router::Lee(ExpansionCosts { x: 1, y: 2, via: 3 }))
+        );
+        assert!(Router::from_str("lee:1,2").is_err());
+        assert!(Router::from_str("dfs").is_err());
+    }
+
+    #[test]
+    fn a_star_finds_shortest_path() {
+        // grid structure:
+        // ####G
+        // S.##.
+        // #....
+        let mut grid = empty_grid(5, 3, 1);
+        for &(x, y) in &[(0, 0), (1, 0), (2, 0), (3, 0), (2, 1), (3, 1), (0, 2)] {
+            grid[Point { x, y, z: 0 }] = Field::Wall;
+        }
+        let start = Point { x: 0, y: 1, z: 0 };
+        let end = Point { x: 4, y: 0, z: 0 };
+
+        let bfs_path = Router::Bfs.route(start, end, &grid).unwrap();
+        let a_star_path = Router::AStar.route(start, end, &grid).unwrap();
+
+        assert_eq!(a_star_path.len(), bfs_path.len());
+        assert_eq!(a_star_path.first(), Some(&start));
+        assert_eq!(a_star_path.last(), Some(&end));
+        assert!(is_connected(&a_star_path));
+    }
+
+    #[test]
+    fn lee_avoids_expensive_vias() {
+        // the direct route switches layers, the cheap one stays on layer 0 around the wall
+        let mut grid = empty_grid(3, 3, 2);
+        grid[Point { x: 1, y: 0, z: 0 }] = Field::Wall;
+        let start = Point { x: 0, y: 0, z: 0 };
+        let end = Point { x: 2, y: 0, z: 0 };
+
+        let costs = ExpansionCosts { x: 1, y: 1, via: 10 };
+        let path = Router::Lee(costs).route(start, end, &grid).unwrap();
+
+        assert!(is_connected(&path));
+        assert!(path.iter().all(|pt| pt.z == 0));
+        assert_eq!(path.len(), 5);
+    }
+
+    #[test]
+    fn no_route_from_used_start() {
+        let mut grid = empty_grid(3, 1, 1);
+        grid[Point { x: 0, y: 0, z: 0 }] = Field::Used;
+        let start = Point { x: 0, y: 0, z: 0 };
+        let end = Point { x: 2, y: 0, z: 0 };
+
+        assert_eq!(Router::Bfs.route(start, end, &grid), None);
+        assert_eq!(Router::AStar.route(start, end, &grid), None);
+        assert_eq!(Router::Lee(ExpansionCosts::default()).route(start, end, &grid), None);
+    }
+
+    #[test]
+    fn search_buffer_is_reused() {
+        // the second search must not see the visited fields of the first one
+        let mut grid = empty_grid(4, 4, 1);
+        let start = Point { x: 0, y: 0, z: 0 };
+        let end = Point { x: 3, y: 3, z: 0 };
+        let first = Router::Bfs.route(start, end, &grid).unwrap();
+        assert_eq!(Router::Bfs.route(start, end, &grid), Some(first.clone()));
+
+        grid[first[1]] = Field::Wall;
+        let second = Router::Bfs.route(start, end, &grid).unwrap();
+        assert_eq!(second.len(), first.len());
+        assert!(!second.contains(&first[1]));
+        assert!(is_connected(&second));
+
+        // a grid of another size resets the buffer
+        let small = empty_grid(2, 1, 1);
+        let path = Router::AStar.route(start, Point { x: 1, y: 0, z: 0 }, &small);
+        assert_eq!(path.map(|p| p.len()), Some(2));
+    }
+}
@@ -0,0 +1,148 @@
+use crate::types::Point;
+use rand::seq::SliceRandom;
+use rand_chacha::rand_core::SeedableRng;
+use rand_chacha::ChaCha12Rng;
+use std::cmp::Reverse;
+use std::fmt;
+use std::str::FromStr;
+
+/// The order in which the pairs of an input are handed to the routing runtimes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PathOrder {
+    /// Keep the order of the input file
+    #[default]
+    File,
+    /// Pairs with the largest Manhattan distance between their endpoints first
+    Longest,
+    /// Pairs with the smallest Manhattan distance between their endpoints first
+    Shortest,
+    /// Pairs whose midpoints lie close to each other are grouped together (Z-order curve)
+    Clustered,
+    /// Shuffled with the given seed
+    Random(u64),
+}
+
+impl fmt::Display for PathOrder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PathOrder::File => write!(f, "file"),
+            PathOrder::Longest => write!(f, "longest"),
+            PathOrder::Shortest => write!(f, "shortest"),
+            PathOrder::Clustered => write!(f, "clustered"),
+            PathOrder::Random(seed) => write!(f, "random:{}", seed),
+        }
+    }
+}
+
+impl FromStr for PathOrder {
+    type Err = String;
+
+    /// Parses `file`, `longest`, `shortest`, `clustered` or `random`. The seed of the random
+    /// order may be given as `random:<seed>` and defaults to 0.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.splitn(2, ':');
+        match (parts.next().map(str::to_lowercase).as_deref(), parts.next()) {
+            (Some("file"), None) => Ok(PathOrder::File),
+            (Some("longest"), None) => Ok(PathOrder::Longest),
+            (Some("shortest"), None) => Ok(PathOrder::Shortest),
+            (Some("clustered"), None) => Ok(PathOrder::Clustered),
+            (Some("random"), None) => Ok(PathOrder::Random(0)),
+            (Some("random"), Some(seed)) => seed
+                .trim()
+                .parse::<u64>()
+                .map(PathOrder::Random)
+                .map_err(|_| format!("Invalid seed `{}`", seed)),
+            _ => Err(format!(
+                "Unknown order `{}`, expected file | longest | shortest | clustered | random[:seed]",
+                s
+            )),
+        }
+    }
+}
+
+impl PathOrder {
+    /// Reorders the `pairs` in place. Sorting is stable, so pairs that compare equal keep
+    /// their relative order from the input.
+    pub fn apply(&self, pairs: &mut [(Point, Point)]) {
+        match self {
+            PathOrder::File => {}
+            PathOrder::Longest => pairs.sort_by_key(|(s, e)| Reverse(manhattan(s, e))),
+            PathOrder::Shortest => pairs.sort_by_key(|(s, e)| manhattan(s, e)),
+            PathOrder::Clustered => pairs.sort_by_key(|(s, e)| {
+                morton_code((s.x + e.x) / 2, (s.y + e.y) / 2, (s.z + e.z) / 2)
+            }),
+            PathOrder::Random(seed) => pairs.shuffle(&mut ChaCha12Rng::seed_from_u64(*seed)),
+        }
+    }
+}
+
+fn manhattan(a: &Point, b: &Point) -> usize {
+    let d = |a: usize, b: usize| if a > b { a - b } else { b - a };
+    d(a.x, b.x) + d(a.y, b.y) + d(a.z, b.z)
+}
+
+/// Position of the point on a Z-order curve, computed by interleaving the bits of the
+/// (lower 21 bits of the) coordinates.
+fn morton_code(x: usize, y: usize, z: usize) -> u64 {
+    (0..21).fold(0, |code, bit| {
+        code | ((x as u64 >> bit) & 1) << (3 * bit)
+            | ((y as u64 >> bit) & 1) << (3 * bit + 1)
+            | ((z as u64 >> bit) & 1) << (3 * bit + 2)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pair(x1: usize, y1: usize, x2: usize, y2: usize) -> (Point, Point) {
+        (Point { x: x1, y: y1, z: 0 }, Point { x: x2, y: y2, z: 0 })
+    }
+
+    #[test]
+    fn parse_orders() {
+        for order in &["file", "longest", "shortest", "clustered", "random:42"] {
+            assert_eq!(PathOrder::from_str(order).unwrap().to_string(), *order);
+        }
+        assert_eq!(PathOrder::from_str("random"), Ok(PathOrder::Random(0)));
+        assert!(PathOrder::from_str("random:x").is_err());
+        assert!(PathOrder::from_str("widest").is_err());
+    }
+
+    #[test]
+    fn sort_by_length() {
+        let mut pairs = vec![pair(0, 0, 1, 0), pair(0, 0, 5, 5), pair(0, 0, 2, 1)];
+
+        PathOrder::Longest.apply(&mut pairs);
+        assert_eq!(pairs, vec![pair(0, 0, 5, 5), pair(0, 0, 2, 1), pair(0, 0, 1, 0)]);
+
+        PathOrder::Shortest.apply(&mut pairs);
+        assert_eq!(pairs, vec![pair(0, 0, 1, 0), pair(0, 0, 2, 1), pair(0, 0, 5, 5)]);
+    }
+
+    #[test]
+    fn clustered_groups_neighbours() {
+        let mut pairs = vec![
+            pair(0, 0, 2, 2),
+            pair(60, 60, 62, 62),
+            pair(1, 1, 3, 3),
+            pair(61, 61, 63, 63),
+        ];
+        PathOrder::Clustered.apply(&mut pairs);
+        assert_eq!(pairs[0], pair(0, 0, 2, 2));
+        assert_eq!(pairs[1], pair(1, 1, 3, 3));
+    }
+
+    #[test]
+    fn random_is_reproducible() {
+        let pairs: Vec<_> = (0..20).map(|i| pair(i, 0, i, 1)).collect();
+        let (mut a, mut b, mut c) = (pairs.clone(), pairs.clone(), pairs.clone());
+
+        PathOrder::Random(7).apply(&mut a);
+        PathOrder::Random(7).apply(&mut b);
+        PathOrder::Random(8).apply(&mut c);
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+        assert_ne!(a, pairs);
+    }
+}
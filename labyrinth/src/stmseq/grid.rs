@@ -1,8 +1,9 @@
+use crate::router::Router;
 use crate::stmseq::types::*;
 use crate::types::*;
 
 /// The grid the maze is set up in. Contains for every field the information about its state.
-pub type Grid = Vec<Vec<Vec<Field>>>;
+pub type Grid = FlatGrid<Field>;
 
 /// Initializes a new, empty grid, optionally with pre-defined walls which can be provided using the `walls` parameter.
 ///
@@ -13,12 +14,17 @@ pub fn initialize_grid(
     depth: usize,
     walls: &Option<Vec<Point>>,
 ) -> Grid {
-    let mut grid = vec![vec![vec![Field::Free; depth]; height]; width];
+    let dimensions = Point {
+        x: width,
+        y: height,
+        z: depth,
+    };
+    let mut grid = Grid::new(dimensions, Field::Free);
 
     // place walls if any
     if let Some(wall_vec) = walls {
         for wall in wall_vec {
-            grid[wall.x][wall.y][wall.z] = Field::Wall;
+            grid[*wall] = Field::Wall;
         }
     }
 
@@ -26,14 +32,14 @@ pub fn initialize_grid(
 }
 
 pub fn at_grid_coordinates<'a>(grid: &'a Grid, pt: &Point) -> &'a Field {
-    &grid[pt.x][pt.y][pt.z]
+    &grid[*pt]
 }
 
 /// Updates the maze with a mapped path by updating the underlying grid and the management data
 /// structures in the `Maze` struct.
 pub fn update_maze(maze: &mut Maze, path: Path) {
     for pt in &path.path {
-        maze.grid[pt.x][pt.y][pt.z] = Field::Used;
+        maze.grid[*pt] = Field::Used;
     }
     maze.paths.push(path);
 }
@@ -49,10 +55,10 @@ pub fn path_available(grid: &Grid, path: &Path) -> bool {
     true
 }
 
-pub fn route_paths(mut maze: Maze, mut to_map: Vec<(Point, Point)>) -> Maze {
+pub fn route_paths(mut maze: Maze, mut to_map: Vec<(Point, Point)>, router: &Router) -> Maze {
     // search for a path for all point pairs (sort out any pairs w/o path)
     for pair in to_map.drain(..) {
-        if let Some(path) = super::pathfinder::find_path(pair.clone(), &maze.grid) {
+        if let Some(path) = super::pathfinder::find_path(pair.clone(), &maze.grid, router) {
             update_maze(&mut maze, path);
         } else {
             maze.unmappable_paths.push(pair);
@@ -7,7 +7,8 @@ use crate::stm_grid::StmGrid;
 use stm::{Transaction, StmResult};
 #[cfg(all(feature = "transactional", feature = "naive"))]
 use crate::types::at_stm_grid_coordinates;
-use std::collections::LinkedList;
+#[cfg(not(feature = "naive"))]
+use crate::router::Router;
 #[cfg(feature = "naive")]
 use std::collections::HashMap;
 
@@ -16,24 +17,6 @@ use std::collections::HashMap;
 #[cfg(feature = "naive")]
 type BacktrackMetaData = HashMap<Point, Option<Point>>;
 
-/// This data structure contains information on whether a point has been visited before and
-/// information on how to get back from the end point to the start.
-/// Each point is assigned the previous point in the path to allow easy backtracking.
-#[derive(Clone, Copy, Debug, Default, PartialEq)]
-enum PointStatus {
-    #[default]
-    Unvisited,
-    BacktrackInfo(Option<Point>)
-}
-
-impl PointStatus {
-    #[inline]
-    pub fn unvisited(&self) -> bool {
-        *self == Self::Unvisited
-    }
-}
-
-
 #[cfg(all(feature = "transactional", feature = "naive"))]
 pub fn find_path(points: (Point, Point), grid: &StmGrid, transaction: &mut Transaction) -> StmResult<Option<Path>> {
     compile_error!("Needs to be adjusted to the new `enqueued` Data Structure used in the other `find_path` implementations");
@@ -87,99 +70,14 @@ pub fn find_path(points: (Point, Point), grid: &StmGrid, transaction: &mut Trans
 }
 
 
+/// Searches a path between the two `points` on the `grid` using the given `router`.
 #[cfg(not(feature = "naive"))]
-pub fn find_path(points: (Point, Point), grid: &Grid) -> Option<Path> {
-    // TODO: Add costs?
+pub fn find_path(points: (Point, Point), grid: &Grid, router: &Router) -> Option<Path> {
     let (start, end) = points;
 
-    // check if the route is still available
-    if at_grid_coordinates(grid, &start) != &Field::Free {
-        return None;
-    }
-
-    let mut point_status = vec![vec![vec![PointStatus::Unvisited; grid[0][0].len()]; grid[0].len()]; grid.len()];
-    let mut unseen_points = LinkedList::new();
-
-    // set the start point
-    point_status[start.x][start.y][start.z] = PointStatus::BacktrackInfo(None);
-    unseen_points.push_back(start);
-
-    while !unseen_points.is_empty() {
-        let current = unseen_points.pop_front().unwrap();
-
-        // stop when reacing the end node
-        if current == end {
-            return Some(generate_path(current, &point_status));
-        }
-
-        // get a list of all possible successors
-        for child in get_successors(&current, grid) {
-            // sort out anything that has been seen or is blocked
-            match at_grid_coordinates(grid, &child) {
-                &Field::Used => continue,
-                &Field::Wall => continue,
-                &Field::Free => (),
-            }
-
-            if point_status[child.x][child.y][child.z].unvisited() {
-                point_status[child.x][child.y][child.z] = PointStatus::BacktrackInfo(Some(current));
-                unseen_points.push_back(child);
-            }
-        }
-    }
-
-    // All points have been processed and no path was found
-    None
-}
-
-#[cfg(not(feature = "naive"))]
-fn get_successors(cur: &Point, grid: &Grid) -> Vec<Point> {
-    let mut res = Vec::with_capacity(6);
-
-    if cur.x > 0 {
-        res.push(Point {
-            x: cur.x - 1,
-            y: cur.y,
-            z: cur.z,
-        });
-    }
-    if cur.x < grid.len() - 1 {
-        res.push(Point {
-            x: cur.x + 1,
-            y: cur.y,
-            z: cur.z,
-        });
-    }
-    if cur.y > 0 {
-        res.push(Point {
-            x: cur.x,
-            y: cur.y - 1,
-            z: cur.z,
-        });
-    }
-    if cur.y < grid[0].len() - 1 {
-        res.push(Point {
-            x: cur.x,
-            y: cur.y + 1,
-            z: cur.z,
-        });
-    }
-    if cur.z > 0 {
-        res.push(Point {
-            x: cur.x,
-            y: cur.y,
-            z: cur.z - 1,
-        });
-    }
-    if cur.z < grid[0][0].len() - 1 {
-        res.push(Point {
-            x: cur.x,
-            y: cur.y,
-            z: cur.z + 1,
-        });
-    }
-
-    res
+    router
+        .route(start, end, grid)
+        .map(|path| Path { start, end, path })
 }
 
 #[cfg(all(feature = "transactional", feature = "naive"))]
@@ -193,7 +91,7 @@ fn get_successors(cur: &Point, grid: &StmGrid) -> Vec<Point> {
             z: cur.z,
         });
     }
-    if cur.x < grid.len() - 1 {
+    if cur.x < grid.dimensions().x - 1 {
         res.push(Point {
             x: cur.x + 1,
             y: cur.y,
@@ -207,7 +105,7 @@ fn get_successors(cur: &Point, grid: &StmGrid) -> Vec<Point> {
             z: cur.z,
         });
     }
-    if cur.y < grid[0].len() - 1 {
+    if cur.y < grid.dimensions().y - 1 {
         res.push(Point {
             x: cur.x,
             y: cur.y + 1,
@@ -221,7 +119,7 @@ fn get_successors(cur: &Point, grid: &StmGrid) -> Vec<Point> {
             z: cur.z - 1,
         });
     }
-    if cur.z < grid[0][0].len() - 1 {
+    if cur.z < grid.dimensions().z - 1 {
         res.push(Point {
             x: cur.x,
             y: cur.y,
@@ -232,26 +130,6 @@ fn get_successors(cur: &Point, grid: &StmGrid) -> Vec<Point> {
     res
 }
 
-#[cfg(not(feature = "naive"))]
-fn generate_path(end_node: Point, meta_info: &Vec<Vec<Vec<PointStatus>>>) -> Path {
-    let mut path = vec![end_node];
-    let mut current = end_node;
-
-    while let PointStatus::BacktrackInfo(Some(next)) = meta_info[current.x][current.y][current.z] {
-        path.push(next);
-        current = next;
-    }
-
-    // important, we built the vec from end -> start
-    path.reverse();
-
-    Path {
-        start: current,
-        end: end_node,
-        path,
-    }
-}
-
 #[cfg(feature = "naive")]
 fn generate_path(end_node: Point, mut meta_info: BacktrackMetaData) -> Path {
     let mut path = vec![end_node.clone()];
@@ -279,7 +157,7 @@ fn generate_path(end_node: Point, mut meta_info: BacktrackMetaData) -> Path {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::types::*;
+    use crate::stmseq::types::initialize_grid;
 
     #[test]
     fn simple_pathfinding() {
@@ -298,7 +176,7 @@ mod tests {
             ],
         };
 
-        let path = find_path((start, end), &grid);
+        let path = find_path((start, end), &grid, &Router::Bfs);
 
         assert_eq!(path, Some(expected_path));
     }
@@ -338,7 +216,7 @@ mod tests {
             ],
         };
 
-        let path = find_path((start, end), &grid);
+        let path = find_path((start, end), &grid, &Router::Bfs);
 
         assert_eq!(path, Some(expected_path));
     }
@@ -364,7 +242,7 @@ mod tests {
         let start = Point { x: 0, y: 1, z: 0 };
         let end = Point { x: 4, y: 0, z: 0 };
 
-        let path = find_path((start, end), &grid);
+        let path = find_path((start, end), &grid, &Router::Bfs);
 
         assert_eq!(path, None);
     }
@@ -387,11 +265,11 @@ mod tests {
         ];
 
         let mut grid = initialize_grid(5, 3, 1, &Some(obstacles));
-        grid[2][2][0] = Field::Used;
+        grid[Point { x: 2, y: 2, z: 0 }] = Field::Used;
         let start = Point { x: 0, y: 1, z: 0 };
         let end = Point { x: 4, y: 0, z: 0 };
 
-        let path = find_path((start, end), &grid);
+        let path = find_path((start, end), &grid, &Router::Bfs);
 
         assert_eq!(path, None);
     }
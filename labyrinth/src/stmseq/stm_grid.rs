@@ -3,8 +3,8 @@ use crate::stmseq::types::*;
 use stm::{StmError, StmResult, TVar, Transaction};
 
 /// The grid the maze is set up in. Contains for every field the information about its state.
-pub type StmGrid = Vec<Vec<Vec<TVar<Field>>>>;
-pub type Grid = Vec<Vec<Vec<Field>>>;
+pub type StmGrid = FlatGrid<TVar<Field>>;
+pub type Grid = FlatGrid<Field>;
 
 /// Initializes a new, empty grid, optionally with pre-defined walls which can be provided using the `walls` parameter.
 ///
@@ -15,21 +15,18 @@ pub fn initialize_grid(
     depth: usize,
     walls: &Option<Vec<Point>>,
 ) -> StmGrid {
-    let mut grid = vec![vec![vec![TVar::new(Field::Free); depth]; height]; width];
-
+    let dimensions = Point {
+        x: width,
+        y: height,
+        z: depth,
+    };
     // must initialize this way because of the `clone` semantics of `vec!`
-    for x in 0..width {
-        for y in 0..height {
-            for z in 0..depth {
-                grid[x][y][z] = TVar::new(Field::Free);
-            }
-        }
-    }
+    let mut grid = StmGrid::from_fn(dimensions, |_| TVar::new(Field::Free));
 
     // place walls if any
     if let Some(wall_vec) = walls {
         for wall in wall_vec {
-            grid[wall.x][wall.y][wall.z] = TVar::new(Field::Wall);
+            grid[*wall] = TVar::new(Field::Wall);
         }
     }
 
@@ -41,18 +38,18 @@ pub fn at_stm_grid_coordinates<'a>(
     pt: &Point,
     transaction: &mut Transaction,
 ) -> StmResult<Field> {
-    Ok(grid[pt.x][pt.y][pt.z].read(transaction)?)
+    Ok(grid[*pt].read(transaction)?)
 }
 
 pub fn at_grid_coordinates<'a>(grid: &'a Grid, pt: &Point) -> &'a Field {
-    &grid[pt.x][pt.y][pt.z]
+    &grid[*pt]
 }
 
 /// Updates the grid with mapped paths.
 pub fn update_grid(grid: &StmGrid, path: &Path, transaction: &mut Transaction) -> StmResult<()> {
     for pt in &path.path {
         if at_stm_grid_coordinates(grid, pt, transaction)? == Field::Free {
-            grid[pt.x][pt.y][pt.z].write(transaction, Field::Used)?;
+            grid[*pt].write(transaction, Field::Used)?;
         } else {
             return Err(StmError::Retry);
         }
@@ -62,12 +59,10 @@ pub fn update_grid(grid: &StmGrid, path: &Path, transaction: &mut Transaction) -
 }
 
 pub fn create_working_copy(grid: &StmGrid) -> Grid {
-    grid.iter()
-        .map(|y_grid| {
-            y_grid
-                .iter()
-                .map(|z_grid| z_grid.iter().map(|pt| pt.read_atomic()).collect())
-                .collect()
-        })
-        .collect()
+    grid.map(|pt| pt.read_atomic())
+}
+
+/// Creates a new transactional grid with the fields of `grid`.
+pub fn from_working_copy(grid: &Grid) -> StmGrid {
+    grid.map(|field| TVar::new(*field))
 }
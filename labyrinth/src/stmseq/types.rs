@@ -1,4 +1,9 @@
+use crate::compare::RoutingResult;
+use crate::export::{self, ImageFormat};
+use crate::reroute::{self, RerouteStats};
+use crate::router::Router;
 use crate::types::*;
+use std::io;
 
 #[cfg(feature = "transactional")]
 pub use crate::stm_grid::*;
@@ -48,20 +53,12 @@ impl Maze {
     /// Validates the maze by checking if every path is mapped and every point only used once
     #[cfg(feature = "transactional")]
     pub fn is_valid(&self) -> bool {
-        let mut ctrl_grid: Vec<Vec<Vec<Field>>> = self
-            .grid
-            .iter()
-            .map(|x| {
-                x.iter()
-                    .map(|y| y.iter().map(|var| var.read_atomic()).collect())
-                    .collect()
-            })
-            .collect();
+        let mut ctrl_grid = create_working_copy(&self.grid);
 
         for path in &self.paths {
             for pt in &path.path {
-                if ctrl_grid[pt.x][pt.y][pt.z] == Field::Used {
-                    ctrl_grid[pt.x][pt.y][pt.z] = Field::Free;
+                if ctrl_grid[*pt] == Field::Used {
+                    ctrl_grid[*pt] = Field::Free;
                 } else {
                     return false;
                 }
@@ -78,7 +75,7 @@ impl Maze {
         for path in &self.paths {
             for pt in &path.path {
                 if at_grid_coordinates(&ctrl_grid, &pt) == &Field::Used {
-                    ctrl_grid[pt.x][pt.y][pt.z] = Field::Free;
+                    ctrl_grid[*pt] = Field::Free;
                 } else {
                     return false;
                 }
@@ -87,6 +84,68 @@ impl Maze {
 
         true
     }
+
+    /// Writes an image of every layer of the maze into `dir`, see `export::render_layers`.
+    pub fn render<P: AsRef<std::path::Path>>(&self, dir: P, format: ImageFormat) -> io::Result<()> {
+        #[cfg(feature = "transactional")]
+        let grid = &create_working_copy(&self.grid);
+        #[cfg(not(feature = "transactional"))]
+        let grid = &self.grid;
+
+        let paths: Vec<Vec<Point>> = self.paths.iter().map(|p| p.path.clone()).collect();
+        export::render_layers(dir, format, grid, &paths, &self.unmappable_paths)
+    }
+
+    /// Tries to map the unmappable paths by ripping up the paths blocking them, see
+    /// `reroute::rip_up_and_reroute`. Must not be called while other threads work on the grid.
+    pub fn reroute(&mut self, router: &Router, max_rounds: usize) -> RerouteStats {
+        if max_rounds == 0 || self.unmappable_paths.is_empty() {
+            return RerouteStats::default();
+        }
+
+        let mut paths: Vec<Vec<Point>> = self.paths.drain(..).map(|p| p.path).collect();
+
+        #[cfg(feature = "transactional")]
+        let stats = {
+            let mut grid = create_working_copy(&self.grid);
+            let stats = reroute::rip_up_and_reroute(
+                &mut grid,
+                &mut paths,
+                &mut self.unmappable_paths,
+                router,
+                max_rounds,
+            );
+            self.grid = from_working_copy(&grid);
+            stats
+        };
+        #[cfg(not(feature = "transactional"))]
+        let stats = reroute::rip_up_and_reroute(
+            &mut self.grid,
+            &mut paths,
+            &mut self.unmappable_paths,
+            router,
+            max_rounds,
+        );
+
+        self.paths = paths
+            .into_iter()
+            .map(|path| Path {
+                start: path[0],
+                end: path[path.len() - 1],
+                path,
+            })
+            .collect();
+
+        stats
+    }
+
+    /// Returns the canonical routing result of this maze for comparisons between runtimes.
+    pub fn routing_result(&self) -> RoutingResult {
+        RoutingResult::new(
+            self.paths.iter().map(|p| (p.start, p.end, &p.path[..])),
+            &self.unmappable_paths,
+        )
+    }
 }
 
 /// A single path in the maze.
@@ -1,6 +1,6 @@
 use std::fmt;
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
 /// A point in the 3D maze
 pub struct Point {
     pub x: usize,
@@ -21,3 +21,118 @@ pub enum Field {
     Used,
     Wall,
 }
+
+/// Read access to the fields of a grid, as needed by the routers.
+pub trait FieldGrid {
+    /// The size of the grid along each axis.
+    fn dimensions(&self) -> Point;
+
+    /// The state of the field at `pt`.
+    fn field(&self, pt: &Point) -> Field;
+}
+
+/// A three-dimensional grid stored in a single, contiguous vector. The fields are laid out in
+/// the same order as in a nested `Vec` indexed by `[x][y][z]`, so neighbouring fields on the
+/// z-axis are adjacent in memory.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FlatGrid<T> {
+    dimensions: Point,
+    fields: Vec<T>,
+}
+
+impl<T: Clone> FlatGrid<T> {
+    /// Creates a grid of the given size with every field set to `value`.
+    pub fn new(dimensions: Point, value: T) -> Self {
+        FlatGrid {
+            dimensions,
+            fields: vec![value; dimensions.x * dimensions.y * dimensions.z],
+        }
+    }
+}
+
+impl<T> FlatGrid<T> {
+    /// Creates a grid of the given size, initializing each field with `f(point)`.
+    pub fn from_fn<F: FnMut(Point) -> T>(dimensions: Point, mut f: F) -> Self {
+        let mut fields = Vec::with_capacity(dimensions.x * dimensions.y * dimensions.z);
+        for x in 0..dimensions.x {
+            for y in 0..dimensions.y {
+                for z in 0..dimensions.z {
+                    fields.push(f(Point { x, y, z }));
+                }
+            }
+        }
+
+        FlatGrid { dimensions, fields }
+    }
+
+    /// The size of the grid along each axis.
+    #[inline]
+    pub fn dimensions(&self) -> Point {
+        self.dimensions
+    }
+
+    /// Total number of fields in the grid.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.fields.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    /// Position of `pt` in the underlying vector.
+    #[inline]
+    pub fn index_of(&self, pt: &Point) -> usize {
+        debug_assert!(
+            pt.x < self.dimensions.x && pt.y < self.dimensions.y && pt.z < self.dimensions.z,
+            "{} is out of bounds of a {} grid",
+            pt,
+            self.dimensions
+        );
+        (pt.x * self.dimensions.y + pt.y) * self.dimensions.z + pt.z
+    }
+
+    /// All fields in memory order.
+    pub fn iter(&self) -> std::slice::Iter<'_, T> {
+        self.fields.iter()
+    }
+
+    /// Creates a grid of the same size by applying `f` to every field.
+    pub fn map<U, F: FnMut(&T) -> U>(&self, f: F) -> FlatGrid<U> {
+        FlatGrid {
+            dimensions: self.dimensions,
+            fields: self.fields.iter().map(f).collect(),
+        }
+    }
+}
+
+impl<T> std::ops::Index<Point> for FlatGrid<T> {
+    type Output = T;
+
+    #[inline]
+    fn index(&self, pt: Point) -> &T {
+        &self.fields[self.index_of(&pt)]
+    }
+}
+
+impl<T> std::ops::IndexMut<Point> for FlatGrid<T> {
+    #[inline]
+    fn index_mut(&mut self, pt: Point) -> &mut T {
+        let idx = self.index_of(&pt);
+        &mut self.fields[idx]
+    }
+}
+
+impl FieldGrid for FlatGrid<Field> {
+    #[inline]
+    fn dimensions(&self) -> Point {
+        self.dimensions
+    }
+
+    #[inline]
+    fn field(&self, pt: &Point) -> Field {
+        self[*pt]
+    }
+}
@@ -43,8 +43,7 @@ do
 
     cargo build --quiet --release --bin ohua
 
-    target/release/ohua inputs/random-x512-y512-z7-n512.txt --json --outdir $RESPATH/$TODAY-results/labyrinth-non-opt/ --runs $RUNS --threads $tc --frequency $BS --runtime Ohua
-    target/release/ohua inputs/random-x512-y512-z7-n512.txt --json --outdir $RESPATH/$TODAY-results/labyrinth/ --runs $RUNS --threads $tc --frequency $BS --runtime OhuaZeroClone
+    target/release/ohua inputs/random-x512-y512-z7-n512.txt --json --outdir $RESPATH/$TODAY-results/labyrinth/ --runs $RUNS --threads $tc --frequency $BS --runtime Ohua
 done
 cd ..
 